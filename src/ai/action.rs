@@ -5,15 +5,18 @@ use point::Direction;
 use logic::Action;
 use logic::entity::EntityQuery;
 use data::Walkability;
+use graphics::cell::DoorState;
 use point::Path;
+use world::flags::RngStream;
 use world::traits::*;
 use world::World;
 
 // TODO: Allow variable arguments, since we have no need to follow a consistent
 // API?
 
-pub fn ai_wander(_entity: Entity, _world: &World) -> Action {
-    Action::Move(Direction::choose8())
+pub fn ai_wander(_entity: Entity, world: &World) -> Action {
+    let dir = Direction::choose8(&mut *world.rng(RngStream::Ai));
+    Action::Move(dir)
 }
 
 pub fn ai_swing_at(entity: Entity, world: &World) -> Action {
@@ -23,6 +26,17 @@ pub fn ai_swing_at(entity: Entity, world: &World) -> Action {
     Action::SwingAt(ai.target.borrow().unwrap())
 }
 
+pub fn ai_shoot_at(entity: Entity, world: &World) -> Action {
+    let ais = &world.ecs().ais;
+    let ai = ais.get_or_err(entity);
+
+    let target = ai.target.borrow().unwrap();
+    match world.position(target) {
+        Some(pos) => Action::Shoot(pos),
+        None => Action::Wait,
+    }
+}
+
 fn direction_towards(entity: Entity, target: Entity, world: &World) -> Option<Direction> {
     let my_pos = world.position(entity).unwrap();
     let target_pos = world.position(target).unwrap();
@@ -62,9 +76,20 @@ fn direction_towards_target(entity: Entity, world: &World) -> Option<Direction>
     direction_towards(entity, target, world)
 }
 
+/// Paths may lead through closed doors, so open them instead of bumping into
+/// them.
+fn move_or_open_door(entity: Entity, dir: Direction, world: &World) -> Action {
+    let next_pos = world.position(entity).unwrap() + dir;
+    if world.door_at(&next_pos) == Some(DoorState::Closed) {
+        Action::OpenDoor(dir)
+    } else {
+        Action::Move(dir)
+    }
+}
+
 pub fn ai_move_closer(entity: Entity, world: &World) -> Action {
     match direction_towards_target(entity, world) {
-        Some(dir) => Action::Move(dir),
+        Some(dir) => move_or_open_door(entity, dir, world),
         None => Action::Wait,
     }
 }
@@ -53,22 +53,52 @@ fn get_goal(entity: Entity, world: &World) -> (AiGoal, Option<Entity>) {
         AiKind::Wander => (AiGoal::Wander, None),
         AiKind::Follow => (AiGoal::Follow, world.player()),
         AiKind::SeekTarget => {
-            match world.player() {
-                Some(p) => {
-                    if entity.can_see_other(p, world) {
-                        (AiGoal::KillTarget, Some(p))
-                    } else {
-                        (AiGoal::FindTarget, Some(p))
-                    }
-                },
-                None => (AiGoal::DoNothing, None),
+            match find_nearest_hostile(entity, world) {
+                Some(target) => (AiGoal::KillTarget, Some(target)),
+                None => (AiGoal::FindTarget, None),
             }
         },
     }
 }
 
+fn find_nearest_hostile(entity: Entity, world: &World) -> Option<Entity> {
+    let pos = match world.position(entity) {
+        Some(p) => p,
+        None => return None,
+    };
+
+    world.seen_entities(entity)
+         .into_iter()
+         .filter(|&e| entity.is_hostile_to(e, world))
+         .min_by_key(|&e| world.position(e).map_or(i32::max_value(), |p| pos.tile_distance(p)))
+}
+
 pub fn make_new_plan(entity: Entity, world: &World) -> (AiFacts, Option<Entity>) {
     let (goal, target) = get_goal(entity, world);
     let desired = goal.get_end_state();
     (desired, target)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ecs;
+    use point::Point;
+    use testing::*;
+    use world::traits::Mutate;
+
+    #[test]
+    fn test_targets_nearest_hostile() {
+        let mut context = test_context();
+        let world = &mut context.state.world;
+        let mob = world.create(ecs::prefab::mob("putit", 100, "putit"), Point::new(5, 5));
+        let npc = world.create(ecs::prefab::npc("dude"), Point::new(6, 6));
+
+        let (goal, target) = get_goal(mob, world);
+        assert_eq!(goal, AiGoal::KillTarget);
+        assert_eq!(target, Some(npc));
+
+        let (_, target) = get_goal(npc, world);
+        assert_eq!(target, None);
+    }
+}
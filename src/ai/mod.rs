@@ -15,12 +15,6 @@ use ecs::traits::ComponentQuery;
 use world::traits::Query;
 use world::World;
 
-#[derive(Eq, PartialEq, Clone, Debug, Serialize, Deserialize)]
-pub enum Disposition {
-    Friendly,
-    Enemy,
-}
-
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Ai {
     #[serde(skip_serializing)]
@@ -34,8 +28,6 @@ pub struct Ai {
     memory: RefCell<AiMemory>,
     goal: RefCell<AiMemory>,
     next_action: RefCell<Option<AiAction>>,
-
-    pub disposition: Disposition,
 }
 
 pub type AiMemory = GoapState<AiProp, bool>;
@@ -50,6 +42,7 @@ fn default_ai_facts() -> AiFacts {
     facts.insert(AiProp::TargetVisible, false);
     facts.insert(AiProp::TargetDead, false);
     facts.insert(AiProp::NextToTarget, false);
+    facts.insert(AiProp::CanShootTarget, false);
     facts
 }
 
@@ -62,7 +55,6 @@ impl Ai {
             target: RefCell::new(None),
             goal: RefCell::new(AiMemory { facts: facts.clone() }),
             memory: RefCell::new(AiMemory { facts: facts }),
-            disposition: Disposition::Friendly,
             kind: kind,
 
             next_action: RefCell::new(None),
@@ -92,6 +84,7 @@ pub enum AiProp {
     TargetVisible,
     TargetDead,
     NextToTarget,
+    CanShootTarget,
     Exists,
     Moving,
 }
@@ -101,6 +94,7 @@ pub enum AiAction {
     Wander,
     MoveCloser,
     SwingAt,
+    ShootAt,
     Run,
 }
 
@@ -163,6 +157,7 @@ fn update_memory(entity: Entity, world: &World) {
         AiProp::TargetVisible,
         AiProp::TargetDead,
         AiProp::NextToTarget,
+        AiProp::CanShootTarget,
         AiProp::HealthLow,
         AiProp::Exists,
         AiProp::Moving,
@@ -205,6 +200,7 @@ fn choose_action(entity: Entity, world: &World) -> Action {
                 AiAction::Wander => action::ai_wander(entity, world),
                 AiAction::MoveCloser => action::ai_move_closer(entity, world),
                 AiAction::SwingAt => action::ai_swing_at(entity, world),
+                AiAction::ShootAt => action::ai_shoot_at(entity, world),
                 AiAction::Run => action::ai_run_away(entity, world),
             }
         },
@@ -245,6 +241,14 @@ pub fn make_planner() -> AiPlanner {
     effects.set_postcondition(AiProp::TargetDead, true);
     actions.insert(AiAction::SwingAt, effects);
 
+    let mut effects = GoapEffects::new(5);
+    effects.set_precondition(AiProp::HasTarget, true);
+    effects.set_precondition(AiProp::TargetVisible, true);
+    effects.set_precondition(AiProp::CanShootTarget, true);
+    effects.set_precondition(AiProp::TargetDead, false);
+    effects.set_postcondition(AiProp::TargetDead, true);
+    actions.insert(AiAction::ShootAt, effects);
+
     let mut effects = GoapEffects::new(2);
     effects.set_precondition(AiProp::HealthLow, true);
     effects.set_postcondition(AiProp::HealthLow, false);
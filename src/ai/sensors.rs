@@ -5,6 +5,7 @@ use calx_ecs::Entity;
 use ai::{Ai, AiProp};
 use ecs::traits::*;
 use logic::entity::EntityQuery;
+use logic::projectile;
 use world::traits::Query;
 use world::World;
 
@@ -44,6 +45,17 @@ fn next_to_target(world: &World, entity: Entity, ai: &Ai) -> bool {
     })
 }
 
+fn can_shoot_target(world: &World, entity: Entity, ai: &Ai) -> bool {
+    if !projectile::can_shoot(world, entity) {
+        return false;
+    }
+
+    ai.target.borrow().map_or(false, |t| {
+        let pos = world.position(entity).unwrap();
+        projectile::can_hit(world, pos, t)
+    })
+}
+
 fn has_target(_world: &World, _entity: Entity, ai: &Ai) -> bool {
     ai.target.borrow().is_some()
 }
@@ -70,6 +82,7 @@ pub fn make_sensors() -> HashMap<AiProp, Sensor> {
     results.insert(AiProp::HasTarget, Sensor::new(has_target));
     results.insert(AiProp::TargetDead, Sensor::new(target_dead));
     results.insert(AiProp::NextToTarget, Sensor::new(next_to_target));
+    results.insert(AiProp::CanShootTarget, Sensor::new(can_shoot_target));
     results.insert(AiProp::HealthLow, Sensor::new(health_low));
     results.insert(AiProp::Exists, Sensor::new(always_true));
     results.insert(AiProp::Moving, Sensor::new(always_false));
@@ -0,0 +1,5 @@
+extern crate sabi;
+
+fn main() {
+    sabi::save_tool::main();
+}
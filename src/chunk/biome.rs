@@ -0,0 +1,405 @@
+//! Overworld generation from several layers of noise. The elevation, moisture
+//! and temperature at a cell decide its biome, which picks the cells it's made
+//! of, what gets scattered around and what lives there. Everything is a pure
+//! function of the position and the world seed, so a chunk comes out the same
+//! every time it's generated.
+
+use std::u32;
+
+use noise::{NoiseModule, Perlin, Seedable};
+
+use chunk::{CHUNK_WIDTH, Chunk, ChunkIndex};
+use chunk::layout::ChunkLayout;
+use graphics::cell::Cell;
+use point::Point;
+use world::WorldPosition;
+
+const ELEVATION_SCALE: f32 = 0.008;
+const MOISTURE_SCALE: f32 = 0.012;
+const TEMPERATURE_SCALE: f32 = 0.005;
+const POND_SCALE: f32 = 0.09;
+
+/// Pond noise above this turns into water, in biomes that have ponds.
+const POND_THRESHOLD: f32 = 0.55;
+
+/// Mountains higher than this are solid rock.
+const PEAK_ELEVATION: f32 = 0.75;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum Biome {
+    Ocean,
+    Beach,
+    Grassland,
+    Forest,
+    Swamp,
+    Desert,
+    Tundra,
+    Mountain,
+}
+
+/// The noise values at a position, all roughly between -1 and 1.
+#[derive(Clone, Copy, Debug)]
+pub struct Climate {
+    pub elevation: f32,
+    pub moisture: f32,
+    pub temperature: f32,
+}
+
+/// Something scattered over a biome, replacing the ground.
+pub struct Feature {
+    pub cell: &'static str,
+    pub chance: f32,
+}
+
+pub struct SpawnEntry {
+    pub name: &'static str,
+    pub sprite: &'static str,
+    pub health: i32,
+    pub weight: u32,
+}
+
+/// A Lua prefab that can be stamped into a biome, like a house. Towns go
+/// where the world layout puts them instead.
+pub struct PrefabEntry {
+    pub prefab: &'static str,
+    pub weight: u32,
+}
+
+static NO_FEATURES: [Feature; 0] = [];
+static GRASSLAND_FEATURES: [Feature; 2] = [
+    Feature { cell: "tree", chance: 0.02 },
+    Feature { cell: "rock", chance: 0.005 },
+];
+static FOREST_FEATURES: [Feature; 2] = [
+    Feature { cell: "tree", chance: 0.3 },
+    Feature { cell: "rock", chance: 0.01 },
+];
+static SWAMP_FEATURES: [Feature; 1] = [
+    Feature { cell: "tree", chance: 0.08 },
+];
+static DESERT_FEATURES: [Feature; 1] = [
+    Feature { cell: "rock", chance: 0.02 },
+];
+static TUNDRA_FEATURES: [Feature; 2] = [
+    Feature { cell: "rock", chance: 0.03 },
+    Feature { cell: "tree", chance: 0.01 },
+];
+static MOUNTAIN_FEATURES: [Feature; 1] = [
+    Feature { cell: "rock", chance: 0.15 },
+];
+
+static NO_SPAWNS: [SpawnEntry; 0] = [];
+static GRASSLAND_SPAWNS: [SpawnEntry; 1] = [
+    SpawnEntry { name: "putit", sprite: "putit", health: 50, weight: 1 },
+];
+static FOREST_SPAWNS: [SpawnEntry; 2] = [
+    SpawnEntry { name: "putit", sprite: "putit", health: 50, weight: 3 },
+    SpawnEntry { name: "forest putit", sprite: "putit", health: 80, weight: 1 },
+];
+static SWAMP_SPAWNS: [SpawnEntry; 1] = [
+    SpawnEntry { name: "bog putit", sprite: "putit", health: 100, weight: 1 },
+];
+static DESERT_SPAWNS: [SpawnEntry; 1] = [
+    SpawnEntry { name: "sand putit", sprite: "putit", health: 60, weight: 1 },
+];
+static TUNDRA_SPAWNS: [SpawnEntry; 1] = [
+    SpawnEntry { name: "snow putit", sprite: "putit", health: 120, weight: 1 },
+];
+static MOUNTAIN_SPAWNS: [SpawnEntry; 1] = [
+    SpawnEntry { name: "rock putit", sprite: "putit", health: 150, weight: 1 },
+];
+
+/// Every spawn table, for looking up monsters placed by prefabs.
+static ALL_SPAWNS: [&'static [SpawnEntry]; 6] = [
+    &GRASSLAND_SPAWNS,
+    &FOREST_SPAWNS,
+    &SWAMP_SPAWNS,
+    &DESERT_SPAWNS,
+    &TUNDRA_SPAWNS,
+    &MOUNTAIN_SPAWNS,
+];
+
+static NO_PREFABS: [PrefabEntry; 0] = [];
+static GRASSLAND_PREFABS: [PrefabEntry; 1] = [
+    PrefabEntry { prefab: "house", weight: 1 },
+];
+static FOREST_PREFABS: [PrefabEntry; 1] = [
+    PrefabEntry { prefab: "house", weight: 1 },
+];
+
+impl Biome {
+    pub fn from_climate(climate: &Climate) -> Biome {
+        if climate.elevation < -0.25 {
+            Biome::Ocean
+        } else if climate.elevation < -0.15 {
+            Biome::Beach
+        } else if climate.elevation > 0.5 {
+            Biome::Mountain
+        } else if climate.temperature < -0.35 {
+            Biome::Tundra
+        } else if climate.temperature > 0.3 && climate.moisture < -0.1 {
+            Biome::Desert
+        } else if climate.moisture > 0.35 {
+            Biome::Swamp
+        } else if climate.moisture > 0.0 {
+            Biome::Forest
+        } else {
+            Biome::Grassland
+        }
+    }
+
+    /// The cell most of the biome is made of.
+    pub fn ground(&self) -> &'static str {
+        match *self {
+            Biome::Ocean => "water",
+            Biome::Beach | Biome::Desert => "sand",
+            Biome::Grassland | Biome::Forest | Biome::Swamp => "grass",
+            Biome::Tundra => "tile",
+            Biome::Mountain => "cobble",
+        }
+    }
+
+    pub fn features(&self) -> &'static [Feature] {
+        match *self {
+            Biome::Ocean | Biome::Beach => &NO_FEATURES,
+            Biome::Grassland => &GRASSLAND_FEATURES,
+            Biome::Forest => &FOREST_FEATURES,
+            Biome::Swamp => &SWAMP_FEATURES,
+            Biome::Desert => &DESERT_FEATURES,
+            Biome::Tundra => &TUNDRA_FEATURES,
+            Biome::Mountain => &MOUNTAIN_FEATURES,
+        }
+    }
+
+    fn has_ponds(&self) -> bool {
+        match *self {
+            Biome::Grassland | Biome::Forest | Biome::Swamp => true,
+            _ => false,
+        }
+    }
+
+    pub fn spawn_table(&self) -> &'static [SpawnEntry] {
+        match *self {
+            Biome::Ocean | Biome::Beach => &NO_SPAWNS,
+            Biome::Grassland => &GRASSLAND_SPAWNS,
+            Biome::Forest => &FOREST_SPAWNS,
+            Biome::Swamp => &SWAMP_SPAWNS,
+            Biome::Desert => &DESERT_SPAWNS,
+            Biome::Tundra => &TUNDRA_SPAWNS,
+            Biome::Mountain => &MOUNTAIN_SPAWNS,
+        }
+    }
+
+    pub fn prefab_table(&self) -> &'static [PrefabEntry] {
+        match *self {
+            Biome::Grassland => &GRASSLAND_PREFABS,
+            Biome::Forest => &FOREST_PREFABS,
+            _ => &NO_PREFABS,
+        }
+    }
+
+    /// The chance that a chunk of this biome starts out with a monster.
+    pub fn spawn_chance(&self) -> f32 {
+        match *self {
+            Biome::Ocean | Biome::Beach => 0.0,
+            Biome::Grassland => 0.1,
+            Biome::Forest | Biome::Swamp => 0.25,
+            Biome::Desert | Biome::Tundra | Biome::Mountain => 0.15,
+        }
+    }
+}
+
+/// A number between 0 and 1 that only depends on its inputs. `salt` gives
+/// separate rolls for the same position.
+pub fn roll(seed: u32, pos: Point, salt: u32) -> f32 {
+    let mut h = seed ^ salt.wrapping_mul(0x9E37_79B9);
+    h ^= (pos.x as u32).wrapping_mul(0x85EB_CA6B);
+    h = h.rotate_left(13);
+    h ^= (pos.y as u32).wrapping_mul(0xC2B2_AE35);
+
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x85EB_CA6B);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0xC2B2_AE35);
+    h ^= h >> 16;
+
+    h as f32 / u32::MAX as f32
+}
+
+/// The noise layers of one world.
+pub struct BiomeNoise {
+    seed: u32,
+    elevation: Perlin,
+    moisture: Perlin,
+    temperature: Perlin,
+    ponds: Perlin,
+}
+
+impl BiomeNoise {
+    pub fn new(seed: u32) -> Self {
+        let layer = |n: u32| Perlin::new().set_seed(seed.wrapping_add(n) as usize);
+        BiomeNoise {
+            seed: seed,
+            elevation: layer(0),
+            moisture: layer(1),
+            temperature: layer(2),
+            ponds: layer(3),
+        }
+    }
+
+    fn sample(gen: &Perlin, pos: Point, scale: f32) -> f32 {
+        const COS_THETA: f32 = 0.99854;
+        const SIN_THETA: f32 = 0.05408;
+
+        let x = pos.x as f32;
+        let y = pos.y as f32;
+
+        // Perlin doesn't work on integer values, so rotate slightly.
+        let conv = |a: f32, b| scale * (a * COS_THETA + b * SIN_THETA);
+        gen.get([conv(y, -x), conv(x, y), 0.2333333333])
+    }
+
+    pub fn climate_at(&self, pos: Point) -> Climate {
+        Climate {
+            elevation: BiomeNoise::sample(&self.elevation, pos, ELEVATION_SCALE),
+            moisture: BiomeNoise::sample(&self.moisture, pos, MOISTURE_SCALE),
+            temperature: BiomeNoise::sample(&self.temperature, pos, TEMPERATURE_SCALE),
+        }
+    }
+
+    pub fn biome_at(&self, pos: Point) -> Biome {
+        Biome::from_climate(&self.climate_at(pos))
+    }
+
+    pub fn cell_at(&self, pos: Point) -> Cell {
+        let climate = self.climate_at(pos);
+        let biome = Biome::from_climate(&climate);
+
+        if biome == Biome::Mountain && climate.elevation > PEAK_ELEVATION {
+            return Cell::new("wall");
+        }
+
+        if biome.has_ponds() && BiomeNoise::sample(&self.ponds, pos, POND_SCALE) > POND_THRESHOLD {
+            return Cell::new("water");
+        }
+
+        for (i, feature) in biome.features().iter().enumerate() {
+            if roll(self.seed, pos, i as u32) < feature.chance {
+                return Cell::new(feature.cell);
+            }
+        }
+
+        Cell::new(biome.ground())
+    }
+}
+
+/// Generates a chunk of biomes, with the roads, rivers, towns and dungeon
+/// entrances from the world layout drawn over them. The layout isn't drawn
+/// out at sea.
+pub fn generate_biomes(index: &ChunkIndex, seed: u32) -> Chunk {
+    let noise = BiomeNoise::new(seed);
+    let layout = ChunkLayout::new(index, seed);
+    let corner = WorldPosition::from(*index);
+
+    let mut cells = Vec::new();
+    for j in 0..CHUNK_WIDTH {
+        for i in 0..CHUNK_WIDTH {
+            let pos = corner + (i, j);
+            let cell = match layout.feature_at(pos) {
+                Some(feature) if noise.biome_at(pos) != Biome::Ocean => feature.cell(),
+                _ => noise.cell_at(pos),
+            };
+            cells.push(cell);
+        }
+    }
+
+    Chunk { cells: cells }
+}
+
+/// Picks the monsters a chunk starts out with, from the spawn table of the
+/// biome at its center. Some of them may end up somewhere they can't stand,
+/// so they still have to be checked before being placed.
+pub fn biome_spawns(index: &ChunkIndex, seed: u32) -> Vec<(WorldPosition, &'static SpawnEntry)> {
+    // Different salts from the ones used for features.
+    const SPAWN_SALT: u32 = 1000;
+
+    let noise = BiomeNoise::new(seed);
+    let corner = WorldPosition::from(*index);
+    let biome = noise.biome_at(corner + (CHUNK_WIDTH / 2, CHUNK_WIDTH / 2));
+    let table = biome.spawn_table();
+
+    if table.is_empty() || roll(seed, corner, SPAWN_SALT) >= biome.spawn_chance() {
+        return Vec::new();
+    }
+
+    let offset = |salt| (roll(seed, corner, salt) * CHUNK_WIDTH as f32) as i32 % CHUNK_WIDTH;
+    let pos = corner + (offset(SPAWN_SALT + 1), offset(SPAWN_SALT + 2));
+
+    let total: u32 = table.iter().map(|e| e.weight).sum();
+    let mut pick = (roll(seed, corner, SPAWN_SALT + 3) * total as f32) as u32;
+    for entry in table.iter() {
+        if pick < entry.weight {
+            return vec![(pos, entry)];
+        }
+        pick -= entry.weight;
+    }
+
+    vec![(pos, &table[table.len() - 1])]
+}
+
+/// Finds a monster from any biome's spawn table by name.
+pub fn spawn_entry(name: &str) -> Option<&'static SpawnEntry> {
+    ALL_SPAWNS.iter()
+        .flat_map(|table| table.iter())
+        .find(|entry| entry.name == name)
+}
+
+/// The prefabs that can be stamped at a position, from the biome there.
+pub fn biome_prefabs(pos: WorldPosition, seed: u32) -> &'static [PrefabEntry] {
+    BiomeNoise::new(seed).biome_at(pos).prefab_table()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use bincode::{self, Infinite};
+
+    #[test]
+    fn test_deterministic() {
+        let index = ChunkIndex::new(3, -7);
+        let a = bincode::serialize(&generate_biomes(&index, 42), Infinite).unwrap();
+        let b = bincode::serialize(&generate_biomes(&index, 42), Infinite).unwrap();
+        assert_eq!(a, b);
+
+        let c = bincode::serialize(&generate_biomes(&index, 43), Infinite).unwrap();
+        assert!(a != c);
+
+        for i in 0..32 {
+            let index = ChunkIndex::new(i, i * 2);
+            let a: Vec<_> = biome_spawns(&index, 42).iter().map(|&(p, e)| (p, e.name)).collect();
+            let b: Vec<_> = biome_spawns(&index, 42).iter().map(|&(p, e)| (p, e.name)).collect();
+            assert_eq!(a, b);
+        }
+    }
+
+    #[test]
+    fn test_several_biomes() {
+        let noise = BiomeNoise::new(1);
+        let mut biomes = HashSet::new();
+        for x in 0..40 {
+            for y in 0..40 {
+                biomes.insert(noise.biome_at(Point::new(x * 32, y * 32)));
+            }
+        }
+        assert!(biomes.len() >= 3, "Only found {:?}", biomes);
+    }
+
+    #[test]
+    fn test_roll() {
+        let pos = Point::new(5, 9);
+        assert_eq!(roll(1, pos, 0), roll(1, pos, 0));
+        assert!(roll(1, pos, 0) != roll(1, pos, 1));
+        assert!((0..100).map(|i| roll(1, Point::new(i, 0), 0)).all(|r| r >= 0.0 && r <= 1.0));
+    }
+}
@@ -2,6 +2,7 @@ use noise::{NoiseModule, Perlin, Seedable};
 
 use graphics::cell::Cell;
 use chunk::{CHUNK_WIDTH, Chunk, ChunkIndex};
+use chunk::biome::{self, PrefabEntry, SpawnEntry};
 use world::WorldPosition;
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -9,6 +10,9 @@ pub enum ChunkType {
     Blank,
     Fill(Cell),
     Perlin,
+    /// An overworld of biomes, from layers of elevation, moisture and
+    /// temperature noise.
+    Biomes,
 }
 
 use self::ChunkType::*;
@@ -19,6 +23,32 @@ impl ChunkType {
             Blank => generate_blank(Cell::new("floor")),
             Fill(cell) => generate_blank(cell),
             Perlin => generate_perlin(index, seed),
+            Biomes => biome::generate_biomes(index, seed),
+        }
+    }
+
+    /// Whether the world layout decides where dungeon entrances go. Otherwise
+    /// every chunk gets stairs of its own.
+    pub fn uses_layout(&self) -> bool {
+        match *self {
+            Biomes => true,
+            _ => false,
+        }
+    }
+
+    /// The monsters a newly generated chunk starts out with.
+    pub fn spawns(&self, index: &ChunkIndex, seed: u32) -> Vec<(WorldPosition, &'static SpawnEntry)> {
+        match *self {
+            Biomes => biome::biome_spawns(index, seed),
+            _ => Vec::new(),
+        }
+    }
+
+    /// The prefabs that can be stamped into the world around a position.
+    pub fn prefabs(&self, pos: WorldPosition, seed: u32) -> &'static [PrefabEntry] {
+        match *self {
+            Biomes => biome::biome_prefabs(pos, seed),
+            _ => &[],
         }
     }
 }
@@ -0,0 +1,389 @@
+//! The coarse layout of the overworld: where towns, dungeon entrances, roads
+//! and rivers go. It's decided per layout region from the seed alone, so a
+//! chunk can draw the parts of a road or river that cross it without looking
+//! at its neighbours, and the pieces line up no matter what order chunks are
+//! generated in.
+//!
+//! Every region has a node, which is either a town or a crossroads. Roads run
+//! between the nodes of neighbouring regions. Rivers cross the edges between
+//! regions at points that only depend on the edge, and run from each crossing
+//! to a meeting point inside the region on either side of it.
+
+use chunk::biome::roll;
+use chunk::{CHUNK_WIDTH, ChunkIndex};
+use graphics::cell::{Cell, CellFeature, StairDest, StairDir};
+use point::Point;
+use world::WorldPosition;
+
+/// The width and height of a layout region, in cells.
+pub const LAYOUT_REGION_SIZE: i32 = 128;
+
+/// Keeps nodes away from region edges, so roads don't hug them.
+const NODE_MARGIN: i32 = 24;
+
+const TOWN_CHANCE: f32 = 0.35;
+const TOWN_RADIUS: i32 = 10;
+const DUNGEON_CHANCE: f32 = 0.4;
+const ROAD_CHANCE: f32 = 0.7;
+const RIVER_CHANCE: f32 = 0.25;
+
+const ROAD_WIDTH: i32 = 1;
+const RIVER_WIDTH: i32 = 2;
+
+// Salts for the rolls made per region or edge, so they don't repeat each
+// other.
+const SALT_NODE_X: u32 = 2000;
+const SALT_NODE_Y: u32 = 2001;
+const SALT_TOWN: u32 = 2002;
+const SALT_DUNGEON: u32 = 2003;
+const SALT_DUNGEON_X: u32 = 2004;
+const SALT_DUNGEON_Y: u32 = 2005;
+const SALT_ROAD_EAST: u32 = 2006;
+const SALT_ROAD_SOUTH: u32 = 2007;
+const SALT_RIVER_EAST: u32 = 2008;
+const SALT_RIVER_SOUTH: u32 = 2009;
+const SALT_RIVER_AT: u32 = 2010;
+const SALT_MEETING_X: u32 = 2011;
+const SALT_MEETING_Y: u32 = 2012;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct LayoutIndex(pub i32, pub i32);
+
+impl LayoutIndex {
+    pub fn from_world_pos(pos: WorldPosition) -> LayoutIndex {
+        let div = |a: i32| if a < 0 { (a + 1) / LAYOUT_REGION_SIZE - 1 } else { a / LAYOUT_REGION_SIZE };
+        LayoutIndex(div(pos.x), div(pos.y))
+    }
+
+    pub fn corner(&self) -> WorldPosition {
+        Point::new(self.0 * LAYOUT_REGION_SIZE, self.1 * LAYOUT_REGION_SIZE)
+    }
+
+    fn key(&self) -> Point {
+        Point::new(self.0, self.1)
+    }
+
+    fn offset(&self, dx: i32, dy: i32) -> LayoutIndex {
+        LayoutIndex(self.0 + dx, self.1 + dy)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Town {
+    pub center: WorldPosition,
+    pub radius: i32,
+}
+
+/// A straight line of some width, the pieces roads and rivers are made of.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Segment {
+    pub from: WorldPosition,
+    pub to: WorldPosition,
+    pub width: i32,
+}
+
+impl Segment {
+    fn contains(&self, pos: WorldPosition) -> bool {
+        let (ax, ay) = (self.from.x as f32, self.from.y as f32);
+        let (bx, by) = (self.to.x as f32, self.to.y as f32);
+        let (px, py) = (pos.x as f32, pos.y as f32);
+
+        let (dx, dy) = (bx - ax, by - ay);
+        let len_sq = dx * dx + dy * dy;
+        let t = if len_sq == 0.0 { 0.0 } else { (((px - ax) * dx + (py - ay) * dy) / len_sq).max(0.0).min(1.0) };
+
+        let (cx, cy) = (ax + t * dx, ay + t * dy);
+        let dist_sq = (px - cx) * (px - cx) + (py - cy) * (py - cy);
+        let radius = self.width as f32 / 2.0;
+        dist_sq <= radius * radius
+    }
+
+    fn touches(&self, min: WorldPosition, max: WorldPosition) -> bool {
+        let pad = self.width;
+        self.from.x.min(self.to.x) - pad <= max.x && self.from.x.max(self.to.x) + pad >= min.x &&
+            self.from.y.min(self.to.y) - pad <= max.y && self.from.y.max(self.to.y) + pad >= min.y
+    }
+}
+
+/// What the layout puts at a position.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LayoutFeature {
+    Town,
+    Road,
+    River,
+    DungeonEntrance,
+}
+
+impl LayoutFeature {
+    /// The cell drawn for this feature, in place of whatever the chunk's
+    /// generator put there.
+    pub fn cell(&self) -> Cell {
+        match *self {
+            LayoutFeature::Town => Cell::new("floor"),
+            LayoutFeature::Road => Cell::new("cobble"),
+            LayoutFeature::River => Cell::new("water"),
+            LayoutFeature::DungeonEntrance => {
+                let mut cell = Cell::new("floor");
+                cell.feature = Some(CellFeature::Stairs(StairDir::Descending, StairDest::Ungenerated));
+                cell
+            },
+        }
+    }
+
+    /// Whether prefabs stamped over this feature have to leave it alone.
+    /// Towns are only open ground, so they can be built on.
+    pub fn is_kept(&self) -> bool {
+        match *self {
+            LayoutFeature::Town => false,
+            _ => true,
+        }
+    }
+}
+
+/// The parts of the layout decided by one region.
+#[derive(Clone, Debug)]
+pub struct RegionLayout {
+    pub index: LayoutIndex,
+    /// Where roads in the region meet. A town is built around it, if there is
+    /// one.
+    pub node: WorldPosition,
+    pub town: Option<Town>,
+    pub dungeon_entrance: Option<WorldPosition>,
+    pub roads: Vec<Segment>,
+    pub rivers: Vec<Segment>,
+}
+
+fn point_in_region(index: LayoutIndex, seed: u32, salt_x: u32, salt_y: u32) -> WorldPosition {
+    let span = (LAYOUT_REGION_SIZE - NODE_MARGIN * 2) as f32;
+    let at = |salt| NODE_MARGIN + (roll(seed, index.key(), salt) * span) as i32;
+    index.corner() + (at(salt_x), at(salt_y))
+}
+
+fn node(index: LayoutIndex, seed: u32) -> WorldPosition {
+    point_in_region(index, seed, SALT_NODE_X, SALT_NODE_Y)
+}
+
+fn river_meeting(index: LayoutIndex, seed: u32) -> WorldPosition {
+    point_in_region(index, seed, SALT_MEETING_X, SALT_MEETING_Y)
+}
+
+/// Where a river crosses the edge between a region and its neighbour to the
+/// east or south, if one does. Both regions come to the same answer.
+fn river_crossing(index: LayoutIndex, seed: u32, east: bool) -> Option<WorldPosition> {
+    let salt = if east { SALT_RIVER_EAST } else { SALT_RIVER_SOUTH };
+    if roll(seed, index.key(), salt) >= RIVER_CHANCE {
+        return None;
+    }
+
+    let along = NODE_MARGIN + (roll(seed, index.key(), SALT_RIVER_AT + salt) *
+                               (LAYOUT_REGION_SIZE - NODE_MARGIN * 2) as f32) as i32;
+    let corner = index.corner();
+    if east {
+        Some(corner + (LAYOUT_REGION_SIZE, along))
+    } else {
+        Some(corner + (along, LAYOUT_REGION_SIZE))
+    }
+}
+
+/// Roads take an L-shaped path from one node to the other, so they line up
+/// with the grid.
+fn road_between(a: WorldPosition, b: WorldPosition) -> Vec<Segment> {
+    let bend = Point::new(b.x, a.y);
+    vec![
+        Segment { from: a, to: bend, width: ROAD_WIDTH },
+        Segment { from: bend, to: b, width: ROAD_WIDTH },
+    ]
+}
+
+impl RegionLayout {
+    pub fn new(index: LayoutIndex, seed: u32) -> Self {
+        let key = index.key();
+        let here = node(index, seed);
+
+        let town = if roll(seed, key, SALT_TOWN) < TOWN_CHANCE {
+            Some(Town { center: here, radius: TOWN_RADIUS })
+        } else {
+            None
+        };
+
+        let dungeon_entrance = if roll(seed, key, SALT_DUNGEON) < DUNGEON_CHANCE {
+            Some(point_in_region(index, seed, SALT_DUNGEON_X, SALT_DUNGEON_Y))
+        } else {
+            None
+        };
+
+        // Each region owns the roads to its east and south neighbours.
+        let mut roads = Vec::new();
+        if roll(seed, key, SALT_ROAD_EAST) < ROAD_CHANCE {
+            roads.extend(road_between(here, node(index.offset(1, 0), seed)));
+        }
+        if roll(seed, key, SALT_ROAD_SOUTH) < ROAD_CHANCE {
+            roads.extend(road_between(here, node(index.offset(0, 1), seed)));
+        }
+
+        // Each region draws its half of every river crossing its edges.
+        let meeting = river_meeting(index, seed);
+        let crossings = [
+            river_crossing(index, seed, true),
+            river_crossing(index, seed, false),
+            river_crossing(index.offset(-1, 0), seed, true),
+            river_crossing(index.offset(0, -1), seed, false),
+        ];
+        let rivers = crossings.iter()
+            .filter_map(|c| *c)
+            .map(|c| Segment { from: c, to: meeting, width: RIVER_WIDTH })
+            .collect();
+
+        RegionLayout {
+            index: index,
+            node: here,
+            town: town,
+            dungeon_entrance: dungeon_entrance,
+            roads: roads,
+            rivers: rivers,
+        }
+    }
+}
+
+/// The parts of the layout that reach into one chunk.
+pub struct ChunkLayout {
+    towns: Vec<Town>,
+    entrances: Vec<WorldPosition>,
+    roads: Vec<Segment>,
+    rivers: Vec<Segment>,
+}
+
+impl ChunkLayout {
+    pub fn new(index: &ChunkIndex, seed: u32) -> Self {
+        let min = WorldPosition::from(*index);
+        let max = min + (CHUNK_WIDTH - 1, CHUNK_WIDTH - 1);
+        ChunkLayout::within(LayoutIndex::from_world_pos(min), seed, min, max)
+    }
+
+    /// Collects the features near the rectangle from `min` to `max`, which
+    /// has to be inside the layout region `center`. Roads and rivers never
+    /// reach past the regions next to the one that owns them, so only those
+    /// have to be looked at.
+    fn within(center: LayoutIndex, seed: u32, min: WorldPosition, max: WorldPosition) -> Self {
+        let mut layout = ChunkLayout {
+            towns: Vec::new(),
+            entrances: Vec::new(),
+            roads: Vec::new(),
+            rivers: Vec::new(),
+        };
+
+        let inside = |p: WorldPosition, pad: i32| {
+            p.x + pad >= min.x && p.x - pad <= max.x && p.y + pad >= min.y && p.y - pad <= max.y
+        };
+
+        for dx in -1..2 {
+            for dy in -1..2 {
+                let region = RegionLayout::new(center.offset(dx, dy), seed);
+
+                layout.towns.extend(region.town.into_iter().filter(|t| inside(t.center, t.radius)));
+                layout.entrances.extend(region.dungeon_entrance.into_iter().filter(|&e| inside(e, 0)));
+                layout.roads.extend(region.roads.into_iter().filter(|s| s.touches(min, max)));
+                layout.rivers.extend(region.rivers.into_iter().filter(|s| s.touches(min, max)));
+            }
+        }
+
+        layout
+    }
+
+    /// Finds what the layout puts at a position. Roads are drawn over rivers,
+    /// as bridges.
+    pub fn feature_at(&self, pos: WorldPosition) -> Option<LayoutFeature> {
+        if self.entrances.contains(&pos) {
+            return Some(LayoutFeature::DungeonEntrance);
+        }
+        if self.towns.iter().any(|t| pos.distance(t.center) <= t.radius as f32) {
+            return Some(LayoutFeature::Town);
+        }
+        if self.roads.iter().any(|s| s.contains(pos)) {
+            return Some(LayoutFeature::Road);
+        }
+        if self.rivers.iter().any(|s| s.contains(pos)) {
+            return Some(LayoutFeature::River);
+        }
+        None
+    }
+
+    pub fn towns(&self) -> &[Town] {
+        &self.towns
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layout_index() {
+        assert_eq!(LayoutIndex::from_world_pos(Point::new(0, 0)), LayoutIndex(0, 0));
+        assert_eq!(LayoutIndex::from_world_pos(Point::new(127, 128)), LayoutIndex(0, 1));
+        assert_eq!(LayoutIndex::from_world_pos(Point::new(-1, -128)), LayoutIndex(-1, -1));
+        assert_eq!(LayoutIndex::from_world_pos(Point::new(-129, 0)), LayoutIndex(-2, 0));
+    }
+
+    #[test]
+    fn test_neighbours_agree() {
+        // A road or river leaving one region has to arrive in the next.
+        let seed = 7;
+        for x in -3..3 {
+            for y in -3..3 {
+                let index = LayoutIndex(x, y);
+                let region = RegionLayout::new(index, seed);
+                let east = RegionLayout::new(index.offset(1, 0), seed);
+                let south = RegionLayout::new(index.offset(0, 1), seed);
+
+                if let Some(crossing) = river_crossing(index, seed, true) {
+                    assert!(region.rivers.iter().any(|s| s.from == crossing));
+                    assert!(east.rivers.iter().any(|s| s.from == crossing));
+                }
+
+                for road in region.roads.chunks(2) {
+                    assert_eq!(road[0].from, region.node);
+                    assert_eq!(road[0].to, road[1].from);
+                    assert!(road[1].to == east.node || road[1].to == south.node);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_chunks_are_seamless() {
+        // Every chunk has to agree with the layout of the whole region, since
+        // it's drawn without looking at the chunks around it.
+        let seed = 3;
+        let region = LayoutIndex(0, 0);
+        let min = region.corner();
+        let max = min + (LAYOUT_REGION_SIZE - 1, LAYOUT_REGION_SIZE - 1);
+        let whole = ChunkLayout::within(region, seed, min, max);
+
+        let chunks = LAYOUT_REGION_SIZE / CHUNK_WIDTH;
+        for cx in 0..chunks {
+            for cy in 0..chunks {
+                let index = ChunkIndex::new(cx, cy);
+                let layout = ChunkLayout::new(&index, seed);
+                let corner = WorldPosition::from(index);
+                for i in 0..CHUNK_WIDTH {
+                    for j in 0..CHUNK_WIDTH {
+                        let pos = corner + (i, j);
+                        assert_eq!(layout.feature_at(pos), whole.feature_at(pos));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_deterministic() {
+        let a = RegionLayout::new(LayoutIndex(2, -5), 11);
+        let b = RegionLayout::new(LayoutIndex(2, -5), 11);
+        assert_eq!(a.node, b.node);
+        assert_eq!(a.town, b.town);
+        assert_eq!(a.dungeon_entrance, b.dungeon_entrance);
+        assert_eq!(a.roads, b.roads);
+        assert_eq!(a.rivers, b.rivers);
+    }
+}
@@ -1,5 +1,7 @@
+pub mod biome;
 pub mod generator;
 mod index;
+pub mod layout;
 mod pos;
 pub mod serial;
 
@@ -12,7 +14,7 @@ use point::Point;
 /// Represents a piece of terrain that is part of a larger World. Looking up
 /// cells in a World will resolve to a certain Chunk, but actors don't need to
 /// care about the underlying Chunks.
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Chunk {
     cells: Vec<Cell>,
 }
@@ -1,8 +1,44 @@
-use chunk::Chunk;
+//! How chunks are stored in region files. Each chunk can be compressed on its
+//! own, so a region file can mix compressed chunks with ones saved before
+//! compression was turned on.
 
+use std::fmt;
+use std::io::{Read, Write};
+use std::u64;
+
+use bincode::{self, Infinite};
+use flate2;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use serde::de::{self, Deserialize, Deserializer, SeqAccess, Visitor};
+use serde::ser::{self, Serialize, SerializeTuple, Serializer};
+
+use chunk::{Chunk, CHUNK_WIDTH};
+use graphics::cell::Cell;
 use infinigen::ManagedChunk;
 
-#[derive(Serialize, Deserialize)]
+/// Starts every chunk saved with a compression header. Chunks saved before
+/// there was one start with their cell count instead, which is never this.
+const STORED_MARKER: u64 = u64::MAX;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Compression {
+    None,
+    Deflate,
+}
+
+make_global!(CHUNK_COMPRESSION, Compression, Compression::Deflate);
+
+/// Sets how chunks are compressed when they're written from now on. Chunks
+/// are read the same way no matter how they were written.
+pub fn set_compression(compression: Compression) {
+    instance::with_mut(|c| *c = compression);
+}
+
+pub fn compression() -> Compression {
+    instance::with(|c| *c)
+}
+
 pub struct SerialChunk {
     pub chunk: Chunk,
 }
@@ -12,3 +48,143 @@ impl ManagedChunk for SerialChunk {
 
     const REGION_WIDTH: i32 = 16;
 }
+
+fn compress(chunk: &Chunk, compression: Compression) -> Result<Vec<u8>, String> {
+    let data = bincode::serialize(chunk, Infinite).map_err(|e| e.to_string())?;
+    match compression {
+        Compression::None => Ok(data),
+        Compression::Deflate => {
+            let mut encoder = DeflateEncoder::new(Vec::new(), flate2::Compression::Default);
+            encoder.write_all(&data).map_err(|e| e.to_string())?;
+            encoder.finish().map_err(|e| e.to_string())
+        },
+    }
+}
+
+fn decompress(data: &[u8], compression: Compression) -> Result<Chunk, String> {
+    let data = match compression {
+        Compression::None => data.to_vec(),
+        Compression::Deflate => {
+            let mut decoded = Vec::new();
+            DeflateDecoder::new(data).read_to_end(&mut decoded).map_err(|e| e.to_string())?;
+            decoded
+        },
+    };
+    bincode::deserialize(&data).map_err(|e| e.to_string())
+}
+
+impl Serialize for SerialChunk {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        let compression = compression();
+        let data = compress(&self.chunk, compression).map_err(<S::Error as ser::Error>::custom)?;
+
+        let mut tuple = serializer.serialize_tuple(3)?;
+        tuple.serialize_element(&STORED_MARKER)?;
+        tuple.serialize_element(&compression)?;
+        tuple.serialize_element(&data)?;
+        tuple.end()
+    }
+}
+
+struct SerialChunkVisitor;
+
+impl<'de> Visitor<'de> for SerialChunkVisitor {
+    type Value = SerialChunk;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a stored chunk")
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<SerialChunk, A::Error>
+        where A: SeqAccess<'de>
+    {
+        let missing = |i| <A::Error as de::Error>::invalid_length(i, &"a stored chunk");
+
+        let first: u64 = seq.next_element()?.ok_or_else(|| missing(0))?;
+
+        if first == STORED_MARKER {
+            let compression: Compression = seq.next_element()?.ok_or_else(|| missing(1))?;
+            let data: Vec<u8> = seq.next_element()?.ok_or_else(|| missing(2))?;
+            let chunk = decompress(&data, compression).map_err(<A::Error as de::Error>::custom)?;
+            return Ok(SerialChunk { chunk: chunk });
+        }
+
+        // An uncompressed chunk from before the header, which is just the
+        // length of its cell vector followed by the cells.
+        let mut cells = Vec::with_capacity(first as usize);
+        for i in 0..first as usize {
+            let cell: Cell = seq.next_element()?.ok_or_else(|| missing(i + 1))?;
+            cells.push(cell);
+        }
+        Ok(SerialChunk { chunk: Chunk { cells: cells } })
+    }
+}
+
+impl<'de> Deserialize<'de> for SerialChunk {
+    fn deserialize<D>(deserializer: D) -> Result<SerialChunk, D::Error>
+        where D: Deserializer<'de>
+    {
+        // Long enough to read an old chunk's cells one by one.
+        let max_len = (CHUNK_WIDTH * CHUNK_WIDTH) as usize + 1;
+        deserializer.deserialize_tuple(max_len, SerialChunkVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chunk::generator::ChunkType;
+    use chunk::ChunkIndex;
+    use graphics::cell::{CellFeature, DoorState, StairDest, StairDir};
+
+    fn chunk() -> Chunk {
+        ChunkType::Perlin.generate(&ChunkIndex::new(0, 0), 1)
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        for compression in [Compression::None, Compression::Deflate].iter() {
+            let data = compress(&chunk(), *compression).unwrap();
+            let decoded = decompress(&data, *compression).unwrap();
+            assert_eq!(bincode::serialize(&decoded, Infinite).unwrap(),
+                       bincode::serialize(&chunk(), Infinite).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_read_uncompressed() {
+        let old = bincode::serialize(&chunk(), Infinite).unwrap();
+        let decoded: SerialChunk = bincode::deserialize(&old).unwrap();
+        assert_eq!(bincode::serialize(&decoded.chunk, Infinite).unwrap(), old);
+    }
+
+    #[test]
+    fn test_compressed_is_smaller() {
+        let serial = SerialChunk { chunk: chunk() };
+        let plain = bincode::serialize(&chunk(), Infinite).unwrap();
+        let stored = bincode::serialize(&serial, Infinite).unwrap();
+        assert!(stored.len() < plain.len());
+
+        let decoded: SerialChunk = bincode::deserialize(&stored).unwrap();
+        assert_eq!(bincode::serialize(&decoded.chunk, Infinite).unwrap(), plain);
+    }
+
+    #[test]
+    fn test_read_uncompressed_doors() {
+        let mut old = chunk();
+        old.cells[0].feature = Some(CellFeature::Door(DoorState::Open));
+        old.cells[1].feature = Some(CellFeature::Door(DoorState::Closed));
+        old.cells[2].feature = Some(CellFeature::Stairs(StairDir::Descending, StairDest::Ungenerated));
+
+        let data = bincode::serialize(&old, Infinite).unwrap();
+        let decoded: SerialChunk = bincode::deserialize(&data).unwrap();
+        assert_eq!(bincode::serialize(&decoded.chunk, Infinite).unwrap(), data);
+
+        match decoded.chunk.cells[0].feature {
+            Some(CellFeature::Door(DoorState::Open)) => (),
+            other => panic!("Door decoded wrong: {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use std::collections::HashMap;
+
+use toml::Value;
+
+use util::toml::*;
+
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum Relation {
+    Friendly,
+    Neutral,
+    Hostile,
+}
+
+struct FactionTable {
+    relations: HashMap<(String, String), Relation>,
+}
+
+impl FactionTable {
+    fn insert(&mut self, a: &str, b: &str, relation: Relation) {
+        self.relations.insert((a.to_string(), b.to_string()), relation);
+        self.relations.insert((b.to_string(), a.to_string()), relation);
+    }
+
+    pub fn get(&self, a: &str, b: &str) -> Relation {
+        if a == b {
+            return Relation::Friendly;
+        }
+
+        self.relations.get(&(a.to_string(), b.to_string()))
+            .cloned()
+            .unwrap_or(Relation::Neutral)
+    }
+}
+
+fn make_faction_table() -> FactionTable {
+    let val = toml_value_from_file("data/factions.toml");
+    make_faction_table_from(&val)
+}
+
+fn make_faction_table_from(val: &Value) -> FactionTable {
+    let mut table = FactionTable { relations: HashMap::new() };
+
+    let faction_array = match get_value_in_table(val, "factions") {
+        Some(&Value::Array(ref array)) => array,
+        _           => panic!("Faction array wasn't an array."),
+    };
+
+    for faction in faction_array.iter() {
+        let name: String = expect_value_in_table(&faction, "name");
+
+        let friendly: Vec<String> = get_value_in_table(&faction, "friendly")
+            .map_or(Vec::new(), |v| v.clone().try_into().unwrap());
+        let hostile: Vec<String> = get_value_in_table(&faction, "hostile")
+            .map_or(Vec::new(), |v| v.clone().try_into().unwrap());
+
+        for other in friendly.iter() {
+            table.insert(&name, other, Relation::Friendly);
+        }
+        for other in hostile.iter() {
+            table.insert(&name, other, Relation::Hostile);
+        }
+    }
+
+    table
+}
+
+lazy_static! {
+    static ref FACTION_TABLE: FactionTable = make_faction_table();
+}
+
+/// Gets how members of faction `a` feel about members of faction `b`.
+pub fn relation(a: &str, b: &str) -> Relation {
+    FACTION_TABLE.get(a, b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_relations() {
+        let val = toml_value_from_string("
+[[factions]]
+name=\"player\"
+friendly=[\"pet\"]
+hostile=[\"monster\"]
+
+[[factions]]
+name=\"monster\"
+");
+        let table = make_faction_table_from(&val);
+        assert_eq!(table.get("player", "monster"), Relation::Hostile);
+        assert_eq!(table.get("monster", "player"), Relation::Hostile);
+        assert_eq!(table.get("pet", "player"), Relation::Friendly);
+        assert_eq!(table.get("monster", "monster"), Relation::Friendly);
+        assert_eq!(table.get("pet", "monster"), Relation::Neutral);
+    }
+}
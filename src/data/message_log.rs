@@ -3,6 +3,7 @@ use std::collections::VecDeque;
 pub struct MessageLog {
     log: VecDeque<String>,
     next_line: bool,
+    total: usize,
     pub valid: bool,
 }
 
@@ -11,11 +12,14 @@ impl MessageLog {
         MessageLog {
             log: VecDeque::new(),
             next_line: true,
+            total: 0,
             valid: false,
         }
     }
 
     pub fn append(&mut self, text: &str) {
+        self.total += 1;
+
         if self.next_line {
             self.log.push_front(String::new());
             self.next_line = false;
@@ -36,6 +40,11 @@ impl MessageLog {
         self.next_line = true;
     }
 
+    /// The number of messages appended so far.
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
     pub fn get_lines(&self, line_count: usize) -> Vec<String> {
         self.log.iter().take(line_count).cloned().collect()
     }
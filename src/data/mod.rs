@@ -1,4 +1,5 @@
 mod turn_order;
+pub mod faction;
 mod walkability;
 pub mod spatial;
 mod message_log;
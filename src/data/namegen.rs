@@ -3,7 +3,7 @@ use std::io::BufReader;
 use std::fs::File;
 use std::path::Path;
 
-use rand::{self, Rng};
+use rand::Rng;
 
 fn lines_from_file<P>(filename: P) -> Vec<String>
     where P: AsRef<Path> {
@@ -18,8 +18,6 @@ lazy_static!(
     static ref NAMES: Vec<String> = lines_from_file(NAME_FILE);
 );
 
-pub fn gen() -> String {
-    // NOTE: rng inside NameGen is probably overkill.
-    let mut rng = rand::thread_rng();
+pub fn gen<R: Rng>(rng: &mut R) -> String {
     rng.choose(NAMES.as_slice()).unwrap().clone()
 }
@@ -19,7 +19,9 @@ pub type TurnOrderResult<T> = Result<T, TurnOrderError>;
 // NOTE: This could be implemented with priority queues, but whatever.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TurnOrder {
+    #[serde(with = "::util::pairs")]
     active: BTreeMap<Entity, i32>,
+    #[serde(with = "::util::pairs")]
     paused: BTreeMap<Entity, i32>,
 }
 
@@ -1,6 +1,7 @@
 use std::collections::HashSet;
 use std::fmt;
 
+use calx_ecs::Entity;
 use serde::de::{self, Deserialize, Deserializer, Visitor};
 use serde::ser::{Serialize, Serializer};
 
@@ -8,6 +9,7 @@ use slog::Logger;
 
 use item::{ItemContainer, ItemEffect};
 use log;
+use logic::status::StatusEffect;
 use point::Point;
 use stats::properties::Properties;
 
@@ -25,6 +27,19 @@ impl Name {
     }
 }
 
+/// The faction an entity belongs to, which determines who it will attack. See
+/// `data/factions.toml`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Faction {
+    pub name: String,
+}
+
+impl Faction {
+    pub fn new(name: &str) -> Self {
+        Faction { name: name.to_string() }
+    }
+}
+
 #[cfg(never)]
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Position {
@@ -36,6 +51,12 @@ pub struct Position {
 pub struct Health {
     pub hit_points: i32,
     pub max_hit_points: i32,
+
+    /// Whoever hurt this last, so they can be credited if it dies. Not saved,
+    /// since entity IDs aren't kept when moving between maps.
+    #[serde(skip_serializing)]
+    #[serde(skip_deserializing)]
+    pub last_attacker: Option<Entity>,
 }
 
 impl Health {
@@ -44,6 +65,7 @@ impl Health {
         Health {
             hit_points: max,
             max_hit_points: max,
+            last_attacker: None,
         }
     }
 
@@ -55,6 +77,17 @@ impl Health {
         self.hit_points -= amount as i32;
     }
 
+    pub fn hurt_by(&mut self, amount: u32, attacker: Option<Entity>) {
+        self.hurt(amount);
+        if attacker.is_some() {
+            self.last_attacker = attacker;
+        }
+    }
+
+    pub fn heal(&mut self, amount: u32) {
+        self.hit_points = ::std::cmp::min(self.max_hit_points, self.hit_points + amount as i32);
+    }
+
     pub fn kill(&mut self) {
         self.hit_points = 0;
     }
@@ -95,7 +128,7 @@ impl Turn {
 pub struct Item {
     pub count: u32,
     pub weight: f32,
-    pub effect: ItemEffect,
+    pub effects: Vec<ItemEffect>,
 }
 
 impl Item {
@@ -103,7 +136,7 @@ impl Item {
         Item {
             count: 1,
             weight: 0.0,
-            effect: ItemEffect::Heal,
+            effects: Vec::new(),
         }
     }
 
@@ -111,8 +144,12 @@ impl Item {
         self.count as f32 * self.weight
     }
 
+    pub fn is_usable(&self) -> bool {
+        !self.effects.is_empty()
+    }
+
     pub fn can_merge(&self, other: &Item) -> bool {
-        false
+        self.weight == other.weight && self.effects == other.effects
     }
 
     pub fn merge(&mut self, other: &Item) {
@@ -120,6 +157,18 @@ impl Item {
     }
 }
 
+/// Status effects currently affecting an entity.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Effects {
+    pub effects: Vec<StatusEffect>,
+}
+
+impl Effects {
+    pub fn new() -> Self {
+        Effects { effects: Vec::new() }
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Fov {
     pub visible: HashSet<Point>,
@@ -200,6 +249,16 @@ pub struct Inventory {
     pub container: ItemContainer,
 }
 
+impl Inventory {
+    pub fn new() -> Self {
+        Inventory { container: ItemContainer::new() }
+    }
+
+    pub fn with_limits(capacity: usize, weight_limit: f32) -> Self {
+        Inventory { container: ItemContainer::with_limits(capacity, weight_limit) }
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Npc {}
 
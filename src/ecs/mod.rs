@@ -4,6 +4,7 @@ pub mod prefab;
 pub mod traits;
 
 use ai;
+use logic::activity;
 
 Ecs! {
     healths: components::Health,
@@ -16,5 +17,8 @@ Ecs! {
     ais: ai::Ai,
     fovs: components::Fov,
     npcs: components::Npc,
+    factions: components::Faction,
+    effects: components::Effects,
+    activities: activity::Activity,
     logs: components::Log,
 }
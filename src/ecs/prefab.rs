@@ -1,6 +1,8 @@
 use ai::{Ai, AiKind};
 use ecs::Loadout;
 use ecs::components::*;
+use item;
+use stats::properties::{Prop, Properties};
 
 pub fn mob(name: &str, health: i32, sprite: &str) -> Loadout {
     Loadout::new()
@@ -11,12 +13,25 @@ pub fn mob(name: &str, health: i32, sprite: &str) -> Loadout {
         .c(Ai::new(AiKind::SeekTarget))
         .c(Fov::new())
         .c(Log::new("mob"))
+        .c(Faction::new("monster"))
+}
+
+pub fn player(health: i32) -> Loadout {
+    mob("player", health, "player")
+        .c(Faction::new("player"))
+        .c(Inventory::new())
+}
+
+pub fn ranged_mob(name: &str, health: i32, sprite: &str) -> Loadout {
+    let mut props = Properties::new();
+    props.set(Prop::Ranged, true).unwrap();
+    mob(name, health, sprite).c(Props { props: props })
 }
 
 pub fn npc(name: &str) -> Loadout {
     mob(name, 1000, "npc").c(Npc::new()).c(
         Ai::new(AiKind::Wait),
-    )
+    ).c(Faction::new("townsfolk"))
 }
 
 pub fn item(name: &str, sprite: &str) -> Loadout {
@@ -26,3 +41,42 @@ pub fn item(name: &str, sprite: &str) -> Loadout {
         .c(Appearance::new(sprite))
         .c(Log::new("item"))
 }
+
+pub fn explosive_item(name: &str, sprite: &str) -> Loadout {
+    let mut props = Properties::new();
+    props.set(Prop::Explosive, true).unwrap();
+    item(name, sprite).c(Props { props: props })
+}
+
+/// An item that lets whoever carries it shoot.
+pub fn ranged_weapon(name: &str, sprite: &str) -> Loadout {
+    let mut props = Properties::new();
+    props.set(Prop::Ranged, true).unwrap();
+    item(name, sprite).c(Props { props: props })
+}
+
+/// An item that can lock and unlock doors.
+pub fn key(name: &str, sprite: &str) -> Loadout {
+    let mut props = Properties::new();
+    props.set(Prop::Key, true).unwrap();
+    item(name, sprite).c(Props { props: props })
+}
+
+/// What's left of the player after dying, holding everything they carried.
+pub fn corpse(name: &str) -> Loadout {
+    item(&format!("corpse of {}", name), "player").c(Inventory::new())
+}
+
+/// Creates an item from its definition in `data/item/`.
+pub fn item_from_data(name: &str) -> Loadout {
+    let data = item::effect::load(name);
+    let mut item = Item::new();
+    item.weight = data.weight;
+    item.effects = data.effects;
+
+    Loadout::new()
+        .c(Name::new(&data.name))
+        .c(item)
+        .c(Appearance::new(&data.sprite))
+        .c(Log::new("item"))
+}
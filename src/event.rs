@@ -1,86 +1,212 @@
-use actor::{Actor, ActorId};
-use logic::Action;
-use world::*;
-use point::{PointArea, SquareArea};
-use drawcalls::*;
+use calx_ecs::Entity;
+
+use ecs::traits::*;
+use logic::{self, Action};
+use point::{Point, SquareIter};
 use stats::properties::Prop::*;
+use world::traits::*;
+use world::World;
 
 pub use self::EventKind::*;
 
+/// Events can cause other events, like explosions setting off other
+/// explosives. Anything still happening after this many rounds is dropped.
+const MAX_EVENT_ROUNDS: u32 = 32;
+
+pub const EXPLOSION_RADIUS: i32 = 1;
+pub const EXPLOSION_DAMAGE: u32 = 100;
+
+/// How far away a shout can be heard.
+pub const SHOUT_RADIUS: i32 = 8;
+
 /// An event that can be broadcast to an area of a map. After actions are run,
 /// things inside the range that need to respond to the message can do so.
+#[derive(Clone, Debug)]
 pub struct Event {
     pub area: EventArea,
     pub kind: EventKind,
 }
 
-#[derive(Debug)]
+impl Event {
+    pub fn new(kind: EventKind, area: EventArea) -> Self {
+        Event {
+            kind: kind,
+            area: area,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
 pub enum EventKind {
-    SayName,
+    /// Something said aloud, heard by the player if they're close enough.
     SayThing(String),
-    Explosion,
+    /// Damages everything caught in it, crediting whoever set it off.
+    Explosion(u32, Option<Entity>),
 }
 
+#[derive(Clone, Debug)]
 pub enum EventArea {
-    Square(i32, i32, i32),
-    Actor(ActorId),
+    /// A square with the given radius.
+    Square(Point, i32),
+    /// A circle with the given radius.
+    Radius(Point, i32),
+    Entity(Entity),
+}
+
+impl EventArea {
+    fn points(&self, world: &World) -> Vec<Point> {
+        match *self {
+            EventArea::Square(center, radius) => SquareIter::new(center, radius).collect(),
+            EventArea::Radius(center, radius) => {
+                SquareIter::new(center, radius)
+                    .filter(|&pos| center.distance(pos) <= radius as f32)
+                    .collect()
+            },
+            EventArea::Entity(entity) => world.position(entity).into_iter().collect(),
+        }
+    }
 }
 
-pub fn check_all(world: &World) -> Vec<(Action, ActorId)> {
+/// Finds the things in range of the world's pending events, and how they will
+/// react to them.
+pub fn check_all(world: &World, events: &[Event]) -> Vec<(Entity, Action)> {
     let mut reactions = Vec::new();
-    for event in &world.events {
-        let mut area_iter = get_event_area_iter(world, &event.area);
-        while let Some(pos) = area_iter.next() {
-            world.draw_calls.push(Draw::Point(pos.x, pos.y));
-
-            if let Some(actor) = world.actor_at(pos) {
-                if !actor.is_dead() {
-                    debug!(world.logger, "{} not dead, handling event {:?}", actor, event.kind);
-                    if let Some(action) = handle_event(actor, world, &event.kind) {
-                        reactions.push((action, actor.get_id()))
-                    }
+
+    for event in events.iter() {
+        let mut affected = Vec::new();
+
+        match event.area {
+            EventArea::Entity(entity) => affected.push(entity),
+            _ => {
+                for pos in event.area.points(world) {
+                    affected.extend(world.entities_at(pos));
                 }
+            },
+        }
+
+        for entity in affected.into_iter() {
+            if let Some(action) = handle_event(world, entity, &event.kind) {
+                debug_ecs!(world, entity, "Reacting to {:?} with {:?}", event.kind, action);
+                reactions.push((entity, action));
             }
         }
     }
+
     reactions
 }
 
-fn handle_event(actor: &Actor,
-                _world: &World,
-                event: &EventKind) -> Option<(Action)> {
-    // Oh god.
+fn handle_event(world: &World, entity: Entity, event: &EventKind) -> Option<Action> {
     match *event {
-        EventKind::Explosion => {
-            if actor.properties.check_bool(Explosive) {
-                Some(Action::Explod)
+        EventKind::Explosion(damage, cause) => {
+            let explosive = world.ecs().props.map_or(false, |p| p.props.check_bool(Explosive), entity);
+            if explosive {
+                Some(Action::Explode(cause))
+            } else if world.is_mob(entity) && world.is_alive(entity) {
+                Some(Action::Hurt(damage, cause))
             } else {
-                Some(Action::Hurt(10))
+                None
             }
+        },
+        EventKind::SayThing(ref text) => {
+            if world.is_player(entity) {
+                Some(Action::Hear(text.clone()))
+            } else {
+                None
+            }
+        },
+    }
+}
+
+/// Runs the reactions to all pending events, then the reactions to any events
+/// those reactions caused, and so on.
+pub fn process_events(world: &mut World) {
+    let mut rounds = 0;
+
+    loop {
+        let events = world.take_events();
+        if events.is_empty() {
+            break;
+        }
+
+        if rounds >= MAX_EVENT_ROUNDS {
+            warn!(world.logger, "Events still happening after {} rounds, dropping {} of them.",
+                  rounds, events.len());
+            break;
         }
-        _ => {
-            warn!(actor.logger, "{} can't handle event {:?}!", actor, event);
-            None
+        rounds += 1;
+
+        let reactions = check_all(world, &events);
+        for (entity, action) in reactions.into_iter() {
+            logic::run_reaction(world, entity, action);
         }
     }
+
+    world.update_killed();
 }
 
-fn get_event_area_iter(world: &World, area: &EventArea) -> Box<WorldIter> {
-    match *area {
-        EventArea::Square(x, y, r) => Box::new(SquareArea::new(WorldPosition::new(x, y), r)),
-        EventArea::Actor(id)       => Box::new(PointArea::new(world.actor(&id).get_pos())),
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ecs::prefab;
+    use testing::*;
+
+    #[test]
+    fn test_explosion() {
+        let mut context = test_context();
+        let world = &mut context.state.world;
+        let mob = place_mob(world, Point::new(2, 2));
 
+        world.push_event(Event::new(Explosion(1000, None), EventArea::Square(Point::new(1, 1), 1)));
+        process_events(world);
+
+        assert!(!world.is_alive(mob));
     }
-}
 
-// impl World {
-//     pub fn run_next_event(&mut self, event: Event) -> Vec<(Action, ActorId)> {
-//         if let Some(event) = self.event_queue.pop() {
-//             for pos in event.iter {
-//                 if let Some(actor) = self.actor_at_mut(pos) {
-//                     say_thing(actor, self, &event);
-//                 }
-//             }
-//         }
-//     }
-// }
+    #[test]
+    fn test_chain_explosion() {
+        let mut context = test_context();
+        let world = &mut context.state.world;
+        let mob = place_mob(world, Point::new(8, 8));
+        let bombs: Vec<Entity> = (3..8).map(|i| {
+            world.create(prefab::explosive_item("bomb", "berry"), Point::new(i, i))
+        }).collect();
+
+        world.push_event(Event::new(Explosion(0, None), EventArea::Square(Point::new(2, 2), 1)));
+        process_events(world);
+
+        for bomb in bombs.iter() {
+            assert!(!world.ecs().contains(*bomb));
+        }
+        assert!(!world.is_alive(mob));
+    }
+
+    #[test]
+    fn test_bounded_rounds() {
+        let mut context = test_context_bounded(64, 64);
+        let world = &mut context.state.world;
+        let bombs: Vec<Entity> = (0..MAX_EVENT_ROUNDS as i32 + 8).map(|i| {
+            world.create(prefab::explosive_item("bomb", "berry"), Point::new(i, 2))
+        }).collect();
+
+        world.push_event(Event::new(Explosion(0, None), EventArea::Entity(bombs[0])));
+        process_events(world);
+
+        assert!(world.ecs().contains(*bombs.last().unwrap()));
+    }
+
+    #[test]
+    fn test_shout() {
+        let mut context = test_context();
+        let world = &mut context.state.world;
+        let near = world.create(prefab::npc("dude"), Point::new(4, 4));
+        let far = world.create(prefab::npc("dude"), Point::new(20, 20));
+
+        logic::run_action(world, far, Action::Shout("Over here!".to_string()));
+        process_events(world);
+        assert!(!world.get_messages(10).iter().any(|m| m.contains("Over here!")));
+
+        logic::run_action(world, near, Action::Shout("Hello!".to_string()));
+        process_events(world);
+        assert!(world.get_messages(10).iter().any(|m| m.contains("Hello!")));
+    }
+}
@@ -12,6 +12,7 @@ struct CellData {
     tile: String,
     seethrough: bool,
     passable: bool,
+    diggable: bool,
 }
 
 struct CellTable {
@@ -52,12 +53,14 @@ fn make_cell_data_table() -> CellTable {
         let tile: String = expect_value_in_table(&cell, "tile");
         let seethrough: bool = expect_value_in_table(&cell, "seethrough");
         let passable: bool = expect_value_in_table(&cell, "passable");
+        let diggable: bool = expect_value_in_table(&cell, "diggable");
 
         let data = CellData {
             name: name.clone(),
             tile: tile,
             seethrough: seethrough,
             passable: passable,
+            diggable: diggable,
         };
 
         indices.insert(name, idx);
@@ -97,9 +100,23 @@ impl StairDir {
     }
 }
 
+#[derive(Serialize, Deserialize, Eq, PartialEq, Debug, Copy, Clone)]
+pub enum DoorState {
+    Open,
+    Closed,
+    Locked,
+}
+
+impl DoorState {
+    /// Returns true if the door blocks movement and sight.
+    pub fn is_blocking(&self) -> bool {
+        *self != DoorState::Open
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Copy, Clone)]
 pub enum CellFeature {
-    Door(bool),
+    Door(DoorState),
     Stairs(StairDir, StairDest),
 }
 
@@ -139,11 +156,27 @@ impl Cell {
     }
 
     pub fn can_see_through(&self) -> bool {
-        get_cell(self.type_).seethrough
+        get_cell(self.type_).seethrough && !self.has_closed_door()
     }
 
     pub fn can_pass_through(&self) -> bool {
-        get_cell(self.type_).passable
+        get_cell(self.type_).passable && !self.has_closed_door()
+    }
+
+    /// Whether this is a bare wall that can be dug through.
+    pub fn can_dig(&self) -> bool {
+        get_cell(self.type_).diggable && self.feature.is_none()
+    }
+
+    pub fn door_state(&self) -> Option<DoorState> {
+        match self.feature {
+            Some(Door(state)) => Some(state),
+            _                 => None,
+        }
+    }
+
+    fn has_closed_door(&self) -> bool {
+        self.door_state().map_or(false, |d| d.is_blocking())
     }
 
     pub fn stair_dest_pos(&self) -> Option<Point> {
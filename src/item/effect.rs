@@ -1,5 +1,152 @@
-#[derive(Clone, Debug, Serialize, Deserialize)]
-pub enum ItemEffect {
-    Heal,
-    Damage
+use toml::Value;
+
+use util::toml::*;
+
+const ITEM_TABLE: &'static str = "item";
+const EFFECTS_ARRAY: &'static str = "effects";
+
+macro_attr! {
+    #[derive(Serialize, Deserialize, Eq, PartialEq, Clone, Debug, EnumFromStr!)]
+    pub enum EffectKind {
+        Heal,
+        Damage,
+        Teleport,
+    }
+}
+
+macro_attr! {
+    #[derive(Serialize, Deserialize, Eq, PartialEq, Clone, Debug, EnumFromStr!)]
+    pub enum EffectTarget {
+        /// The entity using the item.
+        User,
+        /// A position chosen when the item is used.
+        Selected,
+    }
+}
+
+/// Something that happens when an item is used. The amount means the number of
+/// hit points for `Heal` and `Damage`, and the farthest distance for
+/// `Teleport`. A radius above zero affects every mob in the area.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ItemEffect {
+    pub kind: EffectKind,
+    pub target: EffectTarget,
+    pub amount: u32,
+    pub radius: i32,
+}
+
+impl ItemEffect {
+    pub fn new(kind: EffectKind, amount: u32) -> Self {
+        ItemEffect {
+            kind: kind,
+            target: EffectTarget::User,
+            amount: amount,
+            radius: 0,
+        }
+    }
+}
+
+/// An item definition loaded from `data/item/`.
+pub struct ItemData {
+    pub name: String,
+    pub sprite: String,
+    pub weight: f32,
+    pub effects: Vec<ItemEffect>,
+}
+
+pub fn load(name: &str) -> ItemData {
+    let value = toml_value_from_file(&format!("./data/item/{}.toml", name));
+    make_item_data(&value)
+}
+
+fn make_item_data(value: &Value) -> ItemData {
+    let item = match get_value_in_table(value, ITEM_TABLE) {
+        Some(t) => t,
+        None    => panic!("Item data had no [item] table!"),
+    };
+
+    let effects = match get_value_in_table(value, EFFECTS_ARRAY) {
+        Some(&Value::Array(ref array)) => array.iter().map(make_effect).collect(),
+        Some(_)                        => panic!("[[effects]] was not an array!"),
+        None                           => Vec::new(),
+    };
+
+    ItemData {
+        name: expect_value_in_table(item, "name"),
+        sprite: expect_value_in_table(item, "sprite"),
+        weight: get_value_in_table(item, "weight")
+            .and_then(|v| v.as_float())
+            .unwrap_or(0.0) as f32,
+        effects: effects,
+    }
+}
+
+fn make_effect(value: &Value) -> ItemEffect {
+    let kind: String = expect_value_in_table(value, "kind");
+    let kind = match kind.parse::<EffectKind>() {
+        Ok(k)  => k,
+        Err(..) => panic!("No such item effect {} in the game.", kind),
+    };
+
+    let target = match get_value_in_table(value, "target").and_then(|v| v.as_str()) {
+        Some(t) => match t.parse::<EffectTarget>() {
+            Ok(t)  => t,
+            Err(..) => panic!("No such effect target {}.", t),
+        },
+        None    => EffectTarget::User,
+    };
+
+    let amount = get_value_in_table(value, "amount").and_then(|v| v.as_integer()).unwrap_or(0);
+    let radius = get_value_in_table(value, "radius").and_then(|v| v.as_integer()).unwrap_or(0);
+    assert!(amount >= 0, "Item effect amount can't be negative!");
+
+    ItemEffect {
+        kind: kind,
+        target: target,
+        amount: amount as u32,
+        radius: radius as i32,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_item(s: &str) -> ItemData {
+        let value = toml_value_from_string(s);
+        make_item_data(&value)
+    }
+
+    #[test]
+    fn test_effects() {
+        let item = test_item("
+[item]
+name=\"bomb\"
+sprite=\"cola\"
+
+[[effects]]
+kind=\"Damage\"
+target=\"Selected\"
+amount=50
+radius=2
+");
+        assert_eq!(item.effects.len(), 1);
+        assert_eq!(item.effects[0].kind, EffectKind::Damage);
+        assert_eq!(item.effects[0].target, EffectTarget::Selected);
+        assert_eq!(item.effects[0].amount, 50);
+        assert_eq!(item.effects[0].radius, 2);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_invalid_effect() {
+        test_item("
+[item]
+name=\"thing\"
+sprite=\"cola\"
+
+[[effects]]
+kind=\"Explode\"
+");
+    }
 }
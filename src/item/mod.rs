@@ -1,11 +1,10 @@
-mod effect;
+pub mod effect;
 
-pub use self::effect::ItemEffect;
-
-use std::collections::HashSet;
+pub use self::effect::{EffectKind, EffectTarget, ItemEffect};
 
 use calx_ecs::Entity;
 
+use ecs::traits::*;
 use world::World;
 use world::traits::*;
 
@@ -15,9 +14,12 @@ fn same_object<T>(a: &T, b: &T) -> bool {
 
 pub type ItemIdx = usize;
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub enum ItemErr {
-    CannotPutInContainer,
+    /// The container has no room for another stack of items.
+    ContainerFull,
+    /// The item would put the container over its weight limit.
+    TooHeavy,
 }
 
 pub use self::ItemErr::*;
@@ -33,81 +35,121 @@ pub struct ItemDesc {
     pub sprite: String,
 }
 
-/// An collection of items inside a container, like a chest or actor's inventory.
+/// How weighed down an entity is by what it carries. Heavier burdens make
+/// actions take longer.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Burden {
+    Unburdened,
+    Burdened,
+    Stressed,
+    Strained,
+}
+
+impl Burden {
+    /// Percentage of the normal delay actions take at this burden level.
+    pub fn delay_percent(&self) -> u32 {
+        match *self {
+            Burden::Unburdened => 100,
+            Burden::Burdened => 125,
+            Burden::Stressed => 150,
+            Burden::Strained => 200,
+        }
+    }
+}
+
+/// The limits on what can be put inside a container, like a chest or actor's
+/// inventory. The items themselves are tracked by the world's spatial index.
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ItemContainer {
+    /// Maximum number of stacks.
     pub capacity: usize,
     pub weight_limit: f32,
-    pub items: HashSet<Entity>,
 
     pub cached_weight: f32,
 }
 
 impl ItemContainer {
     pub fn new() -> Self {
+        ItemContainer::with_limits(100, 100.0)
+    }
+
+    pub fn with_limits(capacity: usize, weight_limit: f32) -> Self {
         ItemContainer {
-            capacity: 100,
-            weight_limit: 100.0,
-            items: HashSet::new(),
+            capacity: capacity,
+            weight_limit: weight_limit,
 
             cached_weight: 0.0,
         }
     }
 
-    pub fn recalculate_weight(&mut self, world: &World) -> f32 {
-        let mut total = 0.0;
-        for entity in self.items.iter() {
-            // i.containing.recalculate_weight();
-            let item = world.ecs().items.get(*entity).unwrap();
-            total += item.weight();
+    /// Checks if `item` could be put inside this container, which belongs to
+    /// `owner`. Items that merge into an existing stack don't need a free
+    /// slot.
+    pub fn can_acquire(&self, world: &World, owner: Entity, item: Entity) -> ItemResult<()> {
+        let weight = world.ecs().items.map_or(0.0, |i| i.weight(), item);
+
+        if find_stack(world, owner, item).is_none() &&
+            world.entities_in(owner).len() >= self.capacity
+        {
+            return Err(ContainerFull);
         }
-        self.cached_weight = total;
-        total
+
+        if contents_weight(world, owner) + weight > self.weight_limit {
+            return Err(TooHeavy);
+        }
+
+        Ok(())
     }
+
+    pub fn burden(&self) -> Burden {
+        let ratio = self.cached_weight / self.weight_limit;
+        if ratio < 0.5 {
+            Burden::Unburdened
+        } else if ratio < 0.75 {
+            Burden::Burdened
+        } else if ratio < 1.0 {
+            Burden::Stressed
+        } else {
+            Burden::Strained
+        }
+    }
+}
+
+/// The total weight of the items inside `owner`.
+pub fn contents_weight(world: &World, owner: Entity) -> f32 {
+    world.entities_in(owner)
+         .iter()
+         .map(|&e| world.ecs().items.map_or(0.0, |i| i.weight(), e))
+         .sum()
 }
 
-// impl ItemContainer {
-//     pub fn can_acquire(&self, item: &Entity, world: &World) -> bool {
-//         let item_compo = world.ecs().items.get(*item).unwrap();
-
-//         if self.items.len() >= self.capacity {
-//             return false
-//         }
-//         if self.cached_weight + item_compo.weight() > self.weight_limit {
-//             return false
-//         }
-
-//         true
-//     }
-
-//     pub fn acquire(&mut self, item: Entity, world: &mut World) -> ItemResult<()> {
-//         let item_compo = world.ecs().items.get(item).unwrap();
-//         for i in self.items.iter() {
-//             let i_compo = world.ecs_mut().items.get_mut(*i).unwrap();
-//             if i_compo.can_merge(&item_compo) {
-//                 i_compo.merge(item_compo);
-//                 return Ok(());
-//             }
-//         }
-
-//         if !self.can_acquire(&item, world) {
-//             return Err(CannotPutInContainer)
-//         }
-
-//         assert!(!self.items.contains(&item));
-//         self.items.insert(item);
-
-//         self.recalculate_weight(world);
-
-//         Ok(())
-//     }
-
-//     pub fn remove(&mut self, entity: Entity) -> ItemResult<()> {
-//         self.items.remove(&entity);
-//         Ok(())
-//     }
-
-//     pub fn len(&self) -> usize {
-//         self.items.len()
-//     }
-// }
+/// Returns true if the two items are the same kind of thing and can be put in
+/// the same stack. Items holding other items, like corpses, never stack, or
+/// the contents of one would be lost.
+pub fn can_stack(world: &World, a: Entity, b: Entity) -> bool {
+    if a == b {
+        return false;
+    }
+
+    let ecs = world.ecs();
+    if ecs.invs.has(a) || ecs.invs.has(b) {
+        return false;
+    }
+
+    let same_name = match (ecs.names.get(a), ecs.names.get(b)) {
+        (Some(x), Some(y)) => x.name == y.name,
+        _ => false,
+    };
+
+    match (ecs.items.get(a), ecs.items.get(b)) {
+        (Some(x), Some(y)) => same_name && x.can_merge(y),
+        _ => false,
+    }
+}
+
+/// Finds a stack inside `owner` that `item` could be merged into.
+pub fn find_stack(world: &World, owner: Entity, item: Entity) -> Option<Entity> {
+    world.entities_in(owner)
+         .into_iter()
+         .find(|&e| can_stack(world, e, item))
+}
@@ -0,0 +1,303 @@
+#![feature(associated_consts)]
+#![feature(conservative_impl_trait)]
+#![feature(test)]
+
+#[macro_use]
+extern crate calx_ecs;
+#[macro_use]
+extern crate enum_derive;
+#[macro_use]
+extern crate hlua;
+#[macro_use]
+extern crate lazy_static;
+#[macro_use]
+extern crate macro_attr;
+#[macro_use]
+extern crate serde_derive;
+#[macro_use]
+extern crate slog;
+
+extern crate backtrace;
+extern crate bincode;
+extern crate calx_alg;
+extern crate cgmath;
+extern crate chrono;
+extern crate crypto;
+extern crate flate2;
+extern crate glob;
+extern crate goap;
+extern crate image;
+extern crate infinigen;
+extern crate noise;
+extern crate rand;
+extern crate regex;
+extern crate rusttype;
+extern crate serde;
+extern crate serde_json;
+extern crate slog_stream;
+extern crate texture_packer;
+extern crate toml;
+pub extern crate tcod;
+
+extern crate test;
+
+#[macro_use]
+extern crate glium;
+
+// Macros must be used before all other modules
+#[macro_use]
+mod macros;
+
+mod ai;
+mod chunk;
+mod data;
+mod ecs;
+mod engine;
+mod event;
+mod graphics;
+mod item;
+mod log;
+mod logic;
+mod lua;
+mod morgue;
+mod point;
+mod prefab;
+mod renderer;
+mod replay;
+pub mod save_tool;
+mod state;
+mod stats;
+mod terrain;
+mod testbed;
+mod util;
+mod world;
+
+#[cfg(test)]
+mod testing;
+
+use glium::glutin;
+use glium::glutin::{VirtualKeyCode, ElementState};
+use state::GameState;
+use engine::keys::{Key, KeyCode};
+
+pub struct GameContext {
+    state: GameState,
+
+    /// Records the player's input, if a replay was requested.
+    recorder: Option<replay::Recorder>,
+
+    /// Answers to prompts from the replay being played, if any.
+    playback: Option<replay::Playback>,
+}
+
+impl GameContext {
+    pub fn new() -> Self {
+        GameContext {
+            state: GameState::new(),
+            recorder: None,
+            playback: None,
+        }
+    }
+}
+
+/// Entry point of the game binary.
+pub fn main() {
+    let args: Vec<String> = std::env::args().collect();
+
+    match args.get(1).map(|s| s.as_str()) {
+        Some("--replay") => {
+            let path = args.get(2).expect("Usage: sabi --replay <file>");
+            run_replay(path);
+        },
+        Some("--record") => {
+            let path = args.get(2).expect("Usage: sabi --record <file>");
+            run_recording(path);
+        },
+        _ => run(),
+    }
+}
+
+fn init() {
+    log::init_panic_hook();
+}
+
+pub fn run() {
+    init();
+
+    let mut context = title_screen();
+    game_loop(&mut context);
+    write_morgue(&context);
+    save(&mut context);
+
+    println!("Exited cleanly.");
+}
+
+/// Lets the player pick a save slot to continue, or start a new game in a new
+/// slot. Exits if the player quits instead.
+fn title_screen() -> GameContext {
+    use renderer::ui::layers::{TitleChoice, TitleLayer};
+
+    let mut error = world::serial::migrate_legacy_save().err()
+        .map(|e| format!("Couldn't move the old save into a slot: {:?}", e));
+
+    loop {
+        let slots = world::serial::list_slots();
+        let mut layer = match error.take() {
+            Some(e) => TitleLayer::new(&slots).with_error(&e),
+            None => TitleLayer::new(&slots),
+        };
+        let choice = renderer::with_mut(|rc| rc.query(&mut layer));
+
+        match choice {
+            None => std::process::exit(0),
+            Some(TitleChoice::NewGame) => {
+                if let Some(context) = new_game() {
+                    return context;
+                }
+            },
+            Some(TitleChoice::Load(slot)) => {
+                world::serial::set_slot(&slot);
+                match state::load_context() {
+                    Ok(context) => return context,
+                    Err(e) => error = Some(format!("Couldn't load {}: {}", slot, e)),
+                }
+            },
+            Some(TitleChoice::Delete(slot)) => {
+                if let Err(e) = world::serial::delete_slot(&slot) {
+                    error = Some(format!("Couldn't delete {}: {:?}", slot, e));
+                }
+            },
+        }
+    }
+}
+
+fn new_game() -> Option<GameContext> {
+    use ecs::traits::*;
+    use renderer::ui::layers::{ChoiceLayer, InputLayer};
+    use world::flags::GameMode;
+    use world::traits::*;
+
+    let name = renderer::with_mut(|rc| rc.query(&mut InputLayer::new("Name your character:")))?;
+    if name.is_empty() {
+        return None;
+    }
+
+    let modes = vec!["Normal".to_string(), "Permadeath".to_string()];
+    let mode = match renderer::with_mut(|rc| rc.query(&mut ChoiceLayer::new(modes)))? {
+        1 => GameMode::Permadeath,
+        _ => GameMode::Normal,
+    };
+
+    world::serial::set_slot(&world::serial::new_slot_name(&name));
+    world::serial::init_paths().unwrap();
+
+    let mut context = state::load_context().ok()?;
+    context.state.world.flags_mut().globals.mode = mode;
+    if let Some(player) = context.state.world.player() {
+        context.state.world.ecs_mut().names.map_mut(|n| n.name = name.clone(), player);
+    }
+
+    // Write the manifest right away so the slot shows up on the title screen.
+    if let Err(e) = world::serial::save_manifest(&context.state.world) {
+        println!("Couldn't create save slot: {}", e);
+    }
+
+    Some(context)
+}
+
+fn run_recording(path: &str) {
+    init();
+
+    let mut context = title_screen();
+    context.recorder = Some(replay::Recorder::new(&context.state.world).unwrap());
+
+    game_loop(&mut context);
+
+    // Saving unloads the world, so the recording has to be finished first.
+    if let Some(recorder) = context.recorder.take() {
+        recorder.finish(&context.state.world).save(path).unwrap();
+    }
+    save(&mut context);
+
+    println!("Recorded replay to {}.", path);
+}
+
+/// Plays back a replay with no window and prints the resulting world.
+fn run_replay(path: &str) {
+    init();
+
+    let replay = replay::Replay::load(path).expect("Couldn't read replay file");
+    let mut context = GameContext::new();
+
+    match replay::verify(&mut context, &replay) {
+        Ok(summary) => {
+            println!("{}", summary);
+            println!("Replay finished, {} keys played.", replay.keys.len());
+        },
+        Err(e) => {
+            println!("{}", e);
+            std::process::exit(1);
+        },
+    }
+}
+
+fn game_loop(context: &mut GameContext) {
+    renderer::with_mut(|rc| rc.update(context));
+
+    'outer: loop {
+        let events = renderer::with(|rc| rc.poll_events());
+        if !events.is_empty() {
+            for event in events {
+                match event {
+                    glutin::Event::Closed => break 'outer,
+                    glutin::Event::Resized(w, h) => {
+                        renderer::with_mut(|renderer| renderer.set_viewport(w, h));
+                        continue;
+                    },
+                    _ => (),
+                }
+
+                match event {
+                    glutin::Event::KeyboardInput(ElementState::Pressed, _, Some(code)) => {
+                        match code {
+                            VirtualKeyCode::Escape => break 'outer,
+                            _ => {
+                                let key = Key::from(KeyCode::from(code));
+                                state::game_step(context, Some(key));
+                                renderer::with_mut(|renderer| renderer.update(context));
+                            },
+                        }
+                    },
+                    _ => (),
+                }
+                renderer::with_mut(|renderer| renderer.render());
+            }
+        } else {
+            renderer::with_mut(|renderer| renderer.render());
+        }
+
+        renderer::with_mut(|renderer| renderer.step_frame());
+    }
+}
+
+/// Writes a summary of the character on quitting. A failure here isn't worth
+/// losing the save over.
+fn write_morgue(context: &GameContext) {
+    let world = &context.state.world;
+    if let Some(player) = world.player() {
+        match morgue::write(world, player, morgue::Ending::Quit) {
+            Ok(path) => println!("Wrote {}", path.display()),
+            Err(e) => println!("Couldn't write the morgue file: {}", e),
+        }
+    }
+}
+
+fn save(context: &mut GameContext) {
+    let result = world::serial::save_world(&mut context.state.world)
+        .and_then(|_| world::serial::save_manifest(&context.state.world));
+
+    if let Err(e) = result {
+        println!("Failed to save the game! {}", e);
+        std::process::exit(1);
+    }
+}
@@ -1,10 +1,21 @@
 use calx_ecs::Entity;
+use rand::Rng;
+
 use data::Walkability;
+use ecs::Loadout;
+use event::{self, Event, EventArea, EventKind};
 use ecs::traits::*;
+use graphics::cell::{CellFeature, DoorState};
+use item::{self, EffectKind, EffectTarget, ItemEffect, ItemErr};
+use logic::activity::{self, ActivityKind};
 use logic::entity::EntityQuery;
-use point::{Direction, Point};
+use logic::projectile;
+use point::{Direction, Point, SquareIter};
 use stats;
+use stats::properties::Prop;
+use util::format;
 use world::traits::*;
+use world::flags::RngStream;
 use world::{World, WorldPosition};
 
 pub type ActionResult = Result<(), ()>;
@@ -14,9 +25,27 @@ pub enum Action {
     Move(Direction),
     MoveOrAttack(Direction),
     Wait,
+    OpenDoor(Direction),
+    CloseDoor(Direction),
+    LockDoor(Direction),
+    UnlockDoor(Direction),
     SwingAt(Entity),
     Pickup(Entity),
-    Drop(Entity),
+    /// Drops the given number of items from a stack.
+    Drop(Entity, u32),
+    Shoot(WorldPosition),
+    Throw(Entity, WorldPosition),
+    StartActivity(ActivityKind),
+    Rest,
+    Dig(WorldPosition),
+    Use(Entity),
+    UseAt(Entity, WorldPosition),
+    Shout(String),
+
+    // Reactions to events.
+    Explode(Option<Entity>),
+    Hurt(u32, Option<Entity>),
+    Hear(String),
 
     Teleport(WorldPosition),
     TeleportUnchecked(WorldPosition),
@@ -26,11 +55,26 @@ pub fn run_entity_action(world: &mut World, entity: Entity, action: Action) -> A
     match action {
         Action::MoveOrAttack(dir) => action_move_or_attack(world, entity, dir),
         Action::Move(dir) => action_move_entity(world, entity, dir),
+        Action::OpenDoor(dir) => action_open_door(world, entity, dir),
+        Action::CloseDoor(dir) => action_close_door(world, entity, dir),
+        Action::LockDoor(dir) => action_lock_door(world, entity, dir),
+        Action::UnlockDoor(dir) => action_unlock_door(world, entity, dir),
         Action::Pickup(target) => action_pickup(world, entity, target),
-        Action::Drop(target) => action_drop(world, entity, target),
+        Action::Drop(target, count) => action_drop(world, entity, target, count),
         Action::Teleport(pos) => action_try_teleport(world, entity, pos),
         Action::TeleportUnchecked(pos) => action_teleport_unchecked(world, entity, pos),
         Action::SwingAt(target) => action_swing_at(world, entity, target),
+        Action::Shoot(pos) => action_shoot(world, entity, pos),
+        Action::Throw(item, pos) => action_throw(world, entity, item, pos),
+        Action::StartActivity(kind) => action_start_activity(world, entity, kind),
+        Action::Rest => action_rest(world, entity),
+        Action::Dig(pos) => action_dig(world, entity, pos),
+        Action::Use(item) => action_use(world, entity, item, None),
+        Action::UseAt(item, pos) => action_use(world, entity, item, Some(pos)),
+        Action::Shout(text) => action_shout(world, entity, text),
+        Action::Explode(cause) => action_explode(world, entity, cause),
+        Action::Hurt(damage, cause) => action_hurt(world, entity, damage, cause),
+        Action::Hear(text) => action_hear(world, entity, text),
         _ => Err(()),
     }
 }
@@ -53,6 +97,292 @@ fn action_move_entity(world: &mut World, entity: Entity, dir: Direction) -> Acti
     world.move_entity(entity, dir).map_err(|_| ())
 }
 
+fn action_open_door(world: &mut World, entity: Entity, dir: Direction) -> ActionResult {
+    let pos = world.position(entity).ok_or(())? + dir;
+
+    match world.door_at(&pos) {
+        Some(DoorState::Closed) => {
+            set_door_state(world, pos, DoorState::Open);
+            format_mes!(world, entity, "%U <open> the door.");
+            Ok(())
+        },
+        Some(DoorState::Locked) => {
+            if world.is_player(entity) {
+                mes!(world, "The door is locked.");
+            }
+            Err(())
+        },
+        _ => Err(()),
+    }
+}
+
+fn action_close_door(world: &mut World, entity: Entity, dir: Direction) -> ActionResult {
+    let pos = world.position(entity).ok_or(())? + dir;
+
+    if world.door_at(&pos) != Some(DoorState::Open) {
+        return Err(());
+    }
+
+    if !world.entities_at(pos).is_empty() {
+        if world.is_player(entity) {
+            mes!(world, "Something is in the way.");
+        }
+        return Err(());
+    }
+
+    set_door_state(world, pos, DoorState::Closed);
+    format_mes!(world, entity, "%U <close> the door.");
+    Ok(())
+}
+
+fn is_key(world: &World, entity: Entity) -> bool {
+    world.ecs().props.map_or(false, |p| p.props.check_bool(Prop::Key), entity)
+}
+
+/// Returns true if an entity carries a key, which fits every lock.
+pub fn has_key(world: &World, entity: Entity) -> bool {
+    world.entities_in(entity).into_iter().any(|item| is_key(world, item))
+}
+
+fn action_lock_door(world: &mut World, entity: Entity, dir: Direction) -> ActionResult {
+    let pos = world.position(entity).ok_or(())? + dir;
+
+    if world.door_at(&pos) != Some(DoorState::Closed) || !has_key(world, entity) {
+        return Err(());
+    }
+
+    set_door_state(world, pos, DoorState::Locked);
+    format_mes!(world, entity, "%U <lock> the door.");
+    Ok(())
+}
+
+fn action_unlock_door(world: &mut World, entity: Entity, dir: Direction) -> ActionResult {
+    let pos = world.position(entity).ok_or(())? + dir;
+
+    if world.door_at(&pos) != Some(DoorState::Locked) || !has_key(world, entity) {
+        return Err(());
+    }
+
+    set_door_state(world, pos, DoorState::Closed);
+    format_mes!(world, entity, "%U <unlock> the door.");
+    Ok(())
+}
+
+fn set_door_state(world: &mut World, pos: WorldPosition, state: DoorState) {
+    if let Some(cell_mut) = world.cell_mut(&pos) {
+        cell_mut.feature = Some(CellFeature::Door(state));
+    }
+
+    // Doors change what can be seen, so the player's view has to be updated.
+    if let Some(player) = world.player() {
+        world.do_fov(player);
+    }
+}
+
+fn action_start_activity(world: &mut World, entity: Entity, kind: ActivityKind) -> ActionResult {
+    activity::start(world, entity, kind);
+    Ok(())
+}
+
+fn action_rest(world: &mut World, entity: Entity) -> ActionResult {
+    world.ecs_mut().healths.map_mut(|h| h.heal(1), entity);
+    Ok(())
+}
+
+fn action_dig(world: &mut World, entity: Entity, pos: WorldPosition) -> ActionResult {
+    if !world.position(entity).ok_or(())?.is_next_to(pos) {
+        return Err(());
+    }
+
+    if !world.cell_const(&pos).map_or(false, |c| c.can_dig()) {
+        return Err(());
+    }
+
+    match world.cell_mut(&pos) {
+        Some(cell_mut) => cell_mut.set("floor"),
+        None => return Err(()),
+    }
+
+    format_mes!(world, entity, "%U <dig> through the wall.");
+
+    if world.is_player(entity) {
+        world.do_fov(entity);
+    }
+
+    Ok(())
+}
+
+fn action_use(world: &mut World,
+              user: Entity,
+              item: Entity,
+              target: Option<WorldPosition>) -> ActionResult {
+    if !world.entities_in(user).contains(&item) {
+        return Err(());
+    }
+
+    let effects = match world.ecs().items.get(item) {
+        Some(i) if i.is_usable() => i.effects.clone(),
+        _ => return Err(()),
+    };
+
+    let user_pos = world.position(user).ok_or(())?;
+
+    if let Some(pos) = target {
+        if !projectile::in_line_of_sight(world, user_pos, pos) {
+            return Err(());
+        }
+    }
+
+    format_mes!(world, user, "%U <use> {}.", a = item.name(world));
+
+    for effect in effects.iter() {
+        let center = match effect.target {
+            EffectTarget::User => user_pos,
+            EffectTarget::Selected => target.unwrap_or(user_pos),
+        };
+        apply_item_effect(world, user, effect, center);
+    }
+
+    consume_item(world, item);
+
+    Ok(())
+}
+
+fn item_effect_targets(world: &World,
+                       user: Entity,
+                       effect: &ItemEffect,
+                       center: WorldPosition) -> Vec<Entity> {
+    if effect.radius > 0 {
+        return SquareIter::new(center, effect.radius)
+            .filter_map(|pos| world.mob_at(pos))
+            .collect();
+    }
+
+    match effect.target {
+        EffectTarget::User => vec![user],
+        EffectTarget::Selected => world.mob_at(center).into_iter().collect(),
+    }
+}
+
+fn apply_item_effect(world: &mut World, user: Entity, effect: &ItemEffect, center: WorldPosition) {
+    for target in item_effect_targets(world, user, effect, center) {
+        match effect.kind {
+            EffectKind::Heal => {
+                world.ecs_mut().healths.map_mut(|h| h.heal(effect.amount), target);
+                format_mes!(world, target, "%U <feel> better.");
+            },
+            EffectKind::Damage => {
+                world.ecs_mut().healths.map_mut(|h| h.hurt_by(effect.amount, Some(user)), target);
+                format_mes!(world, target, "%U <be> hurt! ({})", a = effect.amount);
+
+                if target != user && target.is_dead(world) {
+                    format_mes!(world, user, "%U <kill> {}!", a = target.name(world));
+                }
+            },
+            EffectKind::Teleport => teleport_randomly(world, target, effect.amount as i32),
+        }
+    }
+}
+
+const TELEPORT_TRIES: u32 = 100;
+
+fn teleport_randomly(world: &mut World, entity: Entity, distance: i32) {
+    let pos = match world.position(entity) {
+        Some(p) => p,
+        None => return,
+    };
+
+    for _ in 0..TELEPORT_TRIES {
+        let offset = {
+            let mut rng = world.rng(RngStream::Combat);
+            Point::new(rng.gen_range(-distance, distance + 1),
+                       rng.gen_range(-distance, distance + 1))
+        };
+        let new_pos = pos + offset;
+
+        if new_pos != pos && world.can_walk(new_pos, Walkability::MonstersBlocking) {
+            world.place_entity(entity, new_pos);
+            format_mes!(world, entity, "%U <teleport>.");
+            return;
+        }
+    }
+
+    format_mes!(world, entity, "%U <shudder> for a moment.");
+}
+
+/// Uses up one item in a stack, destroying it if it was the last one.
+fn consume_item(world: &mut World, item: Entity) {
+    let remaining = world.ecs_mut().items.map_mut(|i| {
+        i.count = i.count.saturating_sub(1);
+        i.count
+    }, item).unwrap_or(0);
+
+    if remaining == 0 {
+        world.kill_entity(item);
+        world.remove_entity(item);
+    }
+}
+
+/// Blows something up, hurting everything around it. Mobs die in the blast,
+/// and anything else is destroyed. Anything killed is credited to `cause`.
+pub fn explode(world: &mut World,
+               entity: Entity,
+               radius: i32,
+               damage: u32,
+               cause: Option<Entity>) {
+    let pos = match world.position(entity) {
+        Some(pos) => pos,
+        None => return,
+    };
+
+    format_mes!(world, entity, "%U <explode>!");
+    world.push_event(Event::new(EventKind::Explosion(damage, cause),
+                                EventArea::Square(pos, radius)));
+
+    if world.is_mob(entity) {
+        world.ecs_mut().healths.map_mut(|h| h.kill(), entity);
+    } else {
+        world.kill_entity(entity);
+        world.remove_entity(entity);
+    }
+}
+
+fn action_explode(world: &mut World, entity: Entity, cause: Option<Entity>) -> ActionResult {
+    if world.position(entity).is_none() {
+        return Err(());
+    }
+
+    explode(world, entity, event::EXPLOSION_RADIUS, event::EXPLOSION_DAMAGE, cause);
+    Ok(())
+}
+
+fn action_hurt(world: &mut World, entity: Entity, damage: u32, cause: Option<Entity>) -> ActionResult {
+    world.ecs_mut().healths.map_mut(|h| h.hurt_by(damage, cause), entity);
+    format_mes!(world, entity, "%U <be> hurt! ({})", a = damage);
+
+    if entity.is_dead(world) {
+        format_mes!(world, entity, "%U <be> blown to pieces!");
+    }
+
+    Ok(())
+}
+
+fn action_shout(world: &mut World, entity: Entity, text: String) -> ActionResult {
+    let pos = world.position(entity).ok_or(())?;
+    let said = format::format_message(&format!("%U <shout>, \"{}\"", text), entity, world);
+
+    world.push_event(Event::new(EventKind::SayThing(said),
+                                EventArea::Radius(pos, event::SHOUT_RADIUS)));
+    Ok(())
+}
+
+fn action_hear(world: &mut World, entity: Entity, text: String) -> ActionResult {
+    if world.is_player(entity) {
+        world.message(&text);
+    }
+    Ok(())
+}
+
 fn action_swing_at(world: &mut World, attacker: Entity, other: Entity) -> ActionResult {
     let damage;
     {
@@ -71,7 +401,7 @@ fn action_swing_at(world: &mut World, attacker: Entity, other: Entity) -> Action
 
         damage = stats::formulas::calculate_damage(world, attacker, other);
     }
-    world.ecs_mut().healths.map_mut(|h| h.hurt(damage), other);
+    world.ecs_mut().healths.map_mut(|h| h.hurt_by(damage, Some(attacker)), other);
 
     format_mes!(world, attacker, "%U <hit> {}! ({})", a = other.name(world), b = damage);
 
@@ -82,19 +412,138 @@ fn action_swing_at(world: &mut World, attacker: Entity, other: Entity) -> Action
     Ok(())
 }
 
+fn action_shoot(world: &mut World, attacker: Entity, target_pos: WorldPosition) -> ActionResult {
+    let pos = world.position(attacker).ok_or(())?;
+    if pos == target_pos || !projectile::can_shoot(world, attacker) {
+        return Err(());
+    }
+
+    let hit = projectile::trace(world, pos, target_pos);
+
+    format_mes!(world, attacker, "%U <shoot>.");
+
+    match hit.entity {
+        Some(other) => projectile_hit(world, attacker, other),
+        None => {
+            mes!(world, "The shot misses.");
+            Ok(())
+        },
+    }
+}
+
+fn action_throw(world: &mut World,
+                thrower: Entity,
+                item: Entity,
+                target_pos: WorldPosition) -> ActionResult {
+    if !world.entities_in(thrower).contains(&item) {
+        return Err(());
+    }
+
+    let pos = world.position(thrower).ok_or(())?;
+    if pos == target_pos {
+        return Err(());
+    }
+
+    let hit = projectile::trace(world, pos, target_pos);
+
+    format_mes!(world, thrower, "%U <throw> {}.", a = item.name(world));
+    world.place_entity(item, hit.landed_at);
+
+    let explosive = world.ecs().props.map_or(false, |p| p.props.check_bool(Prop::Explosive), item);
+    if explosive {
+        world.push_event(Event::new(EventKind::Explosion(0, Some(thrower)), EventArea::Entity(item)));
+    }
+
+    match hit.entity {
+        Some(other) => projectile_hit(world, thrower, other),
+        None => Ok(()),
+    }
+}
+
+fn projectile_hit(world: &mut World, attacker: Entity, other: Entity) -> ActionResult {
+    let missed = stats::formulas::check_evasion(world, attacker, other);
+    if missed {
+        format_mes!(world, other, "%U <dodge> it.");
+        return Ok(());
+    }
+
+    let damage = stats::formulas::calculate_ranged_damage(world, attacker, other);
+    world.ecs_mut().healths.map_mut(|h| h.hurt_by(damage, Some(attacker)), other);
+
+    format_mes!(world, other, "%U <be> hit! ({})", a = damage);
+
+    if other.is_dead(world) {
+        format_mes!(world, attacker, "%U <kill> {}! ({})", a = other.name(world), b = damage);
+    }
+
+    Ok(())
+}
+
 fn action_pickup(world: &mut World, parent: Entity, target: Entity) -> ActionResult {
-    world.place_entity_in(parent, target);
+    let check = world.ecs().invs.map_or(Ok(()), |inv| {
+        inv.container.can_acquire(world, parent, target)
+    }, parent);
+
+    if let Err(err) = check {
+        if world.is_player(parent) {
+            match err {
+                ItemErr::ContainerFull => mes!(world, "You can't carry any more things."),
+                ItemErr::TooHeavy => {
+                    mes!(world, "{} is too heavy to carry.", a = target.name(world));
+                },
+            }
+        }
+        return Err(());
+    }
+
     mes!(world, "{} picks up {}.", a = parent.name(world), b = target.name(world));
+
+    match item::find_stack(world, parent, target) {
+        Some(stack) => {
+            let picked_up = world.ecs().items.get_or_err(target).clone();
+            world.ecs_mut().items.map_mut(|i| i.merge(&picked_up), stack);
+            world.kill_entity(target);
+            world.remove_entity(target);
+        },
+        None => world.place_entity_in(parent, target),
+    }
+
     Ok(())
 }
 
-fn action_drop(world: &mut World, entity: Entity, target: Entity) -> ActionResult {
-    let pos = world.position(entity).unwrap();
-    world.place_entity(target, pos);
-    format_mes!(world, entity, "%U <drop> {}.", a = target.name(world));
+fn action_drop(world: &mut World, entity: Entity, target: Entity, count: u32) -> ActionResult {
+    if !world.entities_in(entity).contains(&target) {
+        return Err(());
+    }
+
+    let pos = world.position(entity).ok_or(())?;
+    let stack_count = world.ecs().items.map_or(1, |i| i.count, target);
+
+    if count == 0 || count > stack_count {
+        return Err(());
+    }
+
+    let dropped = if count < stack_count {
+        split_stack(world, target, count, pos)
+    } else {
+        world.place_entity(target, pos);
+        target
+    };
+
+    format_mes!(world, entity, "%U <drop> {}.", a = dropped.name(world));
     Ok(())
 }
 
+/// Takes `count` items off of a stack and places them as a new stack at `pos`.
+fn split_stack(world: &mut World, stack: Entity, count: u32, pos: WorldPosition) -> Entity {
+    let loadout = Loadout::get(world.ecs(), stack);
+    world.ecs_mut().items.map_mut(|i| i.count -= count, stack);
+
+    let split = world.create(loadout, pos);
+    world.ecs_mut().items.map_mut(|i| i.count = count, split);
+    split
+}
+
 fn action_try_teleport(world: &mut World, entity: Entity, pos: WorldPosition) -> ActionResult {
     if world.can_walk(pos, Walkability::MonstersBlocking) {
         format_mes!(world, entity, "Suddenly, %U <disappear>.");
@@ -0,0 +1,293 @@
+//! Actions that take several turns to finish, like resting or travelling.
+//! Activities are interrupted when something interesting happens to the entity
+//! performing them.
+
+use calx_ecs::Entity;
+
+use ecs::traits::*;
+use logic::Action;
+use logic::entity::EntityQuery;
+use point::{Direction, Point};
+use world::traits::*;
+use world::World;
+
+/// The most turns an entity will rest for before giving up.
+pub const REST_TURNS: u32 = 500;
+pub const DIG_TURNS: u32 = 5;
+pub const READ_TURNS: u32 = 3;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum ActivityKind {
+    /// Rest until healed.
+    Rest,
+    /// Dig through the given position.
+    Dig(Point),
+    /// Walk along a path, first point first.
+    Travel(Vec<Point>),
+    /// Read the given item, using it once finished.
+    Read(Entity),
+}
+
+impl ActivityKind {
+    fn turns(&self) -> u32 {
+        match *self {
+            ActivityKind::Rest => REST_TURNS,
+            ActivityKind::Dig(..) => DIG_TURNS,
+            ActivityKind::Travel(ref path) => path.len() as u32,
+            ActivityKind::Read(..) => READ_TURNS,
+        }
+    }
+
+    fn verb(&self) -> &'static str {
+        match *self {
+            ActivityKind::Rest => "resting",
+            ActivityKind::Dig(..) => "digging",
+            ActivityKind::Travel(..) => "travelling",
+            ActivityKind::Read(..) => "reading",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Interrupt {
+    Hurt,
+    HostileSeen,
+    Message,
+}
+
+/// A snapshot of the things about an entity that would interrupt it if they
+/// changed.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Watch {
+    hit_points: i32,
+    seen_hostiles: Vec<Entity>,
+    messages: usize,
+}
+
+impl Watch {
+    pub fn new(world: &World, entity: Entity) -> Self {
+        Watch {
+            hit_points: world.ecs().healths.map_or(0, |h| h.hit_points, entity),
+            seen_hostiles: visible_hostiles(world, entity),
+            messages: world.message_count(),
+        }
+    }
+
+    /// Returns the reason the entity should be interrupted, if any.
+    pub fn check(&self, world: &World, entity: Entity) -> Option<Interrupt> {
+        let hit_points = world.ecs().healths.map_or(0, |h| h.hit_points, entity);
+        if hit_points < self.hit_points {
+            return Some(Interrupt::Hurt);
+        }
+
+        let new_hostile = visible_hostiles(world, entity)
+            .iter()
+            .any(|e| !self.seen_hostiles.contains(e));
+        if new_hostile {
+            return Some(Interrupt::HostileSeen);
+        }
+
+        if world.is_player(entity) && world.message_count() > self.messages {
+            return Some(Interrupt::Message);
+        }
+
+        None
+    }
+}
+
+fn visible_hostiles(world: &World, entity: Entity) -> Vec<Entity> {
+    world.seen_entities(entity)
+         .into_iter()
+         .filter(|&e| entity.is_hostile_to(e, world))
+         .collect()
+}
+
+/// Returns true if a hostile entity is within view.
+pub fn hostile_in_view(world: &World, entity: Entity) -> bool {
+    !visible_hostiles(world, entity).is_empty()
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Activity {
+    pub kind: ActivityKind,
+    pub turns_left: u32,
+    watch: Watch,
+}
+
+impl Activity {
+    pub fn new(kind: ActivityKind, world: &World, entity: Entity) -> Self {
+        Activity {
+            turns_left: kind.turns(),
+            kind: kind,
+            watch: Watch::new(world, entity),
+        }
+    }
+}
+
+/// Starts an activity, replacing the current one.
+pub fn start(world: &mut World, entity: Entity, kind: ActivityKind) {
+    let activity = Activity::new(kind, world, entity);
+    world.ecs_mut().activities.insert(entity, activity);
+}
+
+pub fn in_progress(world: &World, entity: Entity) -> bool {
+    world.ecs().activities.has(entity)
+}
+
+/// Stops the entity's activity without finishing it.
+pub fn cancel(world: &mut World, entity: Entity) {
+    world.ecs_mut().activities.remove(entity);
+}
+
+/// Gets the next action of the entity's activity, if it has one and it wasn't
+/// interrupted. Interrupted and completed activities are removed.
+pub fn next_action(world: &mut World, entity: Entity) -> Option<Action> {
+    let mut activity = match world.ecs().activities.get(entity) {
+        Some(a) => a.clone(),
+        None => return None,
+    };
+
+    if let Some(reason) = activity.watch.check(world, entity) {
+        debug_ecs!(world, entity, "Interrupted: {:?}", reason);
+        cancel(world, entity);
+        format_mes!(world, entity, "%U <stop> {}.", a = activity.kind.verb());
+        return None;
+    }
+
+    let action = step(world, entity, &mut activity);
+
+    match action {
+        Some(..) => {
+            world.ecs_mut().activities.insert(entity, activity);
+        },
+        None => {
+            cancel(world, entity);
+            finish(world, entity, &activity);
+        }
+    }
+
+    action
+}
+
+/// Takes a new snapshot after the entity has acted, so changes it caused
+/// itself aren't counted as interruptions.
+pub fn refresh(world: &mut World, entity: Entity) {
+    let watch = Watch::new(world, entity);
+    world.ecs_mut().activities.map_mut(|a| a.watch = watch, entity);
+}
+
+fn step(world: &World, entity: Entity, activity: &mut Activity) -> Option<Action> {
+    if activity.turns_left == 0 {
+        return None;
+    }
+    activity.turns_left -= 1;
+
+    match activity.kind {
+        ActivityKind::Rest => {
+            let healed = world.ecs().healths.map_or(true, |h| h.hit_points >= h.max_hit_points, entity);
+            if healed {
+                None
+            } else {
+                Some(Action::Rest)
+            }
+        },
+        ActivityKind::Dig(pos) => {
+            if activity.turns_left == 0 {
+                Some(Action::Dig(pos))
+            } else {
+                Some(Action::Wait)
+            }
+        },
+        ActivityKind::Travel(ref mut path) => {
+            if path.is_empty() {
+                return None;
+            }
+            let next = path.remove(0);
+            let pos = match world.position(entity) {
+                Some(p) => p,
+                None => return None,
+            };
+            Direction::from_neighbors(pos, next).map(Action::Move)
+        },
+        ActivityKind::Read(item) => {
+            if activity.turns_left == 0 {
+                Some(Action::Use(item))
+            } else {
+                Some(Action::Wait)
+            }
+        },
+    }
+}
+
+fn finish(world: &mut World, entity: Entity, activity: &Activity) {
+    match activity.kind {
+        ActivityKind::Rest => {
+            format_mes!(world, entity, "%U <finish> resting.");
+        },
+        _ => (),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ecs;
+    use point::POINT_ZERO;
+    use state;
+    use testing::*;
+
+    #[test]
+    fn test_rest() {
+        let mut context = test_context();
+        let player = context.state.world.player().unwrap();
+        context.state.world.ecs_mut().healths.map_mut(|h| h.hurt(10), player);
+
+        state::run_action(&mut context, Action::StartActivity(ActivityKind::Rest));
+
+        let world = &context.state.world;
+        let health = world.ecs().healths.get_or_err(player);
+        assert_eq!(health.hit_points, health.max_hit_points);
+        assert!(!in_progress(world, player));
+    }
+
+    #[test]
+    fn test_read() {
+        let mut context = test_context();
+        let player = context.state.world.player().unwrap();
+        let potion = context.state.world.create(ecs::prefab::item_from_data("potion"), POINT_ZERO);
+        context.state.world.place_entity_in(player, potion);
+        context.state.world.ecs_mut().healths.map_mut(|h| h.hurt(100), player);
+
+        state::run_action(&mut context, Action::StartActivity(ActivityKind::Read(potion)));
+
+        let world = &context.state.world;
+        let health = world.ecs().healths.get_or_err(player);
+        assert_eq!(health.hit_points, health.max_hit_points);
+        assert!(!world.ecs().contains(potion));
+        assert!(!in_progress(world, player));
+    }
+
+    #[test]
+    fn test_travel() {
+        let mut context = test_context();
+        let path = vec![Point::new(1, 0), Point::new(2, 0), Point::new(3, 1)];
+
+        state::run_action(&mut context, Action::StartActivity(ActivityKind::Travel(path)));
+
+        let world = &context.state.world;
+        let player = world.player().unwrap();
+        assert_eq!(world.position(player), Some(Point::new(3, 1)));
+    }
+
+    #[test]
+    fn test_interrupt_hurt() {
+        let mut context = test_context();
+        let player = context.state.world.player().unwrap();
+
+        start(&mut context.state.world, player, ActivityKind::Rest);
+        context.state.world.ecs_mut().healths.map_mut(|h| h.hurt(10), player);
+
+        assert!(next_action(&mut context.state.world, player).is_none());
+        assert!(!in_progress(&context.state.world, player));
+    }
+}
@@ -1,15 +1,22 @@
 use std::fmt::Display;
 
+use calx_ecs::Entity;
+
 use GameContext;
 use data::Walkability;
 use engine::keys::{Key, KeyCode};
 use ecs::traits::*;
-use graphics::cell::{CellFeature, StairDest, StairDir};
+use graphics::cell::{CellFeature, DoorState, StairDest, StairDir};
+use item::EffectTarget;
 use logic::Action;
+use logic::action;
+use logic::activity::{self, ActivityKind};
 use logic::entity::EntityQuery;
-use point::{Direction, Point};
+use logic::projectile;
+use point::{Direction, Path, Point};
+use world::registry::StairConnection;
 use world::traits::*;
-use world::{self, World};
+use world::{self, MapId, World};
 
 use super::debug_command::*;
 
@@ -27,9 +34,19 @@ pub enum Command {
     Move(Direction),
     UseStairs(StairDir),
     Look,
+    CloseDoor,
+    LockDoor,
     Pickup,
     Drop,
     Inventory,
+    Shoot,
+    Throw,
+    Rest,
+    Travel,
+    Dig,
+    Read,
+    Use,
+    Shout,
     Wait,
     Quit,
 
@@ -66,9 +83,19 @@ impl From<Key> for Command {
             Key { code: KeyCode::Comma, .. } => Command::UseStairs(StairDir::Descending),
 
             Key { code: KeyCode::M, .. } => Command::Look,
+            Key { code: KeyCode::C, .. } => Command::CloseDoor,
+            Key { code: KeyCode::O, .. } => Command::LockDoor,
             Key { code: KeyCode::G, .. } => Command::Pickup,
             Key { code: KeyCode::D, .. } => Command::Drop,
             Key { code: KeyCode::I, .. } => Command::Inventory,
+            Key { code: KeyCode::F, .. } => Command::Shoot,
+            Key { code: KeyCode::T, .. } => Command::Throw,
+            Key { code: KeyCode::Z, .. } => Command::Rest,
+            Key { code: KeyCode::X, .. } => Command::Travel,
+            Key { code: KeyCode::P, .. } => Command::Dig,
+            Key { code: KeyCode::R, .. } => Command::Read,
+            Key { code: KeyCode::Q, .. } => Command::Use,
+            Key { code: KeyCode::S, .. } => Command::Shout,
 
             Key { code: KeyCode::E, .. } => Command::Teleport,
             Key { code: KeyCode::F1, .. } => Command::DebugMenu,
@@ -84,10 +111,20 @@ pub fn process_player_command(context: &mut GameContext, command: Command) -> Co
         Command::Quit => Err(CommandError::Invalid("Can't quit.")),
 
         Command::Look => cmd_look(context),
+        Command::CloseDoor => cmd_close_door(context),
+        Command::LockDoor => cmd_lock_door(context),
         Command::UseStairs(dir) => cmd_use_stairs(context, dir),
         Command::Pickup => cmd_pickup(context),
         Command::Drop => cmd_drop(context),
         Command::Inventory => cmd_inventory(context),
+        Command::Shoot => cmd_shoot(context),
+        Command::Throw => cmd_throw(context),
+        Command::Rest => cmd_rest(context),
+        Command::Travel => cmd_travel(context),
+        Command::Dig => cmd_dig(context),
+        Command::Read => cmd_read(context),
+        Command::Use => cmd_use(context),
+        Command::Shout => cmd_shout(context),
 
         Command::Move(dir) => cmd_player_move(context, dir),
         Command::Wait => cmd_add_action(context, Action::Wait),
@@ -114,9 +151,68 @@ fn cmd_player_move(context: &mut GameContext, dir: Direction) -> CommandResult<(
         return Ok(());
     }
 
+    match context.state.world.door_at(&new_pos) {
+        Some(DoorState::Closed) => return cmd_add_action(context, Action::OpenDoor(dir)),
+        Some(DoorState::Locked) => {
+            let player = context.state.world.player().ok_or(CommandError::Bug(
+                "No player in the world!",
+            ))?;
+            if !action::has_key(&context.state.world, player) {
+                return Err(CommandError::Invalid("The door is locked."));
+            }
+            return cmd_add_action(context, Action::UnlockDoor(dir));
+        },
+        _ => (),
+    }
+
     cmd_add_action(context, Action::MoveOrAttack(dir))
 }
 
+/// Finds the direction of a door next to the player in the given state,
+/// asking which one if there are several.
+fn pick_door(context: &mut GameContext,
+             state: DoorState,
+             none_nearby: &'static str,
+             prompt: &str) -> CommandResult<Direction> {
+    let position = player_pos(context)?;
+    let doors: Vec<Direction> = Direction::iter8()
+        .cloned()
+        .filter(|&dir| {
+            context.state.world.door_at(&(position + dir)) == Some(state)
+        })
+        .collect();
+
+    match doors.len() {
+        0 => Err(CommandError::Invalid(none_nearby)),
+        1 => Ok(doors[0]),
+        _ => {
+            mes!(context.state.world, prompt);
+            let pos = select_tile(context, |_, _| ())?;
+            Direction::from_neighbors(position, pos)
+                .ok_or(CommandError::Invalid("That's too far away."))
+        },
+    }
+}
+
+fn cmd_close_door(context: &mut GameContext) -> CommandResult<()> {
+    let dir = pick_door(context, DoorState::Open, "There's no open door nearby.",
+                        "Close which door?")?;
+    cmd_add_action(context, Action::CloseDoor(dir))
+}
+
+fn cmd_lock_door(context: &mut GameContext) -> CommandResult<()> {
+    let player = context.state.world.player().ok_or(CommandError::Bug(
+        "No player in the world!",
+    ))?;
+    if !action::has_key(&context.state.world, player) {
+        return Err(CommandError::Invalid("You have no key."));
+    }
+
+    let dir = pick_door(context, DoorState::Closed, "There's no closed door nearby.",
+                        "Lock which door?")?;
+    cmd_add_action(context, Action::LockDoor(dir))
+}
+
 fn cmd_add_action(context: &mut GameContext, action: Action) -> CommandResult<()> {
     context.state.add_action(action);
     Ok(())
@@ -160,9 +256,185 @@ fn cmd_drop(context: &mut GameContext) -> CommandResult<()> {
         "No player in the world!",
     ))?;
     let items = context.state.world.entities_in(player);
+    let names = items.iter().map(|&i| item_name_with_count(&context.state.world, i)).collect();
+    let idx = menu_choice(context, names).ok_or(CommandError::Cancel)?;
+    let item = items[idx];
+
+    let stack_count = context.state.world.ecs().items.map_or(1, |i| i.count, item);
+    let count = if stack_count > 1 {
+        let input = player_input(context, "How many?").ok_or(CommandError::Cancel)?;
+        let count = input.parse::<u32>()
+                         .map_err(|_| CommandError::Invalid("That's not a number."))?;
+        if count == 0 || count > stack_count {
+            return Err(CommandError::Invalid("You don't have that many."));
+        }
+        count
+    } else {
+        1
+    };
+
+    cmd_add_action(context, Action::Drop(item, count))
+}
+
+fn item_name_with_count(world: &World, item: Entity) -> String {
+    let name = item.name(world);
+    match world.ecs().items.map_or(1, |i| i.count, item) {
+        1 => name,
+        count => format!("{} (x{})", name, count),
+    }
+}
+
+fn cmd_shoot(context: &mut GameContext) -> CommandResult<()> {
+    let player = context.state.world.player().ok_or(CommandError::Bug(
+        "No player in the world!",
+    ))?;
+    if !projectile::can_shoot(&context.state.world, player) {
+        return Err(CommandError::Invalid("You have nothing to shoot with."));
+    }
+
+    mes!(context.state.world, "Shoot at what?");
+    let pos = select_tile(context, |_, _| ())?;
+
+    if pos == player_pos(context)? {
+        return Err(CommandError::Invalid("You can't shoot yourself."));
+    }
+
+    cmd_add_action(context, Action::Shoot(pos))
+}
+
+fn cmd_throw(context: &mut GameContext) -> CommandResult<()> {
+    let player = context.state.world.player().ok_or(CommandError::Bug(
+        "No player in the world!",
+    ))?;
+    let items = context.state.world.entities_in(player);
+    if items.is_empty() {
+        return Err(CommandError::Invalid("You have nothing to throw."));
+    }
+
     let names = items.iter().map(|i| i.name(&context.state.world)).collect();
     let idx = menu_choice(context, names).ok_or(CommandError::Cancel)?;
-    cmd_add_action(context, Action::Drop(items[idx]))
+
+    mes!(context.state.world, "Throw where?");
+    let pos = select_tile(context, |_, _| ())?;
+
+    if pos == player_pos(context)? {
+        return Err(CommandError::Invalid("You can't throw that at yourself."));
+    }
+
+    cmd_add_action(context, Action::Throw(items[idx], pos))
+}
+
+fn check_no_hostiles(context: &GameContext) -> CommandResult<()> {
+    let player = context.state.world.player().ok_or(CommandError::Bug(
+        "No player in the world!",
+    ))?;
+
+    if activity::hostile_in_view(&context.state.world, player) {
+        return Err(CommandError::Invalid("You can't do that with enemies nearby."));
+    }
+
+    Ok(())
+}
+
+fn cmd_rest(context: &mut GameContext) -> CommandResult<()> {
+    check_no_hostiles(context)?;
+    cmd_add_action(context, Action::StartActivity(ActivityKind::Rest))
+}
+
+fn cmd_travel(context: &mut GameContext) -> CommandResult<()> {
+    check_no_hostiles(context)?;
+
+    mes!(context.state.world, "Travel where?");
+    let pos = select_tile(context, |_, _| ())?;
+    let start = player_pos(context)?;
+
+    if pos == start {
+        return Err(CommandError::Cancel);
+    }
+
+    let path: Vec<Point> = Path::find(start, pos, &context.state.world, Walkability::MonstersBlocking)
+        .collect();
+
+    if path.is_empty() {
+        return Err(CommandError::Invalid("You can't find a way there."));
+    }
+
+    cmd_add_action(context, Action::StartActivity(ActivityKind::Travel(path)))
+}
+
+fn cmd_dig(context: &mut GameContext) -> CommandResult<()> {
+    mes!(context.state.world, "Dig where?");
+    let pos = select_tile(context, |_, _| ())?;
+
+    if !pos.is_next_to(player_pos(context)?) {
+        return Err(CommandError::Invalid("You can only dig next to yourself."));
+    }
+
+    if !context.state.world.cell_const(&pos).map_or(false, |c| c.can_dig()) {
+        return Err(CommandError::Invalid("There's nothing to dig there."));
+    }
+
+    cmd_add_action(context, Action::StartActivity(ActivityKind::Dig(pos)))
+}
+
+fn cmd_read(context: &mut GameContext) -> CommandResult<()> {
+    let player = context.state.world.player().ok_or(CommandError::Bug(
+        "No player in the world!",
+    ))?;
+    let items: Vec<Entity> = context.state.world.entities_in(player)
+        .into_iter()
+        .filter(|&i| context.state.world.ecs().items.map_or(false, |item| item.is_usable(), i))
+        .collect();
+    if items.is_empty() {
+        return Err(CommandError::Invalid("You have nothing to read."));
+    }
+
+    let names = items.iter().map(|i| i.name(&context.state.world)).collect();
+    let idx = menu_choice(context, names).ok_or(CommandError::Cancel)?;
+    cmd_add_action(context, Action::StartActivity(ActivityKind::Read(items[idx])))
+}
+
+fn cmd_use(context: &mut GameContext) -> CommandResult<()> {
+    let player = context.state.world.player().ok_or(CommandError::Bug(
+        "No player in the world!",
+    ))?;
+    let items: Vec<Entity> = context.state.world.entities_in(player)
+        .into_iter()
+        .filter(|&i| context.state.world.ecs().items.map_or(false, |item| item.is_usable(), i))
+        .collect();
+    if items.is_empty() {
+        return Err(CommandError::Invalid("You have nothing to use."));
+    }
+
+    let names = items.iter().map(|i| i.name(&context.state.world)).collect();
+    let idx = menu_choice(context, names).ok_or(CommandError::Cancel)?;
+    let item = items[idx];
+
+    let needs_target = context.state.world.ecs().items.get_or_err(item)
+        .effects.iter()
+        .any(|e| e.target == EffectTarget::Selected);
+
+    if needs_target {
+        mes!(context.state.world, "Use it on what?");
+        let pos = select_tile(context, |_, _| ())?;
+        let player_pos = context.state.world.position(player).ok_or(CommandError::Bug(
+            "Player has no position!",
+        ))?;
+        if !projectile::in_line_of_sight(&context.state.world, player_pos, pos) {
+            return Err(CommandError::Invalid("You can't see that from here."));
+        }
+        cmd_add_action(context, Action::UseAt(item, pos))
+    } else {
+        cmd_add_action(context, Action::Use(item))
+    }
+}
+
+fn cmd_shout(context: &mut GameContext) -> CommandResult<()> {
+    let text = player_input(context, "Shout what?").ok_or(CommandError::Cancel)?;
+    if text.is_empty() {
+        return Err(CommandError::Cancel);
+    }
+    cmd_add_action(context, Action::Shout(text))
 }
 
 fn cmd_inventory(context: &mut GameContext) -> CommandResult<()> {
@@ -215,7 +487,7 @@ fn cmd_use_stairs(context: &mut GameContext, dir: StairDir) -> CommandResult<()>
     let world = &mut context.state.world;
     let next = find_stair_dest(world, pos, dir)?;
 
-    let (true_next, dest) = load_stair_dest(world, pos, next)?;
+    let (true_next, dest) = load_stair_dest(world, pos, dir, next)?;
     world.move_to_map(true_next, dest).unwrap();
 
     debug!(world.logger, "map id: {:?}", world.map_id());
@@ -225,15 +497,20 @@ fn cmd_use_stairs(context: &mut GameContext, dir: StairDir) -> CommandResult<()>
 fn load_stair_dest(
     world: &mut World,
     stair_pos: Point,
+    stair_dir: StairDir,
     next: StairDest,
 ) -> CommandResult<(World, Point)> {
     match next {
         StairDest::Generated(map_id, dest) => {
             debug!(world.logger, "Found stair leading to: {:?}", map_id);
-            let world = world::serial::load_world(map_id).map_err(|_| {
+            let new_world = world::serial::load_world(map_id).map_err(|_| {
                 CommandError::Bug("Failed to load already generated world!")
             })?;
-            Ok((world, dest))
+
+            // Stairs from before the map registry haven't been recorded yet.
+            connect_stairs(world, stair_pos, stair_dir, map_id, dest);
+
+            Ok((new_world, dest))
         },
         StairDest::Ungenerated => {
             debug!(world.logger, "Failed to load map, generating...");
@@ -252,35 +529,57 @@ fn load_stair_dest(
 }
 
 fn generate_stair_dest(world: &mut World, stair_pos: Point) -> CommandResult<(World, Point)> {
+    let stair_dir = match world.cell_const(&stair_pos).and_then(|c| c.feature) {
+        Some(CellFeature::Stairs(stair_dir, StairDest::Ungenerated)) => stair_dir,
+        _ => return Err(CommandError::Bug("Stairs should have already been found by now...")),
+    };
+
+    let prev_id = world.flags().map_id;
+    let prev_depth = world.flags().globals.maps.get(prev_id).map_or(0, |m| m.depth);
+    let depth = match stair_dir {
+        StairDir::Descending => prev_depth + 1,
+        StairDir::Ascending => prev_depth - 1,
+    };
+
     let mut new_world = World::new()
         .from_other_world(world)
         .with_prefab("rogue")
         .with_prefab_args(prefab_args!{ width: 100, height: 50, })
+        .with_name(&format!("Dungeon level {}", depth))
+        .with_depth(depth)
         .build()
         .map_err(|_| CommandError::Bug("Failed to generate stair!"))?;
 
-    let prev_id = world.flags().map_id;
     let dest_id = new_world.flags().map_id;
+    let new_stair_pos = new_world.find_stairs_in().ok_or(CommandError::Bug(
+        "Generated world has no stairs!",
+    ))?;
 
-    let mut stairs_mut = world.cell_mut(&stair_pos).unwrap();
+    if let Some(cell_mut) = world.cell_mut(&stair_pos) {
+        let dest = StairDest::Generated(dest_id, new_stair_pos);
+        cell_mut.feature = Some(CellFeature::Stairs(stair_dir, dest));
+    }
 
-    if let Some(CellFeature::Stairs(stair_dir, ref mut dest @ StairDest::Ungenerated)) =
-        stairs_mut.feature
-    {
-        let new_stair_pos = new_world.find_stairs_in().ok_or(CommandError::Bug(
-            "Generated world has no stairs!",
-        ))?;
+    new_world.place_stairs(stair_dir.reverse(), new_stair_pos, prev_id, stair_pos);
 
-        *dest = StairDest::Generated(dest_id, new_stair_pos);
+    connect_stairs(world, stair_pos, stair_dir, dest_id, new_stair_pos);
 
-        new_world.place_stairs(stair_dir.reverse(), new_stair_pos, prev_id, stair_pos);
+    Ok((new_world, new_stair_pos))
+}
 
-        Ok((new_world, new_stair_pos))
-    } else {
-        Err(CommandError::Bug(
-            "Stairs should have already been found by now...",
-        ))
-    }
+fn connect_stairs(world: &mut World,
+                  stair_pos: Point,
+                  stair_dir: StairDir,
+                  to: MapId,
+                  dest: Point) {
+    let from = world.map_id();
+    world.flags_mut().globals.maps.connect(StairConnection {
+        from: from,
+        pos: stair_pos,
+        dir: stair_dir,
+        to: to,
+        dest: dest,
+    });
 }
 
 use glium::glutin::{VirtualKeyCode, ElementState};
@@ -288,6 +587,7 @@ use glium::glutin;
 use graphics::Color;
 use point::LineIter;
 use renderer;
+use replay::{self, Prompt};
 
 fn maybe_examine_tile(pos: Point, world: &mut World) {
     if let Some(mob) = world.mob_at(pos) {
@@ -317,6 +617,21 @@ fn draw_line(start: Point, end: Point, world: &mut World) {
 
 /// Allow the player to choose a tile.
 pub fn select_tile<F>(context: &mut GameContext, callback: F) -> CommandResult<Point>
+where
+    F: Fn(Point, &mut World),
+{
+    let answer = replay::answer(context, |context| ask_tile(context, callback));
+
+    match answer {
+        Prompt::Tile(pos) => {
+            context.state.world.flags_mut().camera = pos;
+            Ok(pos)
+        },
+        _ => Err(CommandError::Cancel),
+    }
+}
+
+fn ask_tile<F>(context: &mut GameContext, callback: F) -> Prompt
 where
     F: Fn(Point, &mut World),
 {
@@ -370,20 +685,28 @@ where
     context.state.world.marks.clear();
 
     if selected {
-        Ok(result)
+        Prompt::Tile(result)
     } else {
-        Err(CommandError::Cancel)
+        Prompt::Cancel
     }
 }
 
 use renderer::ui::layers::ChoiceLayer;
 
 pub fn menu_choice(context: &mut GameContext, choices: Vec<String>) -> Option<usize> {
-    renderer::with_mut(|rc| {
-        rc.update(context);
+    let answer = replay::answer(context, |context| {
+        let choice = renderer::with_mut(|rc| {
+            rc.update(context);
 
-        rc.query(&mut ChoiceLayer::new(choices))
-    })
+            rc.query(&mut ChoiceLayer::new(choices))
+        });
+        choice.map_or(Prompt::Cancel, Prompt::Choice)
+    });
+
+    match answer {
+        Prompt::Choice(idx) => Some(idx),
+        _ => None,
+    }
 }
 
 pub fn menu_choice_indexed<T: Display + Clone>(
@@ -398,9 +721,17 @@ pub fn menu_choice_indexed<T: Display + Clone>(
 use renderer::ui::layers::InputLayer;
 
 pub fn player_input(context: &mut GameContext, prompt: &str) -> Option<String> {
-    renderer::with_mut(|rc| {
-        rc.update(context);
+    let answer = replay::answer(context, |context| {
+        let input = renderer::with_mut(|rc| {
+            rc.update(context);
 
-        rc.query(&mut InputLayer::new(prompt))
-    })
+            rc.query(&mut InputLayer::new(prompt))
+        });
+        input.map_or(Prompt::Cancel, Prompt::Text)
+    });
+
+    match answer {
+        Prompt::Text(text) => Some(text),
+        _ => None,
+    }
 }
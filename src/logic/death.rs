@@ -0,0 +1,122 @@
+//! What happens when the player dies. In normal games the player comes back at
+//! their home, leaving their belongings behind in their corpse and losing some
+//! of their health. In permadeath games the save is deleted, after writing a
+//! morgue file.
+
+use std::cmp;
+
+use calx_ecs::Entity;
+
+use ecs::prefab;
+use ecs::traits::*;
+use logic::status;
+use morgue::{self, Ending};
+use world::flags::GameMode;
+use world::traits::*;
+use world::World;
+
+/// How much of their maximum health the player loses each time they die.
+pub const HEALTH_PENALTY_PERCENT: i32 = 10;
+
+/// Called as the player is killed, while they still have their items.
+pub fn on_player_killed(world: &mut World, player: Entity) {
+    match world.flags().globals.mode {
+        GameMode::Normal => leave_corpse(world, player),
+        GameMode::Permadeath => {
+            if let Err(e) = morgue::write(world, player, Ending::Died) {
+                warn!(world.logger, "Couldn't write the morgue file: {}", e);
+            }
+        },
+    }
+}
+
+fn leave_corpse(world: &mut World, player: Entity) {
+    let pos = match world.position(player) {
+        Some(pos) => pos,
+        None => return,
+    };
+
+    let items = world.entities_in(player);
+    if items.is_empty() {
+        return;
+    }
+
+    let name = world.ecs().names.map_or("someone".to_string(), |n| n.name.clone(), player);
+    let corpse = world.create(prefab::corpse(&name), pos);
+    for item in items.into_iter() {
+        world.place_entity_in(corpse, item);
+    }
+}
+
+/// Returns true if one of the player's corpses is lying on the map. Corpses are
+/// the only items that hold other items.
+pub fn has_corpse(world: &World) -> bool {
+    world.entities().any(|&e| {
+        world.ecs().items.has(e) && world.ecs().invs.has(e) && world.position(e).is_some()
+    })
+}
+
+/// Returns the dead player, if the player died.
+pub fn dead_player(world: &World) -> Option<Entity> {
+    world.flags().globals.player.and_then(|p| {
+        let dead = world.ecs().contains(p) && !world.is_alive(p);
+        if dead { Some(p) } else { None }
+    })
+}
+
+/// Brings the player back to life with less maximum health and without
+/// whatever was hurting them. They still have to be put somewhere afterwards.
+pub fn revive(world: &mut World, player: Entity) {
+    world.ecs_mut().healths.map_mut(|h| {
+        let lost = h.max_hit_points * HEALTH_PENALTY_PERCENT / 100;
+        h.max_hit_points = cmp::max(h.max_hit_points - lost, 1);
+        h.hit_points = h.max_hit_points;
+    }, player);
+
+    status::clear_harmful(world, player);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use logic::status::{StatusEffect, StatusKind};
+    use point::Point;
+    use testing::*;
+
+    #[test]
+    fn test_corpse() {
+        let mut context = test_context();
+        let world = &mut context.state.world;
+        let player = world.player().unwrap();
+        let pos = world.position(player).unwrap();
+        let item = world.create(prefab::item("cola", "cola"), pos);
+        world.place_entity_in(player, item);
+
+        let max_before = world.ecs().healths.get_or_err(player).max_hit_points;
+        status::add_effect(world, player, StatusEffect::new(StatusKind::Poison(10), 1000));
+        status::add_effect(world, player, StatusEffect::new(StatusKind::Regeneration(10), 1000));
+
+        world.kill(player);
+        world.update_killed();
+        world.purge_dead();
+
+        assert_eq!(dead_player(world), Some(player));
+        let corpse = world.entities_at(pos).into_iter().find(|&e| e != player).unwrap();
+        assert_eq!(world.entities_in(corpse), vec![item]);
+
+        revive(world, player);
+        world.respawn_entity(player, Point::new(1, 1));
+        assert_eq!(world.player(), Some(player));
+        assert_eq!(world.position(player), Some(Point::new(1, 1)));
+        {
+            let health = world.ecs().healths.get_or_err(player);
+            let lost = max_before * HEALTH_PENALTY_PERCENT / 100;
+            assert!(lost > 0);
+            assert_eq!(health.max_hit_points, max_before - lost);
+            assert_eq!(health.hit_points, health.max_hit_points);
+        }
+
+        assert!(!status::has_effect(world, player, |k| k.is_harmful()));
+        assert!(status::has_effect(world, player, |k| *k == StatusKind::Regeneration(10)));
+    }
+}
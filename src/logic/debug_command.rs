@@ -1,5 +1,7 @@
 use GameContext;
+use chunk::generator::ChunkType;
 use ecs;
+use logic::status::{self, StatusEffect, StatusKind};
 use point::{Point, RectangleIter, POINT_ZERO};
 use prefab;
 use renderer;
@@ -16,8 +18,11 @@ pub(super) fn cmd_debug_menu(context: &mut GameContext) -> CommandResult<()> {
           "Item test"      => debug_item_test(context),
           "List entities"  => debug_list_entities(context),
           "Place enemies"  => debug_place_enemies(context),
+          "Apply status"   => debug_apply_status(context),
           "Goto world"     => debug_goto_world(context),
+          "Prune worlds"   => debug_prune_worlds(context),
           "Debug prefab"   => debug_prefab(context),
+          "Biome world"    => debug_biome_world(context),
           "Deploy prefab"  => debug_deploy_prefab(context),
           "Reload shaders" => debug_reload_shaders(),
           "Restart game"   => debug_restart_game(context)
@@ -81,11 +86,31 @@ fn debug_place_enemies(context: &mut GameContext) -> CommandResult<()> {
     Ok(())
 }
 
+fn debug_apply_status(context: &mut GameContext) -> CommandResult<()> {
+    let player = context.state.world.player().ok_or(CommandError::Bug(
+        "No player in the world!",
+    ))?;
+
+    let kinds = vec![
+        StatusKind::Poison(5),
+        StatusKind::Regeneration(5),
+        StatusKind::Speed(50),
+        StatusKind::Speed(-50),
+    ];
+    let names = kinds.iter().map(|k| format!("{:?}", k)).collect();
+    let idx = menu_choice(context, names).ok_or(CommandError::Cancel)?;
+
+    let effect = StatusEffect::new(kinds[idx].clone(), 1000);
+    status::add_effect(&mut context.state.world, player, effect);
+    Ok(())
+}
+
 fn get_debug_world(prefab: &str) -> Result<World, String> {
     World::new()
         .with_prefab(prefab)
         .with_randomized_seed()
         .with_id(TEST_WORLD_ID)
+        .with_name(&format!("Debug world ({})", prefab))
         .build()
 }
 
@@ -96,19 +121,50 @@ fn debug_regen_prefab(context: &mut GameContext, prefab_name: &str) -> CommandRe
     Ok(())
 }
 
+fn debug_biome_world(context: &mut GameContext) -> CommandResult<()> {
+    let world = World::new()
+        .from_other_world(&context.state.world)
+        .with_chunk_type(ChunkType::Biomes)
+        .with_randomized_seed()
+        .with_name("Biome test")
+        .temporary()
+        .build()
+        .map_err(|e| CommandError::Debug(format!("Failed to make world: {}", e)))?;
+    goto_new_world(context, world);
+    Ok(())
+}
+
 fn debug_goto_world(context: &mut GameContext) -> CommandResult<()> {
-    let input = player_input(context, "Which id?").ok_or(CommandError::Cancel)?;
+    let ids: Vec<u32> = {
+        let world = &context.state.world;
+        let current = world.map_id();
+        world.flags().globals.maps.ids().into_iter().filter(|&id| id != current).collect()
+    };
 
-    let id = input.parse::<u32>()
-                  .map_err(|_| CommandError::Invalid("That's not a valid id."))?;
+    if ids.is_empty() {
+        return Err(CommandError::Invalid("There are no other worlds."));
+    }
+
+    let names = {
+        let maps = &context.state.world.flags().globals.maps;
+        ids.iter().map(|&id| maps.describe(id)).collect()
+    };
+    let idx = menu_choice(context, names).ok_or(CommandError::Cancel)?;
 
-    let new_world = world::serial::load_world(id)
+    let new_world = world::serial::load_world(ids[idx])
         .map_err(|_| CommandError::Invalid("That world doesn't exist."))?;
 
     goto_new_world(context, new_world);
     Ok(())
 }
 
+fn debug_prune_worlds(context: &mut GameContext) -> CommandResult<()> {
+    let pruned = world::gc::prune_unreachable(&mut context.state.world)
+        .map_err(|e| CommandError::Debug(format!("Failed to prune worlds: {:?}", e)))?;
+    mes!(context.state.world, "Deleted worlds: {:?}", a = pruned);
+    Ok(())
+}
+
 fn debug_item_test(context: &mut GameContext) -> CommandResult<()> {
     goto_new_world(context, get_debug_world("blank").unwrap());
 
@@ -118,7 +174,15 @@ fn debug_item_test(context: &mut GameContext) -> CommandResult<()> {
         }
     }
 
+    for (i, name) in ["potion", "bomb", "blink_scroll"].iter().enumerate() {
+        let pos = Point::new(i as i32, 4);
+        context.state.world.create(ecs::prefab::item_from_data(name), pos);
+    }
+
+    context.state.world.create(ecs::prefab::ranged_weapon("bow", "cola"), Point::new(3, 4));
+    context.state.world.create(ecs::prefab::key("key", "cola"), Point::new(4, 4));
     context.state.world.create(ecs::prefab::mob("putit", 100, "putit"), Point::new(5, 5));
+    context.state.world.create(ecs::prefab::ranged_mob("putit archer", 50, "putit"), Point::new(8, 8));
 
     Ok(())
 }
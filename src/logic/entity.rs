@@ -6,6 +6,8 @@
 
 use calx_ecs::Entity;
 
+use data::faction::{self, Relation};
+use ecs::traits::*;
 use point::{Point, LineIter};
 use util::grammar::VerbPerson;
 use world::traits::*;
@@ -17,6 +19,8 @@ pub trait EntityQuery {
     fn verb_person(&self, world: &World) -> VerbPerson;
     fn is_dead(&self, world: &World) -> bool;
     fn can_see_other(&self, target: Entity, world: &World) -> bool;
+    fn relation_to(&self, other: Entity, world: &World) -> Relation;
+    fn is_hostile_to(&self, other: Entity, world: &World) -> bool;
 }
 
 impl EntityQuery for Entity {
@@ -72,4 +76,20 @@ impl EntityQuery for Entity {
             false
         }
     }
+
+    fn relation_to(&self, other: Entity, world: &World) -> Relation {
+        if *self == other {
+            return Relation::Friendly;
+        }
+
+        let factions = &world.ecs().factions;
+        match (factions.get(*self), factions.get(other)) {
+            (Some(mine), Some(theirs)) => faction::relation(&mine.name, &theirs.name),
+            _ => Relation::Neutral,
+        }
+    }
+
+    fn is_hostile_to(&self, other: Entity, world: &World) -> bool {
+        world.is_mob(other) && self.relation_to(other, world) == Relation::Hostile
+    }
 }
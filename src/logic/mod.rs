@@ -1,12 +1,18 @@
 mod action;
+pub mod activity;
 pub mod command;
+pub mod death;
 pub mod entity;
 mod debug_command;
+pub mod projectile;
+pub mod status;
 
 pub use self::action::Action;
 pub use self::command::{Command, CommandResult};
 
 use calx_ecs::Entity;
+use ecs::traits::*;
+use item;
 use world::traits::*;
 use world::World;
 
@@ -35,9 +41,25 @@ pub fn run_action(world: &mut World, entity: Entity, action: Action) {
     post_tick(world);
 }
 
+/// Runs an action in response to an event. Unlike `run_action`, things that
+/// aren't alive, like items, can also react.
+pub fn run_reaction(world: &mut World, entity: Entity, action: Action) {
+    if !world.ecs().contains(entity) || !world.is_active(entity) {
+        return;
+    }
+
+    action::run_entity_action(world, entity, action);
+    post_tick_entity(world, entity);
+}
+
 fn post_tick_entity(world: &mut World, entity: Entity) {
     world.update_killed();
 
+    if world.ecs().invs.has(entity) {
+        let weight = item::contents_weight(world, entity);
+        world.ecs_mut().invs.map_mut(|i| i.container.cached_weight = weight, entity);
+    }
+
     if world.is_alive(entity) {
         world.after_entity_moved(entity);
     }
@@ -0,0 +1,147 @@
+//! Tracing of projectiles (shots, thrown items) across the map.
+
+use std::iter;
+
+use calx_ecs::Entity;
+
+use data::Walkability;
+use point::{LineIter, Point};
+use stats::properties::Prop;
+use world::traits::*;
+use world::World;
+
+/// The farthest a projectile can travel, in tiles.
+pub const MAX_RANGE: i32 = 10;
+
+/// Where a projectile ended up after being fired.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProjectileHit {
+    /// The first mob in the projectile's path, if any.
+    pub entity: Option<Entity>,
+
+    /// The last tile the projectile could rest on. Thrown items are dropped
+    /// here.
+    pub landed_at: Point,
+}
+
+/// Traces the path of a projectile from `from` towards `to`. The projectile
+/// stops at the first mob it encounters, before the first tile that blocks
+/// light, or once it exceeds `MAX_RANGE`.
+pub fn trace(world: &World, from: Point, to: Point) -> ProjectileHit {
+    let mut landed_at = from;
+
+    // LineIter doesn't include the end point.
+    let line = LineIter::new(from, to).chain(iter::once(to)).skip(1);
+
+    for pos in line {
+        if from.tile_distance(pos) > MAX_RANGE {
+            break;
+        }
+
+        if !world.light_passes_through(&pos) {
+            break;
+        }
+
+        if let Some(mob) = world.mob_at(pos) {
+            return ProjectileHit {
+                entity: Some(mob),
+                landed_at: pos,
+            };
+        }
+
+        if world.can_walk(pos, Walkability::MonstersWalkable) {
+            landed_at = pos;
+        }
+    }
+
+    ProjectileHit {
+        entity: None,
+        landed_at: landed_at,
+    }
+}
+
+/// Returns true if a projectile fired from `from` would hit `target` first.
+pub fn can_hit(world: &World, from: Point, target: Entity) -> bool {
+    match world.position(target) {
+        Some(pos) => trace(world, from, pos).entity == Some(target),
+        None => false,
+    }
+}
+
+/// Returns true if `to` is close enough to `from` to be targeted, and nothing
+/// in between blocks light. Mobs in the way don't matter.
+pub fn in_line_of_sight(world: &World, from: Point, to: Point) -> bool {
+    if from.tile_distance(to) > MAX_RANGE {
+        return false;
+    }
+
+    // LineIter doesn't include the end point, which can be a wall.
+    LineIter::new(from, to).skip(1).all(|pos| world.light_passes_through(&pos))
+}
+
+fn is_ranged(world: &World, entity: Entity) -> bool {
+    world.ecs().props.map_or(false, |p| p.props.check_bool(Prop::Ranged), entity)
+}
+
+/// Returns true if an entity has something to shoot with. Ranged monsters can
+/// always shoot, everything else needs a ranged weapon on them.
+pub fn can_shoot(world: &World, entity: Entity) -> bool {
+    is_ranged(world, entity) ||
+        world.entities_in(entity).into_iter().any(|item| is_ranged(world, item))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ecs;
+    use testing::*;
+    use world::traits::*;
+
+    #[test]
+    fn test_trace_hits_mob() {
+        let mut context = test_context_bounded(32, 32);
+        let world = &mut context.state.world;
+        let mob = place_mob(world, Point::new(5, 0));
+
+        let hit = trace(world, Point::new(0, 0), Point::new(8, 0));
+        assert_eq!(hit.entity, Some(mob));
+        assert_eq!(hit.landed_at, Point::new(5, 0));
+    }
+
+    #[test]
+    fn test_trace_stops_at_wall() {
+        let mut context = test_context_bounded(32, 32);
+        let world = &mut context.state.world;
+        world.cell_mut(&Point::new(4, 0)).unwrap().set("wall");
+        place_mob(world, Point::new(5, 0));
+
+        let hit = trace(world, Point::new(0, 0), Point::new(8, 0));
+        assert_eq!(hit.entity, None);
+        assert_eq!(hit.landed_at, Point::new(3, 0));
+    }
+
+    #[test]
+    fn test_trace_max_range() {
+        let context = test_context_bounded(32, 32);
+        let world = &context.state.world;
+
+        let hit = trace(world, Point::new(0, 0), Point::new(20, 0));
+        assert_eq!(hit.entity, None);
+        assert_eq!(hit.landed_at, Point::new(MAX_RANGE, 0));
+    }
+
+    #[test]
+    fn test_can_shoot() {
+        let mut context = test_context_bounded(32, 32);
+        let world = &mut context.state.world;
+        let player = world.player().unwrap();
+        assert!(!can_shoot(world, player));
+
+        let archer = world.create(ecs::prefab::ranged_mob("archer", 50, "putit"), Point::new(3, 3));
+        assert!(can_shoot(world, archer));
+
+        let bow = world.create(ecs::prefab::ranged_weapon("bow", "cola"), Point::new(1, 1));
+        world.place_entity_in(player, bow);
+        assert!(can_shoot(world, player));
+    }
+}
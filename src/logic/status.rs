@@ -0,0 +1,303 @@
+//! Status effects and other things that happen after a set period of time.
+//! Durations are measured in the same ticks as the turn order, so an effect
+//! lasting 100 ticks lasts one turn of an entity with normal speed.
+
+use std::cmp;
+use std::mem;
+
+use calx_ecs::Entity;
+
+use ecs::components::Effects;
+use ecs::traits::*;
+use logic::action;
+use world::traits::*;
+use world::World;
+
+/// Number of ticks between applications of periodic effects, like poison.
+pub const DEFAULT_INTERVAL: i32 = 100;
+
+/// Turns can't be made infinitely long by slowing an entity down.
+const MIN_SPEED: i32 = 10;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum StatusKind {
+    /// Damage taken every interval.
+    Poison(u32),
+    /// Health recovered every interval.
+    Regeneration(u32),
+    /// Change in speed for the duration of the effect. Positive values hasten,
+    /// negative ones slow.
+    Speed(i32),
+    /// Damages every mob within the radius once the effect expires, then
+    /// destroys the entity.
+    Explode(i32, u32),
+}
+
+impl StatusKind {
+    /// Returns true if the effect is bad for whoever has it.
+    pub fn is_harmful(&self) -> bool {
+        match *self {
+            StatusKind::Poison(..) | StatusKind::Explode(..) => true,
+            StatusKind::Speed(amount) => amount < 0,
+            StatusKind::Regeneration(..) => false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StatusEffect {
+    pub kind: StatusKind,
+
+    /// Ticks until the effect expires.
+    pub remaining: i32,
+
+    interval: i32,
+    until_next: i32,
+
+    // The change in speed that was actually applied, so it can be undone.
+    applied: i32,
+}
+
+impl StatusEffect {
+    pub fn new(kind: StatusKind, duration: i32) -> Self {
+        StatusEffect {
+            kind: kind,
+            remaining: duration,
+            interval: DEFAULT_INTERVAL,
+            until_next: DEFAULT_INTERVAL,
+            applied: 0,
+        }
+    }
+
+    pub fn with_interval(mut self, interval: i32) -> Self {
+        assert!(interval > 0);
+        self.interval = interval;
+        self.until_next = interval;
+        self
+    }
+
+    fn is_periodic(&self) -> bool {
+        match self.kind {
+            StatusKind::Poison(..) |
+            StatusKind::Regeneration(..) => true,
+            _ => false,
+        }
+    }
+}
+
+/// Adds a status effect to an entity, applying any immediate changes.
+pub fn add_effect(world: &mut World, entity: Entity, mut effect: StatusEffect) {
+    on_start(world, entity, &mut effect);
+
+    if !world.ecs().effects.has(entity) {
+        world.ecs_mut().effects.insert(entity, Effects::new());
+    }
+
+    world.ecs_mut().effects.map_mut(|e| e.effects.push(effect), entity);
+}
+
+/// Returns true if the entity is currently under the given kind of effect.
+pub fn has_effect<F>(world: &World, entity: Entity, predicate: F) -> bool
+    where F: Fn(&StatusKind) -> bool
+{
+    world.ecs().effects.map_or(false, |e| e.effects.iter().any(|s| predicate(&s.kind)), entity)
+}
+
+/// Removes every harmful effect from an entity without letting them run their
+/// course, so nothing explodes.
+pub fn clear_harmful(world: &mut World, entity: Entity) {
+    let effects = match world.ecs_mut().effects.get_mut(entity) {
+        Some(e) => mem::replace(&mut e.effects, Vec::new()),
+        None => return,
+    };
+
+    let (harmful, kept): (Vec<StatusEffect>, Vec<StatusEffect>) =
+        effects.into_iter().partition(|e| e.kind.is_harmful());
+
+    for effect in harmful.iter() {
+        if let StatusKind::Speed(..) = effect.kind {
+            undo_speed(world, entity, effect);
+        }
+    }
+
+    world.ecs_mut().effects.map_mut(|e| e.effects = kept, entity);
+}
+
+/// Advances all status effects on active entities by the given number of
+/// ticks.
+pub fn update(world: &mut World, ticks: i32) {
+    let affected: Vec<Entity> = world.entities()
+        .filter(|&&e| world.is_active(e) && world.ecs().effects.has(e))
+        .cloned().collect();
+
+    for entity in affected {
+        let effects = match world.ecs_mut().effects.get_mut(entity) {
+            Some(e) => mem::replace(&mut e.effects, Vec::new()),
+            None => continue,
+        };
+
+        let mut kept = Vec::new();
+
+        for mut effect in effects.into_iter() {
+            if effect.is_periodic() {
+                effect.until_next -= cmp::min(ticks, effect.remaining);
+                while effect.until_next <= 0 {
+                    on_interval(world, entity, &effect);
+                    effect.until_next += effect.interval;
+                }
+            }
+
+            effect.remaining -= ticks;
+
+            if effect.remaining <= 0 {
+                on_end(world, entity, &effect);
+            } else {
+                kept.push(effect);
+            }
+        }
+
+        if world.ecs().contains(entity) {
+            world.ecs_mut().effects.map_mut(|e| e.effects.extend(kept), entity);
+        }
+    }
+
+    world.update_killed();
+}
+
+fn on_start(world: &mut World, entity: Entity, effect: &mut StatusEffect) {
+    match effect.kind {
+        StatusKind::Speed(amount) => {
+            effect.applied = world.ecs_mut().turns.map_mut(|t| {
+                let old = t.speed as i32;
+                let new = cmp::max(MIN_SPEED, old + amount);
+                t.speed = new as u32;
+                new - old
+            }, entity).unwrap_or(0);
+
+            if amount > 0 {
+                format_mes!(world, entity, "%U <speed> up.");
+            } else {
+                format_mes!(world, entity, "%U <slow> down.");
+            }
+        },
+        StatusKind::Poison(..) => {
+            format_mes!(world, entity, "%U <be> poisoned!");
+        },
+        _ => (),
+    }
+}
+
+fn on_interval(world: &mut World, entity: Entity, effect: &StatusEffect) {
+    match effect.kind {
+        StatusKind::Poison(damage) => {
+            world.ecs_mut().healths.map_mut(|h| h.hurt(damage), entity);
+            if world.is_player(entity) {
+                mes!(world, "You feel sick. ({})", a = damage);
+            }
+        },
+        StatusKind::Regeneration(amount) => {
+            world.ecs_mut().healths.map_mut(|h| h.heal(amount), entity);
+        },
+        _ => (),
+    }
+}
+
+fn undo_speed(world: &mut World, entity: Entity, effect: &StatusEffect) {
+    world.ecs_mut().turns.map_mut(|t| {
+        t.speed = cmp::max(MIN_SPEED, t.speed as i32 - effect.applied) as u32
+    }, entity);
+}
+
+fn on_end(world: &mut World, entity: Entity, effect: &StatusEffect) {
+    match effect.kind {
+        StatusKind::Speed(..) => {
+            undo_speed(world, entity, effect);
+            format_mes!(world, entity, "%U <return> to normal speed.");
+        },
+        StatusKind::Poison(..) => {
+            if world.is_player(entity) {
+                mes!(world, "You feel better.");
+            }
+        },
+        StatusKind::Explode(radius, damage) => {
+            // Whoever hurt the thing last gets the credit for what it takes
+            // down with it.
+            let cause = world.ecs().healths.map_or(None, |h| h.last_attacker, entity);
+            action::explode(world, entity, radius, damage, cause);
+        },
+        _ => (),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ecs;
+    use event;
+    use point::Point;
+    use testing::*;
+
+    #[test]
+    fn test_poison() {
+        let mut context = test_context();
+        let world = &mut context.state.world;
+        let mob = place_mob(world, Point::new(1, 1));
+
+        add_effect(world, mob, StatusEffect::new(StatusKind::Poison(10), 300));
+
+        update(world, 100);
+        assert_eq!(world.ecs().healths.get_or_err(mob).hit_points, 90);
+
+        update(world, 250);
+        assert_eq!(world.ecs().healths.get_or_err(mob).hit_points, 70);
+        assert!(!has_effect(world, mob, |k| *k == StatusKind::Poison(10)));
+    }
+
+    #[test]
+    fn test_speed() {
+        let mut context = test_context();
+        let world = &mut context.state.world;
+        let mob = place_mob(world, Point::new(1, 1));
+
+        add_effect(world, mob, StatusEffect::new(StatusKind::Speed(50), 200));
+        assert_eq!(world.ecs().turns.get_or_err(mob).speed, 150);
+
+        update(world, 200);
+        assert_eq!(world.ecs().turns.get_or_err(mob).speed, 100);
+    }
+
+    #[test]
+    fn test_speed_min() {
+        let mut context = test_context();
+        let world = &mut context.state.world;
+        let mob = place_mob(world, Point::new(1, 1));
+
+        add_effect(world, mob, StatusEffect::new(StatusKind::Speed(-200), 200));
+        assert_eq!(world.ecs().turns.get_or_err(mob).speed, MIN_SPEED as u32);
+
+        add_effect(world, mob, StatusEffect::new(StatusKind::Speed(50), 100));
+        world.ecs_mut().turns.map_mut(|t| t.speed = 20, mob);
+
+        update(world, 100);
+        assert_eq!(world.ecs().turns.get_or_err(mob).speed, MIN_SPEED as u32);
+    }
+
+    #[test]
+    fn test_explode() {
+        let mut context = test_context();
+        let world = &mut context.state.world;
+        let mob = place_mob(world, Point::new(2, 2));
+        let bomb = world.create(ecs::prefab::item("bomb", "cola"), Point::new(1, 1));
+
+        add_effect(world, bomb, StatusEffect::new(StatusKind::Explode(2, 1000), 100));
+
+        update(world, 50);
+        assert!(world.ecs().contains(bomb));
+
+        update(world, 50);
+        assert!(!world.ecs().contains(bomb));
+
+        event::process_events(world);
+        assert!(!world.is_alive(mob));
+    }
+}
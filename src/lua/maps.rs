@@ -0,0 +1,68 @@
+//! Read-only access to the map registry from Lua, under the `maps` namespace.
+//! Prefabs are generated before the world they're placed in exists, so the
+//! scripts see a copy of the registry that is updated whenever a map is built
+//! or entered.
+
+use std::cell::RefCell;
+
+use hlua::{self, Lua};
+
+use world::MapId;
+use world::registry::MapRegistry;
+
+thread_local! {
+    static MAPS: RefCell<(MapRegistry, MapId)> = RefCell::new((MapRegistry::new(), 0));
+}
+
+pub fn set_maps(maps: &MapRegistry, current: MapId) {
+    MAPS.with(|m| *m.borrow_mut() = (maps.clone(), current));
+}
+
+fn with_maps<A, F>(f: F) -> A
+    where F: FnOnce(&MapRegistry, MapId) -> A {
+    MAPS.with(|m| {
+        let m = m.borrow();
+        f(&m.0, m.1)
+    })
+}
+
+fn lua_current() -> MapId {
+    with_maps(|_, current| current)
+}
+
+fn lua_ids() -> Vec<MapId> {
+    with_maps(|maps, _| maps.ids())
+}
+
+fn lua_name(id: MapId) -> Option<String> {
+    with_maps(|maps, _| maps.get(id).map(|m| m.name.clone()))
+}
+
+fn lua_depth(id: MapId) -> Option<i32> {
+    with_maps(|maps, _| maps.get(id).map(|m| m.depth))
+}
+
+fn lua_parent(id: MapId) -> Option<MapId> {
+    with_maps(|maps, _| maps.get(id).and_then(|m| m.parent))
+}
+
+fn lua_prefab(id: MapId) -> Option<String> {
+    with_maps(|maps, _| maps.get(id).and_then(|m| m.prefab.clone()))
+}
+
+/// The maps that stairs on the given map lead to.
+fn lua_connections(id: MapId) -> Vec<MapId> {
+    with_maps(|maps, _| maps.connections_from(id).iter().map(|c| c.to).collect())
+}
+
+pub fn add_lua_interop(lua: &mut Lua) {
+    let mut maps_namespace = lua.empty_array("maps");
+
+    maps_namespace.set("current", hlua::function0(lua_current));
+    maps_namespace.set("ids", hlua::function0(lua_ids));
+    maps_namespace.set("name", hlua::function1(lua_name));
+    maps_namespace.set("depth", hlua::function1(lua_depth));
+    maps_namespace.set("parent", hlua::function1(lua_parent));
+    maps_namespace.set("prefab", hlua::function1(lua_prefab));
+    maps_namespace.set("connections", hlua::function1(lua_connections));
+}
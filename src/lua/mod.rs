@@ -1,6 +1,9 @@
 pub mod log;
+mod maps;
 mod random;
 pub use self::log::*;
+pub use self::maps::set_maps;
+pub use self::random::{next_seed, reseed};
 
 use std::fs::File;
 use std::io::Read;
@@ -31,6 +34,7 @@ fn open_libs(lua: &mut Lua) -> Result<(), hlua::LuaError> {
     lua.openlibs();
 
     self::log::add_lua_interop(lua);
+    self::maps::add_lua_interop(lua);
     self::random::add_lua_interop(lua);
     prefab::add_lua_interop(lua);
 
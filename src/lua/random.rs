@@ -1,7 +1,31 @@
+use std::cell::RefCell;
+
 use prefab::{PrefabError, PrefabResult};
-use rand::{self, Rng};
+use rand::{Rng, SeedableRng, XorShiftRng};
 use hlua::{self, Lua};
 
+thread_local! {
+    // Prefabs are generated before the world they're placed in exists, so the
+    // generator has its own RNG that is reseeded for each map.
+    static LUA_RNG: RefCell<XorShiftRng> = RefCell::new(XorShiftRng::new_unseeded());
+}
+
+pub fn reseed(seed: [u32; 4]) {
+    LUA_RNG.with(|r| *r.borrow_mut() = XorShiftRng::from_seed(seed));
+}
+
+/// Gets a seed for a map generator that doesn't run in Lua, so it depends on
+/// the same reseeding as the Lua ones.
+pub fn next_seed() -> [u32; 4] {
+    // XorShiftRng can't be seeded with all zeroes.
+    with_rng(|r| [r.next_u32(), r.next_u32(), r.next_u32(), r.next_u32() | 1])
+}
+
+fn with_rng<A, F>(f: F) -> A
+    where F: FnOnce(&mut XorShiftRng) -> A {
+    LUA_RNG.with(|r| f(&mut *r.borrow_mut()))
+}
+
 fn lua_between(a: i32, b: i32) -> PrefabResult<i32> {
     if a == b {
         return Ok(a);
@@ -9,22 +33,22 @@ fn lua_between(a: i32, b: i32) -> PrefabResult<i32> {
     if a > b {
         return Err(PrefabError::BadRange(a, b))
     }
-    Ok(rand::thread_rng().gen_range(a, b))
+    Ok(with_rng(|r| r.gen_range(a, b)))
 }
 
 fn lua_zero_to(n: i32) -> PrefabResult<i32> {
     if n <= 0 {
         return Err(PrefabError::BadRange(0, n))
     }
-    Ok(rand::thread_rng().gen_range(0, n))
+    Ok(with_rng(|r| r.gen_range(0, n)))
 }
 
 fn lua_chance(n: f32) -> bool {
-    rand::thread_rng().next_f32() < n
+    with_rng(|r| r.next_f32()) < n
 }
 
 fn lua_coinflip() -> bool {
-    rand::thread_rng().gen()
+    with_rng(|r| r.gen())
 }
 
 pub fn add_lua_interop(lua: &mut Lua) {
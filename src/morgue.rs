@@ -0,0 +1,138 @@
+//! Morgue files, a plain-text record of a character written when the game
+//! ends, either by the player dying in a permadeath game or quitting.
+
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use calx_ecs::Entity;
+use chrono::Local;
+
+use ecs::traits::*;
+use world::serial;
+use world::traits::*;
+use world::World;
+
+/// How many of the last messages are included.
+pub const MORGUE_MESSAGES: usize = 20;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Ending {
+    Died,
+    Quit,
+}
+
+/// Writes a morgue file for the player and returns where it was written.
+pub fn write(world: &World, player: Entity, ending: Ending) -> io::Result<PathBuf> {
+    let dir = serial::get_morgue_dir();
+    fs::create_dir_all(&dir)?;
+
+    let mut path = dir;
+    path.push(format!("{}-{}.txt", serial::current_slot(), Local::now().format("%Y%m%d-%H%M%S")));
+
+    let mut file = File::create(&path)?;
+    file.write_all(describe(world, player, ending).as_bytes())?;
+    Ok(path)
+}
+
+fn entity_name(world: &World, e: Entity) -> String {
+    world.ecs().names.map_or("something".to_string(), |n| n.name.clone(), e)
+}
+
+/// Lists everything inside a container, including what's inside the things it
+/// contains.
+fn describe_contents(world: &World, container: Entity, depth: usize, lines: &mut Vec<String>) {
+    for item in world.entities_in(container) {
+        let name = match world.ecs().items.map_or(1, |i| i.count, item) {
+            1 => entity_name(world, item),
+            count => format!("{} (x{})", entity_name(world, item), count),
+        };
+        lines.push(format!("{}{}", "  ".repeat(depth + 1), name));
+        describe_contents(world, item, depth + 1, lines);
+    }
+}
+
+/// The text of a morgue file.
+pub fn describe(world: &World, player: Entity, ending: Ending) -> String {
+    let globals = world.flags().get_globals();
+    let name = world.ecs().names.map_or("someone".to_string(), |n| n.name.clone(), player);
+    let how = match ending {
+        Ending::Died => "died",
+        Ending::Quit => "quit",
+    };
+
+    let mut lines = Vec::new();
+    lines.push(format!("{} {} on map {} after {} turns.", name, how, world.map_id(), world.turns()));
+    lines.push(format!("Mode: {:?}", globals.mode));
+    lines.push(format!("Seed: {}", world.seed()));
+    lines.push(String::new());
+
+    lines.push("Stats:".to_string());
+    if let Some(health) = world.ecs().healths.get(player) {
+        lines.push(format!("  HP: {}/{}", health.hit_points, health.max_hit_points));
+    }
+    if let Some(turn) = world.ecs().turns.get(player) {
+        lines.push(format!("  Speed: {}", turn.speed));
+    }
+    lines.push(String::new());
+
+    lines.push("Inventory:".to_string());
+    let before = lines.len();
+    describe_contents(world, player, 0, &mut lines);
+    if lines.len() == before {
+        lines.push("  (nothing)".to_string());
+    }
+    lines.push(String::new());
+
+    lines.push("Kills:".to_string());
+    for (name, count) in globals.kills.iter() {
+        lines.push(format!("  {} {}", count, name));
+    }
+    if globals.kills.is_empty() {
+        lines.push("  (none)".to_string());
+    }
+    lines.push(String::new());
+
+    lines.push("Maps visited:".to_string());
+    for map_id in globals.maps_visited.iter() {
+        lines.push(format!("  Map {}", map_id));
+    }
+    lines.push(String::new());
+
+    lines.push("Last messages:".to_string());
+    for message in world.get_messages(MORGUE_MESSAGES).iter().rev() {
+        lines.push(format!("  {}", message.trim_right()));
+    }
+    lines.push(String::new());
+
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ecs::prefab;
+    use testing::*;
+
+    #[test]
+    fn test_describe() {
+        let mut context = test_context();
+        let world = &mut context.state.world;
+        let player = world.player().unwrap();
+        let pos = world.position(player).unwrap();
+
+        let bag = world.create(prefab::corpse("dood"), pos);
+        let cola = world.create(prefab::item("cola", "cola"), pos);
+        world.place_entity_in(bag, cola);
+        world.place_entity_in(player, bag);
+
+        world.flags_mut().globals.record_kill("putit");
+        world.flags_mut().globals.record_kill("putit");
+        world.flags_mut().globals.visit(0);
+
+        let text = describe(world, player, Ending::Quit);
+        assert!(text.contains("  corpse of dood\n    cola"));
+        assert!(text.contains("  2 putit"));
+        assert!(text.contains("  Map 0"));
+    }
+}
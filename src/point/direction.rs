@@ -1,7 +1,7 @@
 use std::ops::Add;
 use std::slice::Iter;
 
-use rand::{self, Rng};
+use rand::Rng;
 
 use point::Point;
 
@@ -61,8 +61,8 @@ impl Direction {
         Direction::from_movement_offset((-i, -j)).unwrap()
     }
 
-    pub fn choose8() -> Direction {
-        *rand::thread_rng().choose(&DIRECTIONS).unwrap()
+    pub fn choose8<R: Rng>(rng: &mut R) -> Direction {
+        *rng.choose(&DIRECTIONS).unwrap()
     }
 
     pub fn iter8() -> Iter<'static, Direction> {
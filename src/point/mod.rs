@@ -4,7 +4,7 @@ mod pathfinding;
 
 pub use self::direction::Direction;
 pub use self::iter::*;
-pub use self::pathfinding::Path;
+pub use self::pathfinding::{Passability, Path};
 
 use std::cmp::{max, Ordering};
 use std::fmt::{Display, Formatter, Error};
@@ -3,22 +3,40 @@ use std::collections::{BinaryHeap, HashMap};
 use std::f32;
 
 use data::Walkability;
+use graphics::cell::DoorState;
 use world::traits::{Query, WorldQuery};
 use point::Point;
 use world::World;
 
 const CALCULATION_LIMIT: u32 = 50;
 
+/// Something paths can be found through, like the world or a prefab.
+pub trait Passability {
+    /// Returns true if a path can go through the given position.
+    fn can_pass(&self, pos: Point) -> bool;
+}
+
+/// The world, as seen by something walking through it.
+struct WorldPassability<'a> {
+    world: &'a World,
+    walkability: Walkability,
+}
+
+impl<'a> Passability for WorldPassability<'a> {
+    fn can_pass(&self, pos: Point) -> bool {
+        self.world.pos_loaded(&pos)
+            && (self.world.can_walk(pos, self.walkability)
+                || Path::can_open_door(pos, self.world, self.walkability))
+    }
+}
+
 #[derive(Debug)]
 pub struct Path {
     path: Vec<Point>,
 }
 
 impl Path {
-    fn neighbors(current: Point,
-                 world: &World,
-                 walkability: Walkability) -> Vec<Point> {
-        assert!(world.pos_loaded(&current));
+    fn neighbors<P: Passability>(current: Point, source: &P) -> Vec<Point> {
         let nearby_points: [Point; 9] = [
             (-1, -1).into(),
             (-1,  0).into(),
@@ -33,12 +51,16 @@ impl Path {
 
         nearby_points.iter()
             .map(|&d| current + d)
-            .filter(|&point|
-                    world.pos_loaded(&point)
-                    && world.can_walk(point, walkability))
+            .filter(|&point| source.can_pass(point))
             .collect::<Vec<_>>()
     }
 
+    /// Closed doors are treated as passable, since the entity following the
+    /// path can open them along the way.
+    fn can_open_door(pos: Point, world: &World, walkability: Walkability) -> bool {
+        world.door_at(&pos) == Some(DoorState::Closed) && walkability.can_walk(world, &pos)
+    }
+
     fn search_heuristic(destination: Point, next: Point) -> f32 {
         ((destination.x - next.x).abs() + (destination.y - next.y).abs()) as f32
     }
@@ -95,6 +117,33 @@ impl Path {
             return Path { path: vec![to] };
         }
 
+        assert!(world.pos_loaded(&from));
+        let source = WorldPassability {
+            world: world,
+            walkability: walkability,
+        };
+
+        // NOTE: the map is effectively infinite. We need to limit the
+        // calculations or the algorithm will try to explore the
+        // entire world before it decides that no path exists.
+        Path::search(from, to, &source, stop_when_neighboring, Some(CALCULATION_LIMIT))
+    }
+
+    /// Finds a path through anything that can be passed through. Unlike
+    /// `find`, the search isn't cut short, so the source has to be bounded.
+    pub fn find_through<P: Passability>(from: Point, to: Point, source: &P) -> Self {
+        if from == to || !source.can_pass(to) {
+            return Path { path: vec![] };
+        }
+
+        Path::search(from, to, source, false, None)
+    }
+
+    fn search<P: Passability>(from: Point,
+                              to: Point,
+                              source: &P,
+                              stop_when_neighboring: bool,
+                              limit: Option<u32>) -> Self {
         let mut frontier = BinaryHeap::new();
         frontier.push(State { position: from, cost: 0.0 });
         let mut came_from = HashMap::new();
@@ -103,9 +152,6 @@ impl Path {
         came_from.insert(from, None);
         cost_so_far.insert(from, 0.0);
 
-        // NOTE: the map is effectively infinite. We need to limit the
-        // calculations or the algorithm will try to explore the
-        // entire world before it decides that no path exists.
         let mut calculation_steps = 0;
 
         while let Some(current) = frontier.pop() {
@@ -116,12 +162,12 @@ impl Path {
                 break
             }
 
-            if calculation_steps >= CALCULATION_LIMIT {
+            if limit.map_or(false, |l| calculation_steps >= l) {
                 break
             } else {
                 calculation_steps += 1;
             }
-            let neigh = Path::neighbors(current.position, world, walkability);
+            let neigh = Path::neighbors(current.position, source);
 
             for &next in neigh.iter() {
                 // Make this an in-game property toggle?
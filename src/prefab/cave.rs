@@ -0,0 +1,376 @@
+//! A cave generator written in Rust, for maps too big to make in Lua quickly.
+//! The cave starts out as random noise, which is smoothed into rounded
+//! caverns by a cellular automaton. Caverns too small to matter are filled
+//! in, and the rest are joined to the biggest one with tunnels, so every part
+//! of the cave can be walked to.
+
+use std::cmp::max;
+use std::collections::VecDeque;
+
+use rand::{Rng, SeedableRng, XorShiftRng};
+
+use graphics::cell::Cell;
+use lua;
+use point::Point;
+use prefab::*;
+
+/// Caverns smaller than this are filled in instead of being connected.
+const MIN_CAVERN_SIZE: usize = 12;
+
+#[derive(Clone, Debug)]
+pub struct CaveParams {
+    pub width: i32,
+    pub height: i32,
+    /// The chance that a cell starts out as wall.
+    pub fill: f32,
+    /// How many times the cellular automaton is run.
+    pub smoothing: u32,
+}
+
+impl CaveParams {
+    pub fn new(width: i32, height: i32) -> Self {
+        CaveParams {
+            width: width,
+            height: height,
+            fill: 0.45,
+            smoothing: 5,
+        }
+    }
+
+    /// Reads the parameters from prefab arguments. Anything not given keeps
+    /// its default.
+    pub fn from_args(args: &Option<PrefabArgs>) -> PrefabResult<Self> {
+        let mut params = CaveParams::new(80, 40);
+        let args = match *args {
+            Some(ref args) => args,
+            None => return Ok(params),
+        };
+
+        fn parse<T: ::std::str::FromStr>(args: &PrefabArgs, key: &str, default: T) -> PrefabResult<T> {
+            match args.get(key) {
+                Some(val) => val.parse().map_err(|_| BadArgument(key.to_string(), val.clone())),
+                None => Ok(default),
+            }
+        }
+
+        params.width = parse(args, "width", params.width)?;
+        params.height = parse(args, "height", params.height)?;
+        params.fill = parse(args, "fill", params.fill)?;
+        params.smoothing = parse(args, "smoothing", params.smoothing)?;
+
+        if params.width < 3 || params.height < 3 {
+            return Err(BadRange(params.width, params.height));
+        }
+
+        Ok(params)
+    }
+}
+
+/// Which cells of the cave are walls, before it's turned into a prefab.
+struct Grid {
+    width: i32,
+    height: i32,
+    walls: Vec<bool>,
+}
+
+impl Grid {
+    fn index(&self, x: i32, y: i32) -> usize {
+        (y * self.width + x) as usize
+    }
+
+    fn is_edge(&self, x: i32, y: i32) -> bool {
+        x == 0 || y == 0 || x == self.width - 1 || y == self.height - 1
+    }
+
+    /// Anything outside the grid counts as wall, so caverns don't open onto
+    /// the edge.
+    fn is_wall(&self, x: i32, y: i32) -> bool {
+        if x < 0 || y < 0 || x >= self.width || y >= self.height {
+            return true;
+        }
+        self.walls[self.index(x, y)]
+    }
+
+    fn set_wall(&mut self, x: i32, y: i32, wall: bool) {
+        if !self.is_edge(x, y) {
+            let idx = self.index(x, y);
+            self.walls[idx] = wall;
+        }
+    }
+
+    fn walls_around(&self, x: i32, y: i32) -> u32 {
+        let mut count = 0;
+        for dx in -1..2 {
+            for dy in -1..2 {
+                if (dx != 0 || dy != 0) && self.is_wall(x + dx, y + dy) {
+                    count += 1;
+                }
+            }
+        }
+        count
+    }
+
+    fn random_fill<R: Rng>(width: i32, height: i32, fill: f32, rng: &mut R) -> Self {
+        let mut grid = Grid {
+            width: width,
+            height: height,
+            walls: vec![true; (width * height) as usize],
+        };
+        for y in 1..height - 1 {
+            for x in 1..width - 1 {
+                let wall = rng.next_f32() < fill;
+                grid.set_wall(x, y, wall);
+            }
+        }
+        grid
+    }
+
+    /// Runs one step of the automaton. Cells surrounded by walls become
+    /// walls, and cells mostly surrounded by floor become floor.
+    fn smooth(&self) -> Self {
+        let mut walls = self.walls.clone();
+        for y in 1..self.height - 1 {
+            for x in 1..self.width - 1 {
+                let around = self.walls_around(x, y);
+                if around > 4 {
+                    walls[self.index(x, y)] = true;
+                } else if around < 4 {
+                    walls[self.index(x, y)] = false;
+                }
+            }
+        }
+        Grid {
+            width: self.width,
+            height: self.height,
+            walls: walls,
+        }
+    }
+
+    /// Finds every group of floor cells connected to each other, biggest
+    /// first.
+    fn caverns(&self) -> Vec<Vec<Point>> {
+        let mut seen = vec![false; self.walls.len()];
+        let mut caverns = Vec::new();
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if self.is_wall(x, y) || seen[self.index(x, y)] {
+                    continue;
+                }
+
+                let mut cavern = Vec::new();
+                let mut queue = VecDeque::new();
+                seen[self.index(x, y)] = true;
+                queue.push_back(Point::new(x, y));
+
+                while let Some(pos) = queue.pop_front() {
+                    cavern.push(pos);
+                    for &(dx, dy) in [(1, 0), (-1, 0), (0, 1), (0, -1)].iter() {
+                        let (nx, ny) = (pos.x + dx, pos.y + dy);
+                        if !self.is_wall(nx, ny) && !seen[self.index(nx, ny)] {
+                            seen[self.index(nx, ny)] = true;
+                            queue.push_back(Point::new(nx, ny));
+                        }
+                    }
+                }
+
+                caverns.push(cavern);
+            }
+        }
+
+        // Stable, so caverns of the same size stay in the same order.
+        caverns.sort_by(|a, b| b.len().cmp(&a.len()));
+        caverns
+    }
+
+    /// Carves an L-shaped tunnel between two cells.
+    fn tunnel(&mut self, from: Point, to: Point) {
+        let step = |a: i32, b: i32| if a < b { 1 } else { -1 };
+
+        let mut x = from.x;
+        while x != to.x {
+            self.set_wall(x, from.y, false);
+            x += step(x, to.x);
+        }
+
+        let mut y = from.y;
+        while y != to.y {
+            self.set_wall(to.x, y, false);
+            y += step(y, to.y);
+        }
+
+        self.set_wall(to.x, to.y, false);
+    }
+
+    /// Fills in the small caverns and tunnels from the others to the biggest
+    /// one. Returns the cells of the cave that's left.
+    fn connect(&mut self) -> Vec<Point> {
+        let mut caverns = self.caverns();
+        if caverns.is_empty() {
+            let (x, y) = (self.width / 2, self.height / 2);
+            self.set_wall(x, y, false);
+            return vec![Point::new(x, y)];
+        }
+
+        let mut main = caverns.remove(0);
+        for cavern in caverns.into_iter() {
+            if cavern.len() < MIN_CAVERN_SIZE {
+                for pos in cavern.iter() {
+                    self.set_wall(pos.x, pos.y, true);
+                }
+                continue;
+            }
+
+            // Tunnel between the closest pair of cells, checking only a few
+            // cells of the cavern so big caves don't take forever.
+            let stride = max(cavern.len() / 16, 1);
+            let (from, to) = cavern.iter().enumerate()
+                .filter(|&(i, _)| i % stride == 0)
+                .map(|(_, a)| (*a, *main.iter().min_by_key(|b| distance_sq(*a, **b)).unwrap()))
+                .min_by_key(|&(a, b)| distance_sq(a, b))
+                .unwrap();
+
+            self.tunnel(from, to);
+            main.extend(cavern);
+        }
+
+        // The tunnels added cells of their own.
+        self.caverns().into_iter().next().unwrap_or(main)
+    }
+
+    /// Finds how many steps away every floor cell is from a cell.
+    fn distances_from(&self, start: Point) -> Vec<Option<u32>> {
+        let mut distances = vec![None; self.walls.len()];
+        let mut queue = VecDeque::new();
+        distances[self.index(start.x, start.y)] = Some(0);
+        queue.push_back(start);
+
+        while let Some(pos) = queue.pop_front() {
+            let dist = distances[self.index(pos.x, pos.y)].unwrap();
+            for &(dx, dy) in [(1, 0), (-1, 0), (0, 1), (0, -1)].iter() {
+                let (nx, ny) = (pos.x + dx, pos.y + dy);
+                if !self.is_wall(nx, ny) && distances[self.index(nx, ny)].is_none() {
+                    distances[self.index(nx, ny)] = Some(dist + 1);
+                    queue.push_back(Point::new(nx, ny));
+                }
+            }
+        }
+
+        distances
+    }
+}
+
+fn distance_sq(a: Point, b: Point) -> i32 {
+    (a.x - b.x).pow(2) + (a.y - b.y).pow(2)
+}
+
+/// Generates a cave. The stairs in are placed somewhere random, and the stairs
+/// out as far away from them as possible.
+pub fn generate(params: &CaveParams, seed: [u32; 4]) -> Prefab {
+    let mut rng = XorShiftRng::from_seed(seed);
+
+    let mut grid = Grid::random_fill(params.width, params.height, params.fill, &mut rng);
+    for _ in 0..params.smoothing {
+        grid = grid.smooth();
+    }
+    let cave = grid.connect();
+
+    let mut prefab = Prefab::new(params.width, params.height, "wall");
+    for pos in cave.iter() {
+        prefab.set(pos, Cell::new("floor"));
+    }
+
+    let stairs_in = cave[rng.gen_range(0, cave.len())];
+    let distances = grid.distances_from(stairs_in);
+    let stairs_out = cave.iter()
+        .max_by_key(|pos| distances[grid.index(pos.x, pos.y)])
+        .cloned()
+        .unwrap_or(stairs_in);
+
+    prefab.set_marker(&stairs_in, PrefabMarker::StairsIn);
+    if stairs_out != stairs_in {
+        prefab.set_marker(&stairs_out, PrefabMarker::StairsOut);
+    }
+
+    prefab
+}
+
+/// Makes a cave from prefab arguments, seeded from the prefab RNG like the
+/// Lua maps are.
+pub fn create(args: &Option<PrefabArgs>) -> PrefabResult<Prefab> {
+    let params = CaveParams::from_args(args)?;
+    Ok(generate(&params, lua::next_seed()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEED: [u32; 4] = [1, 2, 3, 4];
+
+    fn floor(prefab: &Prefab) -> Vec<Point> {
+        prefab.iter()
+            .filter(|&(_, cell)| cell.can_pass_through())
+            .map(|(pos, _)| pos)
+            .collect()
+    }
+
+    #[test]
+    fn test_deterministic() {
+        let params = CaveParams::new(60, 40);
+        let a = generate(&params, SEED);
+        let b = generate(&params, SEED);
+        assert_eq!(floor(&a), floor(&b));
+        assert_eq!(a.find_marker(PrefabMarker::StairsIn), b.find_marker(PrefabMarker::StairsIn));
+
+        let c = generate(&params, [5, 6, 7, 8]);
+        assert!(floor(&a) != floor(&c));
+    }
+
+    #[test]
+    fn test_connected() {
+        for i in 1..10 {
+            let params = CaveParams::new(80, 50);
+            let prefab = generate(&params, [i, 2, 3, 4]);
+
+            let mut grid = Grid { width: 80, height: 50, walls: vec![true; 80 * 50] };
+            for pos in floor(&prefab) {
+                let idx = grid.index(pos.x, pos.y);
+                grid.walls[idx] = false;
+            }
+
+            let caverns = grid.caverns();
+            assert_eq!(caverns.len(), 1, "Cave {} isn't connected", i);
+
+            let stairs_in = prefab.find_marker(PrefabMarker::StairsIn).unwrap();
+            let stairs_out = prefab.find_marker(PrefabMarker::StairsOut).unwrap();
+            assert!(caverns[0].contains(&stairs_in));
+            assert!(caverns[0].contains(&stairs_out));
+        }
+    }
+
+    #[test]
+    fn test_walled_in() {
+        let prefab = generate(&CaveParams::new(40, 30), SEED);
+        for (pos, cell) in prefab.iter() {
+            if pos.x == 0 || pos.y == 0 || pos.x == 39 || pos.y == 29 {
+                assert!(!cell.can_pass_through(), "Open edge at {}", pos);
+            }
+        }
+    }
+
+    #[test]
+    fn test_args() {
+        let mut args = PrefabArgs::new();
+        args.insert("width".to_string(), "100".to_string());
+        args.insert("fill".to_string(), "0.5".to_string());
+        let params = CaveParams::from_args(&Some(args)).unwrap();
+        assert_eq!(params.width, 100);
+        assert_eq!(params.height, 40);
+        assert_eq!(params.fill, 0.5);
+
+        let mut args = PrefabArgs::new();
+        args.insert("height".to_string(), "tall".to_string());
+        assert!(CaveParams::from_args(&Some(args)).is_err());
+    }
+}
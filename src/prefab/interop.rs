@@ -7,7 +7,7 @@ use prefab::*;
 use lua;
 
 pub fn get_prefab_names() -> Vec<String> {
-    let mut names = Vec::new();
+    let mut names: Vec<String> = NATIVE_PREFABS.iter().map(|&(name, _)| name.to_string()).collect();
     for entry in glob::glob("lua/maps/*.lua").expect("No prefab path!") {
         if let Ok(path) = entry {
             names.push(path.file_stem().unwrap().to_str().unwrap().to_owned());
@@ -16,10 +16,47 @@ pub fn get_prefab_names() -> Vec<String> {
     names
 }
 
+/// How many times a prefab with unreachable markers is generated again before
+/// corridors are carved through the last one instead.
+const MAX_ATTEMPTS: u32 = 3;
+
+fn generate(name: &str, args: &Option<PrefabArgs>) -> PrefabResult<Prefab> {
+    match find_native(name) {
+        Some(native) => native(args),
+        None => lua::with_mut(|l| map_from_prefab(l, name, args)),
+    }
+}
+
+/// Generates a prefab that can be walked through, by generating it again if
+/// it can't or repairing it once there have been too many attempts.
+fn generate_valid(name: &str, args: &Option<PrefabArgs>) -> PrefabResult<Prefab> {
+    for _ in 1..MAX_ATTEMPTS {
+        let prefab = generate(name, args)?;
+
+        match validate::validate(&prefab) {
+            Ok(()) => return Ok(prefab),
+            Err(e) => {
+                lua::log(format!("Rejected prefab \"{}\" ({}), generating it again", name, e));
+            },
+        }
+    }
+
+    let mut prefab = generate(name, args)?;
+
+    let err = match validate::validate(&prefab) {
+        Ok(()) => return Ok(prefab),
+        Err(e) => e,
+    };
+
+    let carved = validate::repair(&mut prefab)?;
+    lua::log(format!("Repaired prefab \"{}\" ({}) by carving {} cells", name, err, carved.len()));
+    Ok(prefab)
+}
+
 pub fn create(name: &str, args: &Option<PrefabArgs>) -> PrefabResult<Prefab> {
     lua::log(format!("Starting creation of prefab \"{}\"", name));
 
-    let res = lua::with_mut(|l| map_from_prefab(l, name, args));
+    let res = generate_valid(name, args);
 
     lua::log(format!("Finished creating prefab \"{}\"", name));
 
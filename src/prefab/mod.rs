@@ -1,4 +1,6 @@
+pub mod cave;
 mod interop;
+pub mod validate;
 
 pub use self::interop::*;
 
@@ -9,14 +11,17 @@ use hlua;
 
 use graphics::cell::Cell;
 use graphics::Color;
-use point::{Point};
+use point::{Passability, Path, Point};
 
 #[derive(Debug)]
 pub enum PrefabError {
     OutOfBounds(i32, i32),
     BadRange(i32, i32),
+    BadArgument(String, String),
     LuaException(hlua::LuaError),
     PrefabVarNotDeclared,
+    /// Markers that can't be walked to from where the player arrives.
+    Unreachable(Vec<(Point, PrefabMarker)>),
 }
 
 use self::PrefabError::*;
@@ -26,6 +31,12 @@ impl fmt::Display for PrefabError {
         let string = match *self {
             PrefabError::LuaException(hlua::LuaError::SyntaxError(ref e)) |
             PrefabError::LuaException(hlua::LuaError::ExecutionError(ref e)) => e.clone(),
+            PrefabError::Unreachable(ref markers) => {
+                let markers: Vec<_> = markers.iter()
+                    .map(|&(pos, ref marker)| format!("{:?} at {}", marker, pos))
+                    .collect();
+                format!("Unreachable markers: {}", markers.join(", "))
+            },
             ref e => format!("{:?}", e),
         };
         write!(f, "{}", string)
@@ -42,6 +53,20 @@ pub type PrefabResult<T> = Result<T, PrefabError>;
 pub type Markers = HashMap<Point, PrefabMarker>;
 pub type PrefabArgs = HashMap<String, String>;
 
+type NativePrefab = fn(&Option<PrefabArgs>) -> PrefabResult<Prefab>;
+
+/// Prefabs generated in Rust instead of by a script in `lua/maps`. They're
+/// created by name the same way.
+const NATIVE_PREFABS: [(&'static str, NativePrefab); 1] = [
+    ("cave", cave::create),
+];
+
+fn find_native(name: &str) -> Option<NativePrefab> {
+    NATIVE_PREFABS.iter()
+        .find(|&&(native, _)| native == name)
+        .map(|&(_, create)| create)
+}
+
 #[derive(Debug, Clone)]
 pub struct Prefab {
     cells: Vec<Cell>,
@@ -166,11 +191,15 @@ impl Prefab {
     }
 }
 
-#[cfg(never)]
 impl Prefab {
-    fn connected(&self, from: &Point, to: &Point) -> bool {
-        let blocked = |pos| self.get(pos).can_pass_through();
-        Path::find(self, from, to, blocked).len() > 0
+    pub fn connected(&self, from: &Point, to: &Point) -> bool {
+        from == to || Path::find_through(*from, *to, self).len() > 0
+    }
+}
+
+impl Passability for Prefab {
+    fn can_pass(&self, pos: Point) -> bool {
+        self.in_bounds(&pos) && self.get(&pos).can_pass_through()
     }
 }
 
@@ -0,0 +1,201 @@
+//! Checking that a generated prefab can be played through. Every stair, door
+//! and connection has to be reachable from where the player arrives, or the
+//! map can leave them stuck. Maps that aren't can have corridors carved
+//! through them to fix it.
+
+use std::collections::{HashSet, VecDeque};
+
+use graphics::cell::Cell;
+use point::{Passability, Point};
+use prefab::*;
+
+/// Returns true if the player has to be able to walk to a marker.
+fn must_reach(marker: &PrefabMarker) -> bool {
+    match *marker {
+        PrefabMarker::StairsIn |
+        PrefabMarker::StairsOut |
+        PrefabMarker::Door |
+        PrefabMarker::Connection => true,
+        PrefabMarker::Mob(..) | PrefabMarker::Npc => false,
+    }
+}
+
+/// The markers that have to be reachable, in the same order every time.
+fn required_markers(prefab: &Prefab) -> Vec<(Point, PrefabMarker)> {
+    let mut markers: Vec<_> = prefab.markers()
+        .filter(|&(_, marker)| must_reach(marker))
+        .map(|(pos, marker)| (*pos, marker.clone()))
+        .collect();
+    markers.sort_by_key(|&(pos, _)| (pos.y, pos.x));
+    markers
+}
+
+/// Where reachability is checked from. The player arrives at the stairs in,
+/// if there are any.
+fn anchor(prefab: &Prefab) -> Option<Point> {
+    prefab.find_marker(PrefabMarker::StairsIn)
+        .or_else(|| required_markers(prefab).first().map(|&(pos, _)| pos))
+}
+
+/// Finds the markers that can't be walked to from the anchor.
+pub fn unreachable_markers(prefab: &Prefab) -> Vec<(Point, PrefabMarker)> {
+    let from = match anchor(prefab) {
+        Some(pos) => pos,
+        None => return Vec::new(),
+    };
+
+    required_markers(prefab).into_iter()
+        .filter(|&(pos, _)| !prefab.connected(&from, &pos))
+        .collect()
+}
+
+pub fn validate(prefab: &Prefab) -> PrefabResult<()> {
+    let unreachable = unreachable_markers(prefab);
+    if unreachable.is_empty() {
+        Ok(())
+    } else {
+        Err(PrefabError::Unreachable(unreachable))
+    }
+}
+
+/// Finds every cell that can be walked to from a position.
+fn reachable_from(prefab: &Prefab, start: Point) -> HashSet<Point> {
+    let mut seen = HashSet::new();
+    let mut queue = VecDeque::new();
+    seen.insert(start);
+    queue.push_back(start);
+
+    while let Some(pos) = queue.pop_front() {
+        for dx in -1..2 {
+            for dy in -1..2 {
+                let next = pos + (dx, dy);
+                if !seen.contains(&next) && prefab.can_pass(next) {
+                    seen.insert(next);
+                    queue.push_back(next);
+                }
+            }
+        }
+    }
+
+    seen
+}
+
+/// Turns everything impassable between two cells into floor, going
+/// horizontally and then vertically.
+fn carve_corridor(prefab: &mut Prefab, from: Point, to: Point, carved: &mut Vec<Point>) {
+    let step = |a: i32, b: i32| if a < b { 1 } else { -1 };
+    let mut carve = |prefab: &mut Prefab, pos: Point| {
+        if !prefab.can_pass(pos) {
+            prefab.set(&pos, Cell::new("floor"));
+            carved.push(pos);
+        }
+    };
+
+    let mut pos = from;
+    carve(prefab, pos);
+    while pos.x != to.x {
+        pos.x += step(pos.x, to.x);
+        carve(prefab, pos);
+    }
+    while pos.y != to.y {
+        pos.y += step(pos.y, to.y);
+        carve(prefab, pos);
+    }
+}
+
+/// Carves corridors from every unreachable marker to the closest cell that
+/// can be reached. Returns the cells that were carved.
+pub fn repair(prefab: &mut Prefab) -> PrefabResult<Vec<Point>> {
+    let from = match anchor(prefab) {
+        Some(pos) => pos,
+        None => return Ok(Vec::new()),
+    };
+
+    // The player has to be able to stand where they arrive.
+    let mut carved = Vec::new();
+    if !prefab.can_pass(from) {
+        prefab.set(&from, Cell::new("floor"));
+        carved.push(from);
+    }
+
+    for (pos, _) in unreachable_markers(prefab).into_iter() {
+        // An earlier corridor might have gone past this one.
+        if prefab.connected(&from, &pos) {
+            continue;
+        }
+
+        let reachable = reachable_from(prefab, from);
+        let closest = reachable.iter()
+            .min_by_key(|p| ((p.x - pos.x).pow(2) + (p.y - pos.y).pow(2), p.y, p.x))
+            .cloned()
+            .unwrap_or(from);
+
+        carve_corridor(prefab, pos, closest, &mut carved);
+    }
+
+    validate(prefab)?;
+    Ok(carved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two rooms split by a wall, with the stairs out on the far side.
+    fn split_rooms() -> Prefab {
+        let mut prefab = Prefab::new(12, 6, "floor");
+        for y in 0..6 {
+            prefab.set(&Point::new(6, y), Cell::new("wall"));
+        }
+        prefab.set_marker(&Point::new(1, 1), PrefabMarker::StairsIn);
+        prefab.set_marker(&Point::new(10, 4), PrefabMarker::StairsOut);
+        prefab.set_marker(&Point::new(3, 3), PrefabMarker::Npc);
+        prefab
+    }
+
+    #[test]
+    fn test_connected() {
+        let prefab = split_rooms();
+        assert!(prefab.connected(&Point::new(1, 1), &Point::new(5, 5)));
+        assert!(!prefab.connected(&Point::new(1, 1), &Point::new(10, 4)));
+        assert!(prefab.connected(&Point::new(10, 4), &Point::new(7, 0)));
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut prefab = split_rooms();
+        match validate(&prefab) {
+            Err(PrefabError::Unreachable(markers)) => {
+                assert_eq!(markers, vec![(Point::new(10, 4), PrefabMarker::StairsOut)]);
+            },
+            other => panic!("Expected Unreachable, got {:?}", other),
+        }
+
+        prefab.set(&Point::new(6, 2), Cell::new("floor"));
+        assert!(validate(&prefab).is_ok());
+    }
+
+    #[test]
+    fn test_repair() {
+        let mut prefab = split_rooms();
+        let carved = repair(&mut prefab).unwrap();
+        assert_eq!(carved.len(), 1);
+        assert!(carved[0].x == 6);
+        assert!(validate(&prefab).is_ok());
+
+        // Nothing left to carve.
+        assert!(repair(&mut prefab).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_walled_in_marker() {
+        let mut prefab = Prefab::new(8, 8, "wall");
+        prefab.set(&Point::new(1, 1), Cell::new("floor"));
+        prefab.set_marker(&Point::new(1, 1), PrefabMarker::StairsIn);
+        prefab.set_marker(&Point::new(6, 6), PrefabMarker::Door);
+
+        assert!(validate(&prefab).is_err());
+        repair(&mut prefab).unwrap();
+        assert!(prefab.connected(&Point::new(1, 1), &Point::new(6, 6)));
+    }
+}
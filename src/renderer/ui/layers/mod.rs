@@ -1,5 +1,7 @@
 mod input;
 mod choice;
+mod title;
 
 pub use self::input::*;
 pub use self::choice::*;
+pub use self::title::*;
@@ -0,0 +1,109 @@
+use glium::glutin::{VirtualKeyCode, ElementState};
+
+use renderer::ui::*;
+use renderer::ui::elements::*;
+use world::serial::SlotInfo;
+
+#[derive(Clone, Debug)]
+pub enum TitleChoice {
+    NewGame,
+    Load(String),
+    Delete(String),
+}
+
+/// Lists the save slots to continue from, with an entry for starting a new
+/// game at the top.
+pub struct TitleLayer {
+    title: UiText,
+    help: UiText,
+    list: UiList,
+    error: Option<UiText>,
+    slots: Vec<String>,
+    choice: Option<TitleChoice>,
+}
+
+impl TitleLayer {
+    pub fn new(slots: &[SlotInfo]) -> Self {
+        let mut items = vec!["New game".to_string()];
+        items.extend(slots.iter().map(|s| s.describe()));
+
+        TitleLayer {
+            title: UiText::new((120, 60), "sabi"),
+            help: UiText::new((120, 80), "Enter: play  D: delete  Esc: quit"),
+            list: UiList::new((100, 100), items),
+            error: None,
+            slots: slots.iter().map(|s| s.name.clone()).collect(),
+            choice: None,
+        }
+    }
+
+    /// Shows why the last thing picked didn't work out.
+    pub fn with_error(mut self, error: &str) -> Self {
+        self.error = Some(UiText::new((120, 40), error));
+        self
+    }
+
+    fn selected_slot(&self) -> Option<String> {
+        match self.list.get_selected_idx() {
+            Some(0) | None => None,
+            Some(idx) => self.slots.get(idx - 1).cloned(),
+        }
+    }
+}
+
+impl UiElement for TitleLayer {
+    fn draw(&self, renderer: &mut UiRenderer) {
+        self.title.draw(renderer);
+        self.help.draw(renderer);
+        self.list.draw(renderer);
+        if let Some(ref error) = self.error {
+            error.draw(renderer);
+        }
+    }
+}
+
+impl UiLayer for TitleLayer {
+    fn on_event(&mut self, event: glutin::Event) -> EventResult {
+        match event {
+            glutin::Event::KeyboardInput(ElementState::Pressed, _, Some(code)) => {
+                match code {
+                    VirtualKeyCode::Escape => EventResult::Canceled,
+                    VirtualKeyCode::Return => {
+                        self.choice = match self.selected_slot() {
+                            Some(slot) => Some(TitleChoice::Load(slot)),
+                            None => Some(TitleChoice::NewGame),
+                        };
+                        EventResult::Done
+                    },
+                    VirtualKeyCode::D => {
+                        match self.selected_slot() {
+                            Some(slot) => {
+                                self.choice = Some(TitleChoice::Delete(slot));
+                                EventResult::Done
+                            },
+                            None => EventResult::Ignored,
+                        }
+                    },
+                    VirtualKeyCode::Up => {
+                        self.list.select_prev();
+                        EventResult::Consumed(None)
+                    },
+                    VirtualKeyCode::Down => {
+                        self.list.select_next();
+                        EventResult::Consumed(None)
+                    },
+                    _ => EventResult::Ignored,
+                }
+            },
+            _ => EventResult::Ignored,
+        }
+    }
+}
+
+impl UiQuery for TitleLayer {
+    type QueryResult = TitleChoice;
+
+    fn result(&self) -> Option<TitleChoice> {
+        self.choice.clone()
+    }
+}
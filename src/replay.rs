@@ -0,0 +1,333 @@
+//! Recording of player input, so a game can be played back exactly as it
+//! happened. Since all randomness comes from the world's seeded RNG streams,
+//! the same starting world and keys always give the same result.
+//!
+//! Besides the keys passed to `state::game_step`, the answers to any prompts
+//! commands show afterwards, like menus, tile selection and text input, are
+//! recorded in the order they were given. Playing a replay answers the prompts
+//! from the recording instead of asking the player.
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+
+use bincode::{self, Infinite};
+use infinigen::*;
+
+use GameContext;
+use engine::keys::Key;
+use graphics::cell::CellFeature;
+use logic::Command;
+use point::Point;
+use state;
+use world::serial;
+use world::traits::*;
+use world::World;
+
+#[derive(Serialize, Deserialize)]
+pub struct Replay {
+    pub seed: u32,
+
+    /// The serialized world the recording started in.
+    world: Vec<u8>,
+
+    pub keys: Vec<Key>,
+
+    /// The answers to every prompt shown after a key.
+    pub prompts: Vec<Prompt>,
+
+    /// A summary of the world after the last key, to check the replay against.
+    pub expected: Option<String>,
+}
+
+impl Replay {
+    pub fn load<P: AsRef<Path>>(path: P) -> SerialResult<Self> {
+        let mut data: Vec<u8> = Vec::new();
+        let mut file = File::open(path)?;
+        file.read_to_end(&mut data)?;
+        let replay = bincode::deserialize(&data)?;
+        Ok(replay)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> SerialResult<()> {
+        let data = bincode::serialize(self, Infinite)?;
+        let mut file = File::create(path)?;
+        file.write(data.as_slice())?;
+        Ok(())
+    }
+
+    /// Loads the world the recording started in.
+    pub fn initial_world(&self) -> SerialResult<World> {
+        let world = bincode::deserialize(&self.world)?;
+        Ok(world)
+    }
+}
+
+/// The player's answer to a prompt a command showed.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum Prompt {
+    Choice(usize),
+    Tile(Point),
+    Text(String),
+    Cancel,
+}
+
+/// The answers to prompts left in a replay being played.
+pub type Playback = VecDeque<Prompt>;
+
+/// Keeps track of every key the player presses.
+pub struct Recorder {
+    replay: Replay,
+}
+
+impl Recorder {
+    pub fn new(world: &World) -> SerialResult<Self> {
+        let data = bincode::serialize(world, Infinite)?;
+
+        let replay = Replay {
+            seed: world.seed(),
+            world: data,
+            keys: Vec::new(),
+            prompts: Vec::new(),
+            expected: None,
+        };
+
+        Ok(Recorder { replay: replay })
+    }
+
+    pub fn record(&mut self, key: Key) {
+        self.replay.keys.push(key);
+    }
+
+    pub fn record_prompt(&mut self, prompt: Prompt) {
+        self.replay.prompts.push(prompt);
+    }
+
+    /// Stops recording, remembering what the world ended up like.
+    pub fn finish(mut self, world: &World) -> Replay {
+        self.replay.expected = Some(summarize(world));
+        self.replay
+    }
+}
+
+/// Answers a prompt from the replay being played, or by asking the player with
+/// `ask` if there isn't one. The answer is recorded if input is being recorded.
+pub fn answer<F>(context: &mut GameContext, ask: F) -> Prompt
+    where F: FnOnce(&mut GameContext) -> Prompt
+{
+    let played = context.playback.as_mut().map(|p| p.pop_front().unwrap_or(Prompt::Cancel));
+    let answer = match played {
+        Some(answer) => answer,
+        None => ask(context),
+    };
+
+    if let Some(ref mut recorder) = context.recorder {
+        recorder.record_prompt(answer.clone());
+    }
+
+    answer
+}
+
+/// The save slot replays are played in. Slot names for characters never start
+/// with a dot, so this can't be one of the player's saves.
+const REPLAY_SLOT: &'static str = ".replay";
+
+/// Replaces the context's world with the replay's starting world and feeds it
+/// every recorded key and prompt answer.
+///
+/// Playback happens in its own save slot, which is wiped before and after, so
+/// anything the replay saves or deletes never touches the player's saves. Only
+/// the starting map is recorded, so the replay fails if it leaves that map.
+pub fn play(context: &mut GameContext, replay: &Replay) -> Result<(), String> {
+    let prev_slot = serial::current_slot();
+    serial::set_slot(REPLAY_SLOT);
+
+    let result = serial::wipe_save()
+        .map_err(|e| format!("Couldn't clear replay slot: {:?}", e))
+        .and_then(|_| play_keys(context, replay));
+
+    context.playback = None;
+    let wiped = serial::wipe_save();
+    serial::set_slot(&prev_slot);
+
+    result?;
+    wiped.map_err(|e| format!("Couldn't clear replay slot: {:?}", e))
+}
+
+fn play_keys(context: &mut GameContext, replay: &Replay) -> Result<(), String> {
+    context.state.world = replay.initial_world()
+        .map_err(|e| format!("Couldn't load replay world: {:?}", e))?;
+    state::init_headless(context);
+
+    let map_id = context.state.world.map_id();
+
+    context.playback = Some(replay.prompts.iter().cloned().collect());
+    for (i, key) in replay.keys.iter().enumerate() {
+        if takes_stairs(&context.state.world, *key) {
+            return Err(format!("Replay takes stairs off the recorded map {} at key {}, \
+                                but only that map was recorded.",
+                               map_id,
+                               i));
+        }
+
+        state::game_step(context, Some(*key));
+
+        if context.state.world.map_id() != map_id {
+            return Err(format!("Replay left the recorded map {} at key {}, \
+                                but only that map was recorded.",
+                               map_id,
+                               i));
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether the key would take the player down or up the stairs they're on.
+fn takes_stairs(world: &World, key: Key) -> bool {
+    let dir = match Command::from(key) {
+        Command::UseStairs(dir) => dir,
+        _ => return false,
+    };
+
+    let pos = match world.player().and_then(|p| world.position(p)) {
+        Some(pos) => pos,
+        None => return false,
+    };
+
+    match world.cell_const(&pos).and_then(|c| c.feature) {
+        Some(CellFeature::Stairs(stair_dir, _)) => stair_dir == dir,
+        _ => false,
+    }
+}
+
+/// Plays a replay and checks the world ends up the same as when it was
+/// recorded. Returns the summary of the final world.
+pub fn verify(context: &mut GameContext, replay: &Replay) -> Result<String, String> {
+    play(context, replay)?;
+
+    let summary = summarize(&context.state.world);
+
+    match replay.expected {
+        Some(ref expected) if *expected != summary => {
+            Err(format!("Replay diverged!\nExpected:\n{}\nGot:\n{}", expected, summary))
+        },
+        _ => Ok(summary),
+    }
+}
+
+/// A plain-text description of the parts of the world a replay should
+/// reproduce.
+pub fn summarize(world: &World) -> String {
+    let mut lines = Vec::new();
+    lines.push(format!("seed: {}", world.seed()));
+    lines.push(format!("map: {}", world.map_id()));
+
+    for entity in world.entities() {
+        let name = world.ecs().names.get(*entity).map_or("?".to_string(), |n| n.name.clone());
+        let hp = world.ecs().healths.get(*entity).map(|h| h.hit_points);
+        lines.push(format!("{:?} {} at {:?} hp {:?}", entity, name, world.position(*entity), hp));
+    }
+
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ecs;
+    use engine::keys::KeyCode;
+    use graphics::cell::StairDir;
+    use point::POINT_ZERO;
+    use testing::*;
+
+    #[test]
+    fn test_replay() {
+        let mut context = test_context_bounded(64, 64);
+        place_mob(&mut context.state.world, Point::new(10, 10));
+
+        let mut recorder = Recorder::new(&context.state.world).unwrap();
+        let keys = [KeyCode::L, KeyCode::L, KeyCode::J, KeyCode::Space, KeyCode::N, KeyCode::Space];
+        for code in keys.iter() {
+            let key = Key::from(*code);
+            recorder.record(key);
+            state::game_step(&mut context, Some(key));
+        }
+        let replay = recorder.finish(&context.state.world);
+
+        let mut other = test_context_bounded(64, 64);
+        let summary = verify(&mut other, &replay).unwrap();
+        assert_eq!(Some(summary), replay.expected);
+
+        let player = other.state.world.player().unwrap();
+        assert_eq!(other.state.world.position(player), Some(Point::new(3, 2)));
+    }
+
+    #[test]
+    fn test_replay_prompts() {
+        let mut context = test_context_bounded(64, 64);
+        let player = context.state.world.player().unwrap();
+        let rock = context.state.world.create(ecs::prefab::item("rock", "cola"), Point::new(0, 0));
+        let cola = context.state.world.create(ecs::prefab::item("cola", "cola"), Point::new(0, 0));
+        context.state.world.ecs_mut().items.map_mut(|i| i.count = 3, cola);
+        context.state.world.place_entity_in(player, rock);
+        context.state.world.place_entity_in(player, cola);
+
+        let rock_idx = context.state.world.entities_in(player).iter().position(|&e| e == rock).unwrap();
+
+        // Throw the rock, then drop two of the colas.
+        let keys = [KeyCode::T, KeyCode::D];
+        let prompts = vec![Prompt::Choice(rock_idx),
+                           Prompt::Tile(Point::new(4, 0)),
+                           Prompt::Choice(0),
+                           Prompt::Text("2".to_string())];
+
+        let mut recorder = Recorder::new(&context.state.world).unwrap();
+        context.playback = Some(prompts.iter().cloned().collect());
+        context.recorder = Some(recorder);
+        for code in keys.iter() {
+            let key = Key::from(*code);
+            state::game_step(&mut context, Some(key));
+        }
+        recorder = context.recorder.take().unwrap();
+        let replay = recorder.finish(&context.state.world);
+        assert_eq!(replay.keys.len(), keys.len());
+        assert_eq!(replay.prompts, prompts);
+
+        let mut other = test_context_bounded(64, 64);
+        let summary = verify(&mut other, &replay).unwrap();
+        assert_eq!(Some(summary), replay.expected);
+
+        let world = &other.state.world;
+        let player = world.player().unwrap();
+        assert_eq!(world.position(rock), Some(Point::new(4, 0)));
+        assert_eq!(world.entities_in(player).len(), 1);
+        assert_eq!(world.ecs().items.get_or_err(world.entities_in(player)[0]).count, 1);
+    }
+
+    #[test]
+    fn test_replay_leaves_map() {
+        let mut context = test_context_bounded(64, 64);
+        context.state.world.place_stairs(StairDir::Descending, Point::new(1, 0), 114, POINT_ZERO);
+
+        let mut recorder = Recorder::new(&context.state.world).unwrap();
+        recorder.record(Key::from(KeyCode::L));
+        recorder.record(Key::from(KeyCode::Comma));
+        let replay = recorder.finish(&context.state.world);
+
+        // The player's own slot is left alone.
+        serial::set_slot("replay_test");
+        serial::save_manifest(&context.state.world).unwrap();
+
+        let mut other = test_context_bounded(64, 64);
+        assert!(verify(&mut other, &replay).is_err());
+        assert_eq!(serial::current_slot(), "replay_test");
+        assert!(serial::manifest_exists());
+
+        let player = other.state.world.player().unwrap();
+        assert_eq!(other.state.world.position(player), Some(Point::new(1, 0)));
+
+        serial::delete_slot("replay_test").unwrap();
+    }
+}
@@ -0,0 +1,342 @@
+//! `sabi-save`, a tool for looking inside save slots. It can dump the manifest,
+//! each map's world and the chunks in its region files to JSON or TOML, encode
+//! an edited dump back into a save file, and report on or compact the region
+//! files.
+//!
+//! ```text
+//! sabi-save dump <slot directory> <output directory> [--toml]
+//! sabi-save encode-manifest <manifest.json> <manifest.bin>
+//! sabi-save encode-world <world.json> <world.bin>
+//! sabi-save stats <slot directory>
+//! sabi-save compact <slot directory>
+//! ```
+
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json;
+use toml;
+
+use terrain::regions::{RegionStats, Regions};
+use world::World;
+use world::migration::SaveKind;
+use world::serial::{self, SaveManifest};
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Json,
+    Toml,
+}
+
+impl Format {
+    fn from_path(path: &Path) -> Format {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => Format::Toml,
+            _ => Format::Json,
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match *self {
+            Format::Json => "json",
+            Format::Toml => "toml",
+        }
+    }
+}
+
+type ToolResult<T> = Result<T, String>;
+
+fn usage() -> ! {
+    println!("Usage:");
+    println!("  sabi-save dump <slot directory> <output directory> [--toml]");
+    println!("  sabi-save encode-manifest <manifest.json> <manifest.bin>");
+    println!("  sabi-save encode-world <world.json> <world.bin>");
+    println!("  sabi-save stats <slot directory>");
+    println!("  sabi-save compact <slot directory>");
+    process::exit(1);
+}
+
+pub fn main() {
+    let args: Vec<String> = ::std::env::args().collect();
+    let arg = |i: usize| args.get(i).map(|s| s.as_str()).unwrap_or_else(|| usage());
+
+    let result = match arg(1) {
+        "dump" => {
+            let format = if args.iter().any(|a| a == "--toml") { Format::Toml } else { Format::Json };
+            dump(Path::new(arg(2)), Path::new(arg(3)), format)
+        },
+        "encode-manifest" => encode::<SaveManifest>(Path::new(arg(2)), Path::new(arg(3))),
+        "encode-world" => encode::<World>(Path::new(arg(2)), Path::new(arg(3))),
+        "stats" => region_stats(Path::new(arg(2)), false),
+        "compact" => region_stats(Path::new(arg(2)), true),
+        _ => usage(),
+    };
+
+    if let Err(e) = result {
+        println!("{}", e);
+        process::exit(1);
+    }
+}
+
+/// Converts a JSON value to TOML. Keys set to null are left out, which reads
+/// back as `None`.
+fn json_to_toml(value: serde_json::Value) -> ToolResult<Option<toml::Value>> {
+    use serde_json::Value as Json;
+
+    let converted = match value {
+        Json::Null => return Ok(None),
+        Json::Bool(b) => toml::Value::Boolean(b),
+        Json::Number(n) => {
+            match n.as_i64() {
+                Some(i) => toml::Value::Integer(i),
+                None => toml::Value::Float(n.as_f64().ok_or(format!("{} doesn't fit in TOML", n))?),
+            }
+        },
+        Json::String(s) => toml::Value::String(s),
+        Json::Array(values) => {
+            let mut array = Vec::new();
+            for value in values.into_iter() {
+                array.push(json_to_toml(value)?.ok_or("TOML arrays can't hold null")?);
+            }
+            toml::Value::Array(array)
+        },
+        Json::Object(map) => {
+            let mut table = toml::value::Table::new();
+            for (key, value) in map.into_iter() {
+                if let Some(value) = json_to_toml(value)? {
+                    table.insert(key, value);
+                }
+            }
+            toml::Value::Table(table)
+        },
+    };
+
+    Ok(Some(converted))
+}
+
+fn to_text<T: Serialize>(value: &T, format: Format) -> ToolResult<String> {
+    match format {
+        Format::Json => serde_json::to_string_pretty(value).map_err(|e| e.to_string()),
+        // Going through a JSON value turns map keys like enum variants into
+        // strings, which TOML requires. Going through a `toml::Value` puts
+        // tables after plain values, which TOML also requires but the structs
+        // being dumped don't follow.
+        Format::Toml => {
+            let json = serde_json::to_value(value).map_err(|e| e.to_string())?;
+            let toml = json_to_toml(json)?.unwrap_or(toml::Value::Table(toml::value::Table::new()));
+            toml::to_string(&toml).map_err(|e| e.to_string())
+        },
+    }
+}
+
+fn from_text<T: DeserializeOwned>(text: &str, format: Format) -> ToolResult<T> {
+    match format {
+        Format::Json => serde_json::from_str(text).map_err(|e| e.to_string()),
+        Format::Toml => toml::from_str(text).map_err(|e| e.to_string()),
+    }
+}
+
+fn write_text<T: Serialize>(value: &T, dir: &Path, name: &str, format: Format) -> ToolResult<()> {
+    let text = to_text(value, format)?;
+    let path = dir.join(format!("{}.{}", name, format.extension()));
+    let mut file = File::create(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    file.write_all(text.as_bytes()).map_err(|e| format!("{}: {}", path.display(), e))?;
+    println!("Wrote {}", path.display());
+    Ok(())
+}
+
+/// Dumps everything in a save slot, mirroring its layout.
+fn dump(slot_dir: &Path, out_dir: &Path, format: Format) -> ToolResult<()> {
+    fs::create_dir_all(out_dir).map_err(|e| e.to_string())?;
+
+    let manifest: SaveManifest = serial::read_save(&slot_dir.join("manifest.bin"), SaveKind::Manifest)
+        .map_err(|e| format!("manifest: {}", e))?;
+    write_text(&manifest, out_dir, "manifest", format)?;
+
+    let entries = fs::read_dir(slot_dir).map_err(|e| e.to_string())?;
+    for entry in entries.filter_map(|e| e.ok()) {
+        let map_dir = entry.path();
+        if !map_dir.is_dir() {
+            continue;
+        }
+
+        let map_name = entry.file_name().to_string_lossy().into_owned();
+        let map_out = out_dir.join(&map_name);
+        fs::create_dir_all(&map_out).map_err(|e| e.to_string())?;
+
+        // Keep going if one map is broken, so the rest can still be looked at.
+        if let Err(e) = dump_map(&map_dir, &map_out, format) {
+            println!("Map {}: {}", map_name, e);
+        }
+    }
+
+    Ok(())
+}
+
+fn dump_map(map_dir: &Path, out_dir: &Path, format: Format) -> ToolResult<()> {
+    let world: World = serial::read_save(&map_dir.join("world.bin"), SaveKind::World)
+        .map_err(|e| e.to_string())?;
+    write_text(&world, out_dir, "world", format)?;
+
+    let chunk_dir = out_dir.join("chunks");
+    fs::create_dir_all(&chunk_dir).map_err(|e| e.to_string())?;
+
+    let entries = fs::read_dir(map_dir).map_err(|e| e.to_string())?;
+    for entry in entries.filter_map(|e| e.ok()) {
+        let filename = entry.file_name().to_string_lossy().into_owned();
+        let index = match Regions::parse_region_filename(&filename) {
+            Some(index) => index,
+            None => continue,
+        };
+
+        for (chunk_index, chunk) in Regions::read_region_file(&entry.path(), &index) {
+            let name = format!("{}.{}", chunk_index.0.x, chunk_index.0.y);
+            write_text(&chunk.chunk, &chunk_dir, &name, format)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn map_dirs(slot_dir: &Path) -> ToolResult<Vec<(String, PathBuf)>> {
+    let entries = fs::read_dir(slot_dir).map_err(|e| e.to_string())?;
+    let mut dirs: Vec<_> = entries.filter_map(|e| e.ok())
+        .filter(|e| e.path().is_dir())
+        .map(|e| (e.file_name().to_string_lossy().into_owned(), e.path()))
+        .collect();
+    dirs.sort();
+    Ok(dirs)
+}
+
+fn print_stats(name: &str, stats: &RegionStats) {
+    println!("{:>10} {:>8} {:>8} {:>12} {:>12} {:>7.1}%",
+             name, stats.regions, stats.chunks, stats.file_bytes, stats.used_bytes,
+             stats.fragmentation() * 100.0);
+}
+
+/// Prints how much space each map's region files take up, optionally
+/// compacting them first.
+fn region_stats(slot_dir: &Path, compact: bool) -> ToolResult<()> {
+    println!("{:>10} {:>8} {:>8} {:>12} {:>12} {:>8}",
+             "map", "regions", "chunks", "bytes", "used", "wasted");
+
+    let mut total = RegionStats::default();
+    for (name, dir) in map_dirs(slot_dir)? {
+        if compact {
+            Regions::compact_map(&dir, 0.0).map_err(|e| format!("Map {}: {:?}", name, e))?;
+        }
+
+        let stats = Regions::map_stats(&dir).map_err(|e| format!("Map {}: {:?}", name, e))?;
+        print_stats(&name, &stats);
+        total = total + stats;
+    }
+
+    print_stats("total", &total);
+    Ok(())
+}
+
+/// Reads an edited dump and writes it out as a save file. The old save file
+/// is kept as a backup.
+fn encode<T: Serialize + DeserializeOwned>(input: &Path, output: &Path) -> ToolResult<()> {
+    let mut text = String::new();
+    File::open(input)
+        .and_then(|mut f| f.read_to_string(&mut text))
+        .map_err(|e| format!("{}: {}", input.display(), e))?;
+
+    let value: T = from_text(&text, Format::from_path(input))?;
+    serial::write_save(output, &value).map_err(|e| e.to_string())?;
+
+    println!("Wrote {}", output.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ecs;
+    use graphics::cell::{CellFeature, StairDest, StairDir};
+    use point::Point;
+    use prefab::PrefabMarker;
+    use world::Bounds;
+    use world::flags::GlobalFlags;
+    use world::registry::MapInfo;
+    use world::serial::SlotMeta;
+    use world::traits::*;
+
+    #[test]
+    fn test_manifest_roundtrip() {
+        let mut globals = GlobalFlags::new();
+        globals.record_kill("putit");
+        globals.visit(3);
+        globals.maps.register(MapInfo {
+            id: 3,
+            name: "Overworld".to_string(),
+            prefab: None,
+            depth: 0,
+            parent: None,
+            bounds: Bounds::Unbounded,
+        });
+
+        let manifest = SaveManifest {
+            globals: globals,
+            map_id: 3,
+            meta: SlotMeta {
+                character_name: "dood".to_string(),
+                turns: 42,
+                last_played: 0,
+            },
+        };
+
+        for format in [Format::Json, Format::Toml].iter() {
+            let text = to_text(&manifest, *format).unwrap();
+            let decoded: SaveManifest = from_text(&text, *format).unwrap();
+            assert_eq!(decoded.map_id, 3);
+            assert_eq!(decoded.meta.character_name, "dood");
+            assert_eq!(decoded.meta.turns, 42);
+            assert_eq!(decoded.globals, manifest.globals);
+        }
+    }
+
+    #[test]
+    fn test_world_roundtrip() {
+        serial::delete_world_if_exists(110).unwrap();
+        let mut world = World::new()
+            .with_bounds(Bounds::Bounded(64, 64))
+            .with_id(110)
+            .build()
+            .unwrap();
+
+        let player = world.create(ecs::prefab::player(100), Point::new(1, 1));
+        world.set_player(Some(player));
+        let archer = world.create(ecs::prefab::ranged_mob("archer", 50, "putit"), Point::new(5, 5));
+        let bow = world.create(ecs::prefab::ranged_weapon("bow", "cola"), Point::new(0, 0));
+        world.place_entity_in(player, bow);
+
+        let stairs = Point::new(3, 4);
+        world.cell_mut(&stairs).unwrap().feature =
+            Some(CellFeature::Stairs(StairDir::Descending, StairDest::Ungenerated));
+        world.terrain_mut().markers.insert(stairs, PrefabMarker::StairsIn);
+
+        for format in [Format::Json, Format::Toml].iter() {
+            let text = to_text(&world, *format).unwrap();
+            let mut decoded: World = from_text(&text, *format).unwrap();
+
+            assert_eq!(decoded.entities().len(), world.entities().len());
+            assert_eq!(decoded.position(archer), Some(Point::new(5, 5)));
+            assert_eq!(decoded.entities_in(player), vec![bow]);
+            assert_eq!(decoded.turn_order().get_time_for(archer).ok(),
+                       world.turn_order().get_time_for(archer).ok());
+            assert_eq!(decoded.terrain().chunk_count(), world.terrain().chunk_count());
+            assert_eq!(decoded.terrain().markers.get(&stairs), Some(&PrefabMarker::StairsIn));
+
+            match decoded.cell(&stairs).and_then(|c| c.feature) {
+                Some(CellFeature::Stairs(StairDir::Descending, StairDest::Ungenerated)) => (),
+                other => panic!("Stairs weren't kept: {:?}", other),
+            }
+        }
+    }
+}
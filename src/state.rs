@@ -6,16 +6,28 @@ use GameContext;
 use ai;
 use chunk::generator::ChunkType;
 use engine::keys::Key;
+use event;
+use logic::activity::{self, Watch};
+use logic::death;
 use logic::command::{self, Command, CommandError};
 use logic::{self, Action};
 use stats;
-use world::serial::SaveManifest;
+use world::flags::{GameMode, Home};
+use world::serial::SaveResult;
 use world::traits::*;
 use world::{self, Bounds, World, WorldPosition};
 
+/// How many turns pass between autosaves in a normal game.
+pub const AUTOSAVE_TURNS: u32 = 100;
+
 pub struct GameState {
     pub world: World,
     action_queue: VecDeque<Action>,
+
+    /// Turns between autosaves, or `None` to never autosave.
+    pub autosave_interval: Option<u32>,
+    /// The world's turn count when it was last saved.
+    last_save_turn: u64,
 }
 
 impl GameState {
@@ -25,9 +37,12 @@ impl GameState {
                 .with_bounds(Bounds::Unbounded)
                 .with_chunk_type(ChunkType::Perlin)
                 .with_randomized_seed()
+                .with_name("Overworld")
                 .build()
                 .unwrap(),
             action_queue: VecDeque::new(),
+            autosave_interval: None,
+            last_save_turn: 0,
         }
     }
 
@@ -49,11 +64,15 @@ impl GameState {
 pub fn game_step(context: &mut GameContext, input: Option<Key>) {
     let dead = check_player_dead(&mut context.state.world);
     if dead {
-        restart_game(context);
+        on_player_death(context);
         return;
     }
 
     if let Some(key) = input {
+        if let Some(ref mut recorder) = context.recorder {
+            recorder.record(key);
+        }
+
         let command = Command::from(key);
         run_command(context, command);
     }
@@ -88,14 +107,80 @@ fn check_player_dead(world: &mut World) -> bool {
     res
 }
 
+fn on_player_death(context: &mut GameContext) {
+    match context.state.world.flags().globals.mode {
+        GameMode::Normal => respawn_player(context),
+        GameMode::Permadeath => restart_game(context),
+    }
+}
+
+/// Brings the player back at their home, which might be on another map.
+fn respawn_player(context: &mut GameContext) {
+    let world = &mut context.state.world;
+
+    let player = match death::dead_player(world) {
+        Some(p) => p,
+        None => return,
+    };
+
+    let home = world.flags().globals.home.unwrap_or(Home {
+        map_id: world.map_id(),
+        pos: WorldPosition::new(1, 1),
+    });
+
+    death::revive(world, player);
+
+    if home.map_id != world.map_id() {
+        match world::serial::load_world(home.map_id) {
+            Ok(home_world) => {
+                world.move_to_map(home_world, home.pos).unwrap();
+            },
+            Err(e) => {
+                warn!(world.logger, "Couldn't load home map {}: {}", home.map_id, e);
+                world.respawn_entity(player, home.pos);
+            },
+        }
+    } else {
+        world.respawn_entity(player, home.pos);
+    }
+
+    mes!(world, "You wake up back at home, feeling weaker.");
+    world.update_camera();
+}
+
 fn run_action_queue(context: &mut GameContext) {
     while let Some(action) = context.state.action_queue.pop_front() {
-        context.state.player_action(action)
+        context.state.player_action(action);
+
+        if context.state.action_queue.is_empty() {
+            break;
+        }
+
+        // Let everything else act before the next queued action, and abort
+        // the rest of the queue if something happened to the player.
+        let player = match context.state.world.player() {
+            Some(p) => p,
+            None => break,
+        };
+        let watch = Watch::new(&context.state.world, player);
+
+        process(context);
+
+        if !context.state.world.is_alive(player) ||
+            watch.check(&context.state.world, player).is_some()
+        {
+            context.state.clear_actions();
+        }
     }
 }
 
 fn process_action(world: &mut World, entity: Entity, action: Action) {
     logic::run_action(world, entity, action);
+    process_events(world);
+
+    if world.is_player(entity) {
+        world.pass_turn();
+    }
 
     if world.is_alive(entity) {
         let delay = stats::formulas::calculate_delay(world, entity, 100);
@@ -112,17 +197,45 @@ fn process_actors(world: &mut World) {
         let leftover_ticks = world.turn_order().get_time_for(entity).unwrap();
         if leftover_ticks > 0 {
             world.advance_time(leftover_ticks);
+            logic::status::update(world, leftover_ticks);
+            process_events(world);
+
+            if check_player_dead(world) {
+                break;
+            }
+
+            // Status effects could have killed the entity whose turn it was.
+            if !world.is_alive(entity) {
+                continue;
+            }
         }
 
         if world.is_player(entity) {
             world.next_message();
 
+            // Keep taking turns for the player until the activity finishes or
+            // is interrupted.
+            if let Some(action) = activity::next_action(world, entity) {
+                process_action(world, entity, action);
+                activity::refresh(world, entity);
+
+                if check_player_dead(world) {
+                    break;
+                }
+                continue;
+            }
+
             break;
         }
 
-        if let Some(action) = ai::run(entity, world) {
+        let action = match activity::next_action(world, entity) {
+            Some(action) => Some(action),
+            None => ai::run(entity, world),
+        };
+
+        if let Some(action) = action {
             process_action(world, entity, action);
-            process_events(world);
+            activity::refresh(world, entity);
         }
 
         if check_player_dead(world) {
@@ -133,16 +246,8 @@ fn process_actors(world: &mut World) {
     world.purge_dead();
 }
 
-fn process_events(_world: &mut World) {
-    // let mut responses = event::check_all(world);
-    // while responses.len() != 0 {
-    //     world.events.clear();
-    //     while let Some((action, id)) = responses.pop() {
-    //         // FIXME: don't delay actors here.
-    //         logic::run_action(world, &id, action);
-    //     }
-    //     responses.extend(event::check_all(world));
-    // }
+fn process_events(world: &mut World) {
+    event::process_events(world);
 }
 
 
@@ -155,37 +260,70 @@ fn update_world(context: &mut GameContext) {
 pub fn process(context: &mut GameContext) {
     update_world(context);
     process_actors(&mut context.state.world);
+    autosave(context);
+}
+
+fn autosave(context: &mut GameContext) {
+    let interval = match context.state.autosave_interval {
+        Some(i) => i,
+        None => return,
+    };
+
+    let turns = context.state.world.turns();
+    if turns.saturating_sub(context.state.last_save_turn) < interval as u64 {
+        return;
+    }
+    context.state.last_save_turn = turns;
+
+    let world = &mut context.state.world;
+    if let Err(e) = world::serial::quicksave(world) {
+        warn!(world.logger, "Autosave failed: {}", e);
+        mes!(world, "Autosave failed!");
+    }
 }
 
 pub fn init_headless(context: &mut GameContext) {
     context.state.world.on_load();
 }
 
-pub fn load_context() -> GameContext {
-    let manifest = match world::serial::load_manifest() {
-        Ok(m) => m,
-        Err(_) => SaveManifest::new(),
-    };
-
+/// Loads the saved game, or starts a new one if there is no save. A save that
+/// exists but can't be loaded is an error, so it isn't overwritten by accident.
+pub fn load_context() -> SaveResult<GameContext> {
     let mut context = GameContext::new();
 
-    if let Ok(world) = world::serial::load_world(manifest.map_id) {
-        context.state.world = world;
+    if world::serial::manifest_exists() {
+        let manifest = world::serial::load_manifest()?;
+        context.state.world = world::serial::load_world(manifest.map_id)?;
     } else {
         let e = context.state.world.create(
-            ::ecs::prefab::mob("player", 10000, "player"),
+            ::ecs::prefab::player(10000),
             WorldPosition::new(1, 1),
         );
         context.state.world.set_player(Some(e));
+
+        let home = Home {
+            map_id: context.state.world.map_id(),
+            pos: WorldPosition::new(1, 1),
+        };
+        context.state.world.flags_mut().globals.home = Some(home);
+        context.state.world.flags_mut().globals.visit(home.map_id);
     }
 
+    context.state.autosave_interval = Some(AUTOSAVE_TURNS);
+    context.state.last_save_turn = context.state.world.turns();
+
     init(&mut context);
-    context
+    Ok(context)
 }
 
+/// Deletes the save and starts over in the same slot and game mode.
 pub fn restart_game(context: &mut GameContext) {
+    let mode = context.state.world.flags().globals.mode;
+
     world::serial::wipe_save();
-    *context = load_context();
+    *context = load_context().expect("Couldn't start a new game after wiping the save");
+
+    context.state.world.flags_mut().globals.mode = mode;
 }
 
 pub fn init(context: &mut GameContext) {
@@ -2,9 +2,11 @@ use std::fmt::{self, Display};
 
 use calx_ecs::Entity;
 use ecs::traits::*;
-use rand;
+use item::Burden;
+use rand::Rng;
 use rand::distributions::{Range, IndependentSample};
 
+use world::flags::RngStream;
 use world::traits::Query;
 use world::World;
 
@@ -25,14 +27,13 @@ impl Dice {
         }
     }
 
-    pub fn roll(&self) -> u32 {
-        let mut rng = rand::thread_rng();
+    pub fn roll<R: Rng>(&self, rng: &mut R) -> u32 {
         let lower = self.bonus;
         let upper = lower + self.sides + 1;
         let range = Range::new(lower, upper);
         let mut result = 0;
         for _ in 0..self.rolls {
-            result += range.ind_sample(&mut rng);
+            result += range.ind_sample(rng);
         }
         result
     }
@@ -50,7 +51,8 @@ impl Display for Dice {
 
 pub fn calculate_delay(world: &World, entity: Entity, action_cost: u32) -> i32 {
     let speed = world.ecs().turns.get_or_err(entity).speed;
-    (100 * action_cost / speed) as i32
+    let burden = world.ecs().invs.map_or(Burden::Unburdened, |i| i.container.burden(), entity);
+    (action_cost * burden.delay_percent() / speed) as i32
 }
 
 pub fn check_evasion(_world: &World, _attacker: Entity, _defender: Entity) -> bool {
@@ -61,5 +63,12 @@ pub fn calculate_damage(world: &World, attacker: Entity, defender: Entity) -> u3
     let dice = Dice::new(2, 4, 4);
     debug_ecs!(world, attacker, "attacking {:?} with {}", defender, dice);
 
-    dice.roll()
+    dice.roll(&mut *world.rng(RngStream::Combat))
+}
+
+pub fn calculate_ranged_damage(world: &World, attacker: Entity, defender: Entity) -> u32 {
+    let dice = Dice::new(1, 6, 2);
+    debug_ecs!(world, attacker, "shooting {:?} with {}", defender, dice);
+
+    dice.roll(&mut *world.rng(RngStream::Combat))
 }
@@ -29,6 +29,8 @@ macro_attr! {
     #[derive(Serialize, Deserialize, Eq, PartialEq, Hash, Clone, Debug, EnumFromStr!)]
     pub enum Prop {
         Explosive,
+        Ranged,
+        Key,
 
         // Test use only.
         TestNum,
@@ -47,6 +49,7 @@ pub enum PropErr {
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Properties {
     // poor-man's polymorphism
+    #[serde(with = "::util::pairs")]
     props: HashMap<Prop, PropType>,
 }
 
@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use world::Bounds;
 
@@ -34,9 +34,17 @@ impl Index for ChunkIndex {
 pub struct Terrain {
     regions: Regions,
 
+    #[serde(with = "::util::pairs")]
     chunks: HashMap<ChunkIndex, Chunk>,
     bounds: Bounds,
 
+    /// Chunks that have changed since they were last written to a region file.
+    #[serde(skip_serializing)]
+    #[serde(skip_deserializing)]
+    #[serde(default = "HashSet::new")]
+    dirty: HashSet<ChunkIndex>,
+
+    #[serde(with = "::util::pairs")]
     pub markers: Markers,
     pub id: u32,
 }
@@ -47,15 +55,41 @@ impl Terrain {
             regions: Regions::new(id),
             chunks: HashMap::new(),
             bounds: bounds,
+            dirty: HashSet::new(),
             markers: Markers::new(),
             id: id,
         }
     }
 
+    pub fn bounds(&self) -> Bounds {
+        self.bounds
+    }
+
     pub fn set_id(&mut self, id: u32) {
         self.id = id;
         self.regions.set_id(id);
     }
+
+    pub fn mark_dirty(&mut self, index: ChunkIndex) {
+        self.dirty.insert(index);
+    }
+
+    /// Returns the loaded chunks that have changed since they were last
+    /// saved, and forgets about them.
+    pub fn take_dirty_chunks(&mut self) -> Vec<ChunkIndex> {
+        let chunks = &self.chunks;
+        self.dirty.drain().filter(|i| chunks.contains_key(i)).collect()
+    }
+
+    /// Takes out all the loaded chunks, leaving the terrain as it is when
+    /// saved. Put them back with `restore_chunks`.
+    pub fn take_chunks(&mut self) -> HashMap<ChunkIndex, Chunk> {
+        ::std::mem::replace(&mut self.chunks, HashMap::new())
+    }
+
+    pub fn restore_chunks(&mut self, chunks: HashMap<ChunkIndex, Chunk>) {
+        self.chunks.extend(chunks);
+    }
 }
 
 impl TerrainQuery for Terrain {
@@ -74,6 +108,9 @@ impl TerrainMutate for Terrain {
     }
 
     fn chunk_mut(&mut self, index: ChunkIndex) -> Option<&mut Chunk> {
+        if self.chunks.contains_key(&index) {
+            self.dirty.insert(index);
+        }
         self.chunks.get_mut(&index)
     }
 
@@ -84,6 +121,8 @@ impl TerrainMutate for Terrain {
     }
 
     fn remove_chunk(&mut self, index: &ChunkIndex) -> Option<Chunk> {
+        // Removed chunks are written out when they're unloaded.
+        self.dirty.remove(index);
         self.chunks.remove(index)
     }
 }
@@ -1,12 +1,60 @@
 use std::collections::{HashMap, HashSet};
-use std::fs;
-use std::path::PathBuf;
+use std::fs::{self, File};
+use std::io::Read;
+use std::ops::Add;
+use std::path::{Path, PathBuf};
 
 use chunk::ChunkIndex;
 use chunk::serial::SerialChunk;
 use infinigen::*;
 use world;
 
+/// Region files start with a sector holding where each chunk is.
+const HEADER_SECTORS: u64 = 1;
+
+/// Each chunk's entry in the header is a three byte sector offset followed by
+/// a one byte sector count.
+const HEADER_ENTRY_BYTES: usize = 4;
+
+/// How much space region files take up, and how much of it compacting them
+/// would give back.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct RegionStats {
+    pub regions: usize,
+    pub chunks: usize,
+    /// The size of the region files on disk.
+    pub file_bytes: u64,
+    /// The size the region files would be if they were compacted.
+    pub used_bytes: u64,
+}
+
+impl RegionStats {
+    pub fn wasted_bytes(&self) -> u64 {
+        self.file_bytes.saturating_sub(self.used_bytes)
+    }
+
+    /// The fraction of the files' size that is wasted, between 0 and 1.
+    pub fn fragmentation(&self) -> f32 {
+        if self.file_bytes == 0 {
+            return 0.0;
+        }
+        self.wasted_bytes() as f32 / self.file_bytes as f32
+    }
+}
+
+impl Add for RegionStats {
+    type Output = RegionStats;
+
+    fn add(self, other: RegionStats) -> RegionStats {
+        RegionStats {
+            regions: self.regions + other.regions,
+            chunks: self.chunks + other.chunks,
+            file_bytes: self.file_bytes + other.file_bytes,
+            used_bytes: self.used_bytes + other.used_bytes,
+        }
+    }
+}
+
 /// Implementation of a region manager.
 #[derive(Serialize, Deserialize)]
 pub struct Regions {
@@ -34,6 +82,157 @@ impl Regions {
         format!("r.{}.{}.sr", index.0, index.1)
     }
 
+    /// Gets the index of a region from the name of its file.
+    pub fn parse_region_filename(filename: &str) -> Option<RegionIndex> {
+        let parts: Vec<&str> = filename.split('.').collect();
+        if parts.len() != 4 || parts[0] != "r" || parts[3] != "sr" {
+            return None;
+        }
+
+        match (parts[1].parse::<i32>(), parts[2].parse::<i32>()) {
+            (Ok(x), Ok(y)) => Some(RegionIndex(x, y)),
+            _ => None,
+        }
+    }
+
+    /// Reads every chunk saved in a region file, for inspecting saves outside
+    /// of a running world. Chunks that were never saved are skipped.
+    pub fn read_region_file(path: &Path, index: &RegionIndex) -> Vec<(ChunkIndex, SerialChunk)> {
+        Regions::read_chunks(path, index, false).unwrap_or_else(|_| Vec::new())
+    }
+
+    /// Reads every chunk saved in a region file. If `strict` is set, a
+    /// chunk that can't be read is an error instead of being skipped.
+    fn read_chunks(path: &Path, index: &RegionIndex, strict: bool)
+                   -> SerialResult<Vec<(ChunkIndex, SerialChunk)>> {
+        let handle = Region::get_region_file(path.to_path_buf());
+
+        let mut region = Region {
+            handle: Box::new(handle),
+            unsaved_chunks: HashSet::new(),
+        };
+
+        let width = SerialChunk::REGION_WIDTH;
+        let mut chunks = Vec::new();
+        for x in 0..width {
+            for y in 0..width {
+                let chunk_index = ChunkIndex::new(index.0 * width + x, index.1 * width + y);
+                let chunk: SerialResult<SerialChunk> = region.read_chunk(&chunk_index);
+                match chunk {
+                    Ok(chunk) => chunks.push((chunk_index, chunk)),
+                    Err(SerialError::NoChunkInSavefile(..)) => (),
+                    Err(e) => {
+                        if strict {
+                            return Err(e);
+                        }
+                    },
+                }
+            }
+        }
+        Ok(chunks)
+    }
+
+    /// Finds the region files in a map's save directory.
+    pub fn region_files_in(dir: &Path) -> Vec<(PathBuf, RegionIndex)> {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => return Vec::new(),
+        };
+
+        entries.filter_map(|e| e.ok())
+            .filter_map(|e| {
+                let filename = e.file_name().to_string_lossy().into_owned();
+                Regions::parse_region_filename(&filename).map(|index| (e.path(), index))
+            })
+            .collect()
+    }
+
+    /// Reads how many sectors each saved chunk in a region file takes up from
+    /// the file's header, without reading the chunks themselves.
+    fn header_sector_counts(path: &Path) -> SerialResult<Vec<u64>> {
+        let width = SerialChunk::REGION_WIDTH as usize;
+        let header_len = (width * width * HEADER_ENTRY_BYTES) as u64;
+
+        let file = File::open(path).map_err(SerialError::from)?;
+        let mut header = Vec::new();
+        file.take(header_len).read_to_end(&mut header).map_err(SerialError::from)?;
+
+        let counts = header.chunks(HEADER_ENTRY_BYTES)
+            .filter(|entry| entry.len() == HEADER_ENTRY_BYTES)
+            .map(|entry| entry[HEADER_ENTRY_BYTES - 1] as u64)
+            .filter(|&count| count > 0)
+            .collect();
+        Ok(counts)
+    }
+
+    /// Measures a region file by the sectors its header says are in use.
+    pub fn region_file_stats(path: &Path) -> SerialResult<RegionStats> {
+        let file_bytes = fs::metadata(path).map_err(SerialError::from)?.len();
+        let sector = SerialChunk::SECTOR_SIZE as u64;
+
+        let counts = Regions::header_sector_counts(path)?;
+        let used_sectors = HEADER_SECTORS + counts.iter().sum::<u64>();
+
+        Ok(RegionStats {
+            regions: 1,
+            chunks: counts.len(),
+            file_bytes: file_bytes,
+            used_bytes: used_sectors * sector,
+        })
+    }
+
+    /// Measures every region file of a map.
+    pub fn map_stats(dir: &Path) -> SerialResult<RegionStats> {
+        let mut stats = RegionStats::default();
+        for (path, _) in Regions::region_files_in(dir) {
+            stats = stats + Regions::region_file_stats(&path)?;
+        }
+        Ok(stats)
+    }
+
+    /// Rewrites a region file with its chunks packed together, giving back
+    /// the sectors left over from chunks that changed size. The region must
+    /// not be loaded while this happens.
+    pub fn compact_region_file(path: &Path, index: &RegionIndex) -> SerialResult<()> {
+        // Leave the file alone if anything in it can't be read, so nothing is
+        // lost.
+        let chunks = Regions::read_chunks(path, index, true)?;
+
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".tmp");
+        let temp = PathBuf::from(temp);
+        if temp.exists() {
+            fs::remove_file(&temp).map_err(SerialError::from)?;
+        }
+
+        {
+            let mut region = Region {
+                handle: Box::new(Region::get_region_file(temp.clone())),
+                unsaved_chunks: chunks.iter().map(|&(i, _)| i).collect(),
+            };
+            for (chunk_index, chunk) in chunks.into_iter() {
+                region.write_chunk(chunk, &chunk_index)?;
+            }
+        }
+
+        fs::rename(&temp, path).map_err(SerialError::from)?;
+        Ok(())
+    }
+
+    /// Compacts the region files of a map that waste more than the given
+    /// fraction of their size.
+    pub fn compact_map(dir: &Path, threshold: f32) -> SerialResult<RegionStats> {
+        let mut compacted = RegionStats::default();
+        for (path, index) in Regions::region_files_in(dir) {
+            let stats = Regions::region_file_stats(&path)?;
+            if stats.wasted_bytes() > 0 && stats.fragmentation() > threshold {
+                Regions::compact_region_file(&path, &index)?;
+                compacted = compacted + stats;
+            }
+        }
+        Ok(compacted)
+    }
+
     fn get_region_path(&self, index: &RegionIndex) -> PathBuf {
         let mut save_path = world::serial::get_world_save_dir(self.id);
         fs::create_dir_all(&save_path).unwrap();
@@ -78,3 +277,61 @@ impl<'a> RegionManager<'a, ChunkIndex, SerialChunk> for Regions
         self.regions.contains_key(index)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chunk::CHUNK_WIDTH;
+    use chunk::serial::{set_compression, Compression};
+    use graphics::cell::{CellFeature, StairDest, StairDir};
+    use point::{Point, RectangleIter};
+    use testing::*;
+    use world::serial;
+    use world::traits::*;
+
+    #[test]
+    fn test_compact() {
+        serial::init_paths().unwrap();
+
+        let mut context = test_context_bounded(64, 64);
+        let map_id = 108;
+        let world = &mut context.state.world;
+        world.set_map_id(map_id);
+        serial::save_world(world).unwrap();
+
+        // Grow a chunk past a sector, so it has to be moved to the end of the
+        // file...
+        set_compression(Compression::None);
+        let size = Point::new(CHUNK_WIDTH, CHUNK_WIDTH);
+        for pos in RectangleIter::new(Point::new(0, 0), size) {
+            let stairs = CellFeature::Stairs(StairDir::Descending, StairDest::Generated(1, pos));
+            world.cell_mut(&pos).unwrap().feature = Some(stairs);
+        }
+        serial::save_world(world).unwrap();
+
+        // ...then shrink it again, leaving its old sectors unused.
+        set_compression(Compression::Deflate);
+        world.cell_mut(&Point::new(0, 0)).unwrap().feature = None;
+        serial::save_world(world).unwrap();
+
+        let dir = serial::get_world_save_dir(map_id);
+        let before = Regions::map_stats(&dir).unwrap();
+        assert!(before.wasted_bytes() > 0);
+
+        for (path, index) in Regions::region_files_in(&dir) {
+            Regions::compact_region_file(&path, &index).unwrap();
+        }
+
+        let after = Regions::map_stats(&dir).unwrap();
+        assert_eq!(after.chunks, before.chunks);
+        assert!(after.file_bytes < before.file_bytes);
+
+        // The chunks read back the same as before compacting.
+        let mut loaded = serial::load_world(map_id).unwrap();
+        assert!(loaded.cell(&Point::new(0, 0)).unwrap().feature.is_none());
+        for pos in [Point::new(1, 0), Point::new(CHUNK_WIDTH - 1, CHUNK_WIDTH - 1)].iter() {
+            assert_eq!(loaded.cell(pos).unwrap().stair_dest_pos(), Some(*pos));
+        }
+        assert!(loaded.cell(&Point::new(CHUNK_WIDTH + 1, 0)).unwrap().feature.is_none());
+    }
+}
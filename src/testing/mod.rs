@@ -20,7 +20,7 @@ pub fn blank_world(w: i32, h: i32) -> World {
 
 pub fn get_world_bounded(w: i32, h: i32) -> World {
     let mut world = blank_world(w, h);
-    let e = world.create(ecs::prefab::mob("player", 1000000, "player"), Point::new(0, 0));
+    let e = world.create(ecs::prefab::player(1000000), Point::new(0, 0));
     world.set_player(Some(e));
     world
 }
@@ -6,6 +6,7 @@ pub use self::grid_from_str::make_grid_from_str;
 
 pub mod fov;
 pub mod grammar;
+pub mod pairs;
 pub mod toml;
 #[macro_use]
 pub mod format;
@@ -0,0 +1,90 @@
+//! Saving maps as a sequence of key and value pairs, for use with
+//! `#[serde(with = "util::pairs")]`. JSON and TOML only allow strings as map
+//! keys, so maps keyed by entities or points can't be written as a map. Bincode
+//! writes a map and a sequence of pairs the same way, so switching a map to
+//! this doesn't change how it's saved.
+
+use std::iter::FromIterator;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+#[derive(Serialize)]
+struct PairRef<'a, K: 'a, V: 'a> {
+    key: &'a K,
+    value: &'a V,
+}
+
+#[derive(Deserialize)]
+struct Pair<K, V> {
+    key: K,
+    value: V,
+}
+
+pub fn serialize<'a, M, K, V, S>(map: &'a M, serializer: S) -> Result<S::Ok, S::Error>
+    where &'a M: IntoIterator<Item = (&'a K, &'a V)>,
+          K: Serialize + 'a,
+          V: Serialize + 'a,
+          S: Serializer
+{
+    let pairs: Vec<PairRef<K, V>> = map.into_iter()
+        .map(|(k, v)| PairRef { key: k, value: v })
+        .collect();
+    pairs.serialize(serializer)
+}
+
+pub fn deserialize<'de, M, K, V, D>(deserializer: D) -> Result<M, D::Error>
+    where M: FromIterator<(K, V)>,
+          K: Deserialize<'de>,
+          V: Deserialize<'de>,
+          D: Deserializer<'de>
+{
+    let pairs: Vec<Pair<K, V>> = Deserialize::deserialize(deserializer)?;
+    Ok(pairs.into_iter().map(|p| (p.key, p.value)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use bincode::{self, Infinite};
+    use serde_json;
+
+    use point::Point;
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct Plain {
+        map: BTreeMap<Point, u32>,
+    }
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct Pairs {
+        #[serde(with = "::util::pairs")]
+        map: BTreeMap<Point, u32>,
+    }
+
+    fn map() -> BTreeMap<Point, u32> {
+        let mut map = BTreeMap::new();
+        map.insert(Point::new(1, 2), 3);
+        map.insert(Point::new(-4, 5), 6);
+        map
+    }
+
+    #[test]
+    fn test_same_as_map_in_bincode() {
+        let plain = bincode::serialize(&Plain { map: map() }, Infinite).unwrap();
+        let pairs = bincode::serialize(&Pairs { map: map() }, Infinite).unwrap();
+        assert_eq!(plain, pairs);
+
+        let decoded: Pairs = bincode::deserialize(&plain).unwrap();
+        assert_eq!(decoded.map, map());
+    }
+
+    #[test]
+    fn test_json() {
+        assert!(serde_json::to_string(&Plain { map: map() }).is_err());
+
+        let text = serde_json::to_string(&Pairs { map: map() }).unwrap();
+        let decoded: Pairs = serde_json::from_str(&text).unwrap();
+        assert_eq!(decoded.map, map());
+    }
+}
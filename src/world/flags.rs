@@ -1,7 +1,11 @@
+use std::cell::{RefCell, RefMut};
+use std::collections::BTreeMap;
+
 use calx_alg::EncodeRng;
 use calx_ecs::Entity;
-use rand::{Rng, SeedableRng, XorShiftRng};
+use rand::{SeedableRng, XorShiftRng};
 use world::MapId;
+use world::registry::MapRegistry;
 
 use point::Point;
 
@@ -12,13 +16,91 @@ pub struct Flags {
     pub camera: Point,
     pub map_id: MapId,
     seed: u32,
-    rng: EncodeRng<XorShiftRng>,
+    rngs: RngStreams,
+}
+
+pub type GameRng = EncodeRng<XorShiftRng>;
+
+/// Separate sources of randomness, so that for example generating a map
+/// doesn't change the outcome of the next attack.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RngStream {
+    Mapgen,
+    Combat,
+    Ai,
+}
+
+impl RngStream {
+    fn index(&self) -> u32 {
+        match *self {
+            RngStream::Mapgen => 0,
+            RngStream::Combat => 1,
+            RngStream::Ai => 2,
+        }
+    }
+
+    /// Gets the seed this stream starts from on the given map.
+    pub fn seed_for(&self, seed: u32, map_id: MapId) -> [u32; 4] {
+        // XorShiftRng can't be seeded with all zeroes.
+        [seed, map_id, self.index(), 0x9E37_79B9]
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct RngStreams {
+    mapgen: RefCell<GameRng>,
+    combat: RefCell<GameRng>,
+    ai: RefCell<GameRng>,
+}
+
+impl RngStreams {
+    fn new(seed: u32, map_id: MapId) -> Self {
+        let make = |stream: RngStream| RefCell::new(SeedableRng::from_seed(stream.seed_for(seed, map_id)));
+        RngStreams {
+            mapgen: make(RngStream::Mapgen),
+            combat: make(RngStream::Combat),
+            ai: make(RngStream::Ai),
+        }
+    }
+
+    fn get(&self, stream: RngStream) -> &RefCell<GameRng> {
+        match stream {
+            RngStream::Mapgen => &self.mapgen,
+            RngStream::Combat => &self.combat,
+            RngStream::Ai => &self.ai,
+        }
+    }
+}
+
+/// What happens when the player dies.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Eq, PartialEq)]
+pub enum GameMode {
+    /// The player comes back at their home, but loses their items and some of
+    /// their health.
+    Normal,
+    /// The save is deleted.
+    Permadeath,
+}
+
+/// Where the player comes back after dying.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Home {
+    pub map_id: MapId,
+    pub pos: Point,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
 pub struct GlobalFlags {
     pub max_map_id: u32,
     pub player: Option<Entity>,
+    pub mode: GameMode,
+    pub home: Option<Home>,
+
+    /// How many of each kind of creature the player has killed, by name.
+    pub kills: BTreeMap<String, u32>,
+    /// The maps the player has been on, in the order they were first visited.
+    pub maps_visited: Vec<MapId>,
+    pub maps: MapRegistry,
 }
 
 impl GlobalFlags {
@@ -26,6 +108,21 @@ impl GlobalFlags {
         GlobalFlags {
             max_map_id: 0,
             player: None,
+            mode: GameMode::Normal,
+            home: None,
+            kills: BTreeMap::new(),
+            maps_visited: Vec::new(),
+            maps: MapRegistry::new(),
+        }
+    }
+
+    pub fn record_kill(&mut self, name: &str) {
+        *self.kills.entry(name.to_string()).or_insert(0) += 1;
+    }
+
+    pub fn visit(&mut self, map_id: MapId) {
+        if !self.maps_visited.contains(&map_id) {
+            self.maps_visited.push(map_id);
         }
     }
 }
@@ -34,19 +131,25 @@ impl Flags {
     pub fn new(seed: u32, map_id: MapId) -> Flags {
         Flags {
             globals: GlobalFlags {
-                player: None,
                 max_map_id: map_id,
+                ..GlobalFlags::new()
             },
             camera: Point::new(0, 0),
             map_id: map_id,
 
             seed: seed,
-            rng: SeedableRng::from_seed([seed, seed, seed, seed]),
+            rngs: RngStreams::new(seed, map_id),
         }
     }
 
     pub fn seed(&self) -> u32 { self.seed }
-    pub fn rng(&mut self) -> &mut Rng { &mut self.rng }
+
+    /// Borrows one of the world's random number generators. Rolls are taken
+    /// while the world is otherwise immutable, like during AI, so the streams
+    /// are kept in `RefCell`s.
+    pub fn rng(&self, stream: RngStream) -> RefMut<GameRng> {
+        self.rngs.get(stream).borrow_mut()
+    }
 
     pub fn get_globals(&self) -> GlobalFlags {
         self.globals.clone()
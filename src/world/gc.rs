@@ -0,0 +1,197 @@
+//! Deleting maps that are no longer needed. Maps nothing leads to anymore,
+//! like debug worlds, would otherwise keep their save directories forever.
+
+use graphics::cell::CellFeature;
+use point::Point;
+use world::serial::{self, SaveResult};
+use world::traits::*;
+use world::{MapId, World};
+
+/// Deletes a map's save files and forgets about it. Stairs that led there are
+/// removed, both on the current map and on any saved map they're on.
+pub fn delete_map(world: &mut World, id: MapId) -> SaveResult<()> {
+    debug!(world.logger, "Deleting map {}", id);
+    serial::delete_world_if_exists(id)?;
+
+    let here = world.map_id();
+    let stairs = world.flags().globals.maps.connections_to(id);
+
+    let mut from_ids: Vec<MapId> = stairs.iter()
+        .map(|c| c.from)
+        .filter(|&from| from != id)
+        .collect();
+    from_ids.sort();
+    from_ids.dedup();
+
+    for from in from_ids.into_iter() {
+        let positions: Vec<Point> = stairs.iter()
+            .filter(|c| c.from == from)
+            .map(|c| c.pos)
+            .collect();
+
+        if from == here {
+            remove_stairs(world, &positions);
+        } else if serial::get_world_save_dir(from).exists() {
+            let mut other = serial::load_world(from)?;
+            remove_stairs(&mut other, &positions);
+            serial::save_world(&mut other)?;
+        }
+    }
+
+    world.flags_mut().globals.maps.remove(id);
+    Ok(())
+}
+
+fn remove_stairs(world: &mut World, positions: &[Point]) {
+    for pos in positions.iter() {
+        if let Some(cell) = world.cell_mut(pos) {
+            if let Some(CellFeature::Stairs(..)) = cell.feature {
+                cell.feature = None;
+            }
+        }
+    }
+}
+
+/// Deletes every known map that can't be reached by stairs from the current
+/// map or the player's home. Returns the maps that were deleted.
+pub fn prune_unreachable(world: &mut World) -> SaveResult<Vec<MapId>> {
+    let mut roots = vec![world.map_id()];
+    if let Some(home) = world.flags().globals.home {
+        roots.push(home.map_id);
+    }
+
+    let unreachable = world.flags().globals.maps.unreachable_from(&roots);
+    for id in unreachable.iter() {
+        delete_map(world, *id)?;
+    }
+
+    Ok(unreachable)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ecs;
+    use graphics::cell::StairDir;
+    use point::POINT_ZERO;
+    use testing::*;
+    use world::Bounds;
+    use world::flags::Home;
+    use world::registry::StairConnection;
+
+    fn test_map(id: MapId) -> World {
+        World::new()
+            .with_bounds(Bounds::Bounded(64, 64))
+            .with_id(id)
+            .build()
+            .unwrap()
+    }
+
+    fn temporary_map(id: MapId) -> World {
+        World::new()
+            .with_bounds(Bounds::Bounded(64, 64))
+            .with_id(id)
+            .temporary()
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_prune_unreachable() {
+        let mut context = test_context_bounded(64, 64);
+        let home = Home { map_id: context.state.world.map_id(), pos: POINT_ZERO };
+        context.state.world.flags_mut().globals.home = Some(home);
+
+        // Nothing leads to a map that was teleported to.
+        context.state.world.move_to_map(test_map(104), POINT_ZERO).unwrap();
+        context.state.world.move_to_map(test_map(105), POINT_ZERO).unwrap();
+        let map = serial::load_world(104).unwrap();
+        context.state.world.move_to_map(map, POINT_ZERO).unwrap();
+        assert!(serial::get_world_save_dir(105).exists());
+
+        let pruned = prune_unreachable(&mut context.state.world).unwrap();
+        assert_eq!(pruned, vec![105]);
+        assert!(!serial::get_world_save_dir(105).exists());
+        assert!(!context.state.world.flags().globals.maps.contains(105));
+    }
+
+    #[test]
+    fn test_unrecorded_stairs() {
+        let mut context = test_context_bounded(64, 64);
+        let home = Home { map_id: context.state.world.map_id(), pos: POINT_ZERO };
+        context.state.world.flags_mut().globals.home = Some(home);
+
+        // Stairs made before connections were recorded.
+        context.state.world.move_to_map(test_map(111), POINT_ZERO).unwrap();
+        context.state.world.place_stairs(StairDir::Descending, Point::new(1, 1), 112, POINT_ZERO);
+        context.state.world.move_to_map(test_map(112), POINT_ZERO).unwrap();
+        assert!(context.state.world.flags().globals.maps.connections_from(111).is_empty());
+
+        let map = serial::load_world(111).unwrap();
+        context.state.world.move_to_map(map, POINT_ZERO).unwrap();
+
+        let connections = context.state.world.flags().globals.maps.connections_from(111);
+        assert!(connections.iter().any(|c| c.to == 112 && c.pos == Point::new(1, 1)));
+
+        let pruned = prune_unreachable(&mut context.state.world).unwrap();
+        assert!(!pruned.contains(&112));
+        assert!(serial::get_world_save_dir(112).exists());
+    }
+
+    #[test]
+    fn test_temporary() {
+        let mut context = test_context_bounded(64, 64);
+        context.state.world.move_to_map(test_map(106), POINT_ZERO).unwrap();
+
+        context.state.world.move_to_map(temporary_map(107), POINT_ZERO).unwrap();
+        assert!(context.state.world.flags().globals.maps.is_temporary(107));
+
+        let map = serial::load_world(106).unwrap();
+        context.state.world.move_to_map(map, POINT_ZERO).unwrap();
+
+        assert!(!serial::get_world_save_dir(107).exists());
+        assert!(!context.state.world.flags().globals.maps.contains(107));
+    }
+
+    #[test]
+    fn test_temporary_stairs_elsewhere() {
+        let mut context = test_context_bounded(64, 64);
+        context.state.world.move_to_map(test_map(115), POINT_ZERO).unwrap();
+
+        let stair_pos = Point::new(1, 1);
+        context.state.world.place_stairs(StairDir::Descending, stair_pos, 116, POINT_ZERO);
+        context.state.world.flags_mut().globals.maps.connect(StairConnection {
+            from: 115,
+            pos: stair_pos,
+            dir: StairDir::Descending,
+            to: 116,
+            dest: POINT_ZERO,
+        });
+
+        context.state.world.move_to_map(temporary_map(116), POINT_ZERO).unwrap();
+
+        // Leaving for a map other than the one the stairs are on.
+        context.state.world.move_to_map(test_map(117), POINT_ZERO).unwrap();
+        assert!(!serial::get_world_save_dir(116).exists());
+
+        let map = serial::load_world(115).unwrap();
+        context.state.world.move_to_map(map, POINT_ZERO).unwrap();
+        assert!(context.state.world.cell_const(&stair_pos).unwrap().feature.is_none());
+        assert!(context.state.world.flags().globals.maps.connections_from(115).is_empty());
+    }
+
+    #[test]
+    fn test_temporary_with_corpse() {
+        let mut context = test_context_bounded(64, 64);
+        context.state.world.move_to_map(temporary_map(118), POINT_ZERO).unwrap();
+
+        let corpse = context.state.world.create(ecs::prefab::corpse("you"), Point::new(2, 2));
+        let item = context.state.world.create(ecs::prefab::item("cola", "cola"), POINT_ZERO);
+        context.state.world.place_entity_in(corpse, item);
+
+        context.state.world.move_to_map(test_map(119), POINT_ZERO).unwrap();
+
+        assert!(serial::get_world_save_dir(118).exists());
+        assert!(!context.state.world.flags().globals.maps.is_temporary(118));
+    }
+}
@@ -0,0 +1,420 @@
+//! Versioning for save files. Every file written by `world::serial` starts
+//! with a `SaveHeader`, followed by the bincode-encoded payload. When the
+//! layout of anything that gets saved changes, bump the format version by
+//! adding a step to `MIGRATIONS` that upgrades the previous version's payload.
+//! New fields are easiest to migrate when added to the end of `World` or
+//! `SaveManifest`, since the step only has to append their default value.
+//!
+//! Fields added to `GlobalFlags` have to be spliced into the middle of the
+//! world, after the parts that come before it, and into the start of the
+//! manifest. Those steps decode the `Ecs`,
+//! `Terrain`, `Spatial` and `TurnOrder` to find where the flags start, so
+//! changing how any of those are saved needs a step of its own that rewrites
+//! the whole world.
+
+use std::collections::BTreeMap;
+
+use bincode::{self, Infinite};
+use calx_ecs::Entity;
+use crypto::digest::Digest;
+use crypto::sha3::Sha3;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use data::TurnOrder;
+use data::spatial::Spatial;
+use ecs::Ecs;
+use terrain::Terrain;
+use world::MapId;
+use world::flags::{GameMode, Home};
+use world::placement::Placements;
+use world::registry::{MapInfo, MapRegistry, StairConnection};
+use world::serial::{SaveError, SaveResult};
+
+const MAGIC: [u8; 4] = *b"SABI";
+
+/// The format version new saves are written with.
+pub const FORMAT_VERSION: u32 = 8;
+
+/// From this format version on, a digest of the payload follows the header.
+const DIGEST_VERSION: u32 = 2;
+
+pub const GAME_VERSION: &'static str = env!("CARGO_PKG_VERSION");
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SaveKind {
+    World,
+    Manifest,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SaveHeader {
+    magic: [u8; 4],
+    pub format_version: u32,
+    pub game_version: String,
+}
+
+impl SaveHeader {
+    pub fn current() -> Self {
+        SaveHeader {
+            magic: MAGIC,
+            format_version: FORMAT_VERSION,
+            game_version: GAME_VERSION.to_string(),
+        }
+    }
+}
+
+/// Upgrades a payload of one format version to the next.
+type Migration = fn(SaveKind, Vec<u8>) -> SaveResult<Vec<u8>>;
+
+/// Migration `i` takes a payload of version `i` to version `i + 1`.
+const MIGRATIONS: [Migration; FORMAT_VERSION as usize] = [
+    migrate_unversioned,
+    migrate_add_digest,
+    migrate_add_turns,
+    migrate_add_game_mode,
+    migrate_add_kills,
+    migrate_add_map_registry,
+    migrate_add_temporary_maps,
+    migrate_add_placements,
+];
+
+/// Saves from before headers were added can't be upgraded. Their payload
+/// isn't laid out like version 1: doors were only open or shut, and the AI,
+/// items, containers and global flags have all changed since. None of those
+/// were marked with a version, so the old layout can't be told apart well
+/// enough to rewrite it.
+fn migrate_unversioned(_kind: SaveKind, _data: Vec<u8>) -> SaveResult<Vec<u8>> {
+    Err(SaveError::TooOld)
+}
+
+/// Version 2 only added the digest, which isn't part of the payload.
+fn migrate_add_digest(_kind: SaveKind, data: Vec<u8>) -> SaveResult<Vec<u8>> {
+    Ok(data)
+}
+
+/// Version 3 added the turn count to the end of the world, and the save slot's
+/// character name, turn count and last played time to the end of the manifest.
+fn migrate_add_turns(kind: SaveKind, mut data: Vec<u8>) -> SaveResult<Vec<u8>> {
+    let added = match kind {
+        SaveKind::World => bincode::serialize(&0u64, Infinite)?,
+        SaveKind::Manifest => bincode::serialize(&(String::new(), 0u64, 0i64), Infinite)?,
+    };
+    data.extend(added);
+    Ok(data)
+}
+
+/// Finds where the global flags start in a payload. They come first in the
+/// manifest.
+fn global_flags_offset(kind: SaveKind, data: &[u8]) -> SaveResult<usize> {
+    if kind == SaveKind::Manifest {
+        return Ok(0);
+    }
+
+    let mut rest = data;
+    {
+        let prefix: bincode::Result<(Ecs, Terrain, Spatial, TurnOrder)> =
+            bincode::deserialize_from(&mut rest, Infinite);
+        prefix.map_err(|e| SaveError::Corrupt(format!("couldn't find the global flags: {}", e)))?;
+    }
+    Ok(data.len() - rest.len())
+}
+
+/// Inserts `added` at the end of the global flags, which were laid out as
+/// `Old` in the previous version.
+fn append_to_global_flags<Old>(kind: SaveKind, data: Vec<u8>, added: Vec<u8>) -> SaveResult<Vec<u8>>
+    where Old: DeserializeOwned
+{
+    let mut rest = &data[global_flags_offset(kind, &data)?..];
+    {
+        let old_globals: bincode::Result<Old> = bincode::deserialize_from(&mut rest, Infinite);
+        old_globals.map_err(|e| SaveError::Corrupt(e.to_string()))?;
+    }
+
+    let split = data.len() - rest.len();
+    let mut upgraded = data[..split].to_vec();
+    upgraded.extend(added);
+    upgraded.extend_from_slice(&data[split..]);
+    Ok(upgraded)
+}
+
+/// Version 4 added the game mode and home to the end of the global flags.
+fn migrate_add_game_mode(kind: SaveKind, data: Vec<u8>) -> SaveResult<Vec<u8>> {
+    let added = bincode::serialize(&(GameMode::Normal, None::<Home>), Infinite)?;
+    append_to_global_flags::<(u32, Option<Entity>)>(kind, data, added)
+}
+
+/// Version 5 added the kill counts and visited maps to the end of the global
+/// flags.
+fn migrate_add_kills(kind: SaveKind, data: Vec<u8>) -> SaveResult<Vec<u8>> {
+    let added = (BTreeMap::<String, u32>::new(), Vec::<MapId>::new());
+    let added = bincode::serialize(&added, Infinite)?;
+    append_to_global_flags::<(u32, Option<Entity>, GameMode, Option<Home>)>(kind, data, added)
+}
+
+/// Version 6 added the map registry to the end of the global flags. Maps from
+/// older saves aren't in it until they're visited again.
+fn migrate_add_map_registry(kind: SaveKind, data: Vec<u8>) -> SaveResult<Vec<u8>> {
+    let added = bincode::serialize(&MapRegistry::new(), Infinite)?;
+    append_to_global_flags::<(u32, Option<Entity>, GameMode, Option<Home>,
+                              BTreeMap<String, u32>, Vec<MapId>)>(kind, data, added)
+}
+
+/// Version 7 added the list of temporary maps to the end of the map registry,
+/// which is the last of the global flags.
+fn migrate_add_temporary_maps(kind: SaveKind, data: Vec<u8>) -> SaveResult<Vec<u8>> {
+    let added = bincode::serialize(&Vec::<MapId>::new(), Infinite)?;
+    append_to_global_flags::<(u32, Option<Entity>, GameMode, Option<Home>,
+                              BTreeMap<String, u32>, Vec<MapId>,
+                              (Vec<MapInfo>, Vec<StairConnection>))>(kind, data, added)
+}
+
+/// Version 8 added the prefabs stamped into the world to the end of the
+/// world.
+fn migrate_add_placements(kind: SaveKind, mut data: Vec<u8>) -> SaveResult<Vec<u8>> {
+    if kind == SaveKind::World {
+        data.extend(bincode::serialize(&Placements::new(), Infinite)?);
+    }
+    Ok(data)
+}
+
+fn digest(data: &[u8]) -> String {
+    let mut hasher = Sha3::sha3_256();
+    hasher.input(data);
+    hasher.result_str()
+}
+
+/// Runs every migration needed to bring a payload up to the current version.
+fn migrate(kind: SaveKind, from: u32, mut data: Vec<u8>) -> SaveResult<Vec<u8>> {
+    for version in from..FORMAT_VERSION {
+        data = MIGRATIONS[version as usize](kind, data)?;
+    }
+    Ok(data)
+}
+
+/// Splits a save into its header and payload. Saves without a header are
+/// treated as version 0.
+fn read_header(data: &[u8]) -> SaveResult<(SaveHeader, &[u8])> {
+    if data.len() < MAGIC.len() || data[..MAGIC.len()] != MAGIC {
+        let header = SaveHeader {
+            magic: MAGIC,
+            format_version: 0,
+            game_version: "unknown".to_string(),
+        };
+        return Ok((header, data));
+    }
+
+    let mut payload = data;
+    let header: SaveHeader = bincode::deserialize_from(&mut payload, Infinite)
+        .map_err(|e| SaveError::Corrupt(format!("unreadable save header: {}", e)))?;
+    Ok((header, payload))
+}
+
+pub fn encode<T: Serialize>(value: &T) -> SaveResult<Vec<u8>> {
+    let payload = bincode::serialize(value, Infinite)?;
+
+    let mut data = bincode::serialize(&SaveHeader::current(), Infinite)?;
+    data.extend(bincode::serialize(&digest(&payload), Infinite)?);
+    data.extend(payload);
+    Ok(data)
+}
+
+/// Reads the digest after the header and checks the payload against it.
+fn verify_digest(header: &SaveHeader, data: &[u8]) -> SaveResult<Vec<u8>> {
+    if header.format_version < DIGEST_VERSION {
+        return Ok(data.to_vec());
+    }
+
+    let mut payload = data;
+    let expected: String = bincode::deserialize_from(&mut payload, Infinite)
+        .map_err(|e| SaveError::Corrupt(format!("unreadable digest: {}", e)))?;
+
+    if digest(payload) != expected {
+        return Err(SaveError::Corrupt("digest doesn't match, the save was truncated or damaged"
+                                          .to_string()));
+    }
+
+    Ok(payload.to_vec())
+}
+
+pub fn decode<T: DeserializeOwned>(kind: SaveKind, data: &[u8]) -> SaveResult<T> {
+    let (header, payload) = read_header(data)?;
+
+    if header.format_version > FORMAT_VERSION {
+        return Err(SaveError::TooNew {
+            found: header.format_version,
+            supported: FORMAT_VERSION,
+            game_version: header.game_version,
+        });
+    }
+
+    let payload = verify_digest(&header, payload)?;
+    let payload = migrate(kind, header.format_version, payload)?;
+
+    bincode::deserialize(&payload).map_err(|e| {
+        SaveError::Corrupt(format!("{:?} data of format version {} couldn't be read: {}",
+                                   kind, header.format_version, e))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use testing::*;
+    use world::World;
+    use world::flags::GlobalFlags;
+    use world::serial::SaveManifest;
+    use world::traits::*;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Dood {
+        name: String,
+        hp: u32,
+    }
+
+    fn dood() -> Dood {
+        Dood { name: "Dood".to_string(), hp: 10 }
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let data = encode(&dood()).unwrap();
+        let decoded: Dood = decode(SaveKind::World, &data).unwrap();
+        assert_eq!(decoded, dood());
+    }
+
+    #[test]
+    fn test_unversioned() {
+        let data = bincode::serialize(&dood(), Infinite).unwrap();
+        match decode::<Dood>(SaveKind::World, &data) {
+            Err(SaveError::TooOld) => (),
+            other => panic!("Expected TooOld, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_too_new() {
+        let mut header = SaveHeader::current();
+        header.format_version = FORMAT_VERSION + 1;
+        let mut data = bincode::serialize(&header, Infinite).unwrap();
+        data.extend(bincode::serialize(&dood(), Infinite).unwrap());
+
+        match decode::<Dood>(SaveKind::World, &data) {
+            Err(SaveError::TooNew { found, .. }) => assert_eq!(found, FORMAT_VERSION + 1),
+            other => panic!("Expected TooNew, got {:?}", other),
+        }
+    }
+
+    /// Writes a payload with the header of an older format version.
+    fn encode_as(version: u32, payload: Vec<u8>) -> Vec<u8> {
+        let mut header = SaveHeader::current();
+        header.format_version = version;
+        let mut data = bincode::serialize(&header, Infinite).unwrap();
+        if version >= DIGEST_VERSION {
+            data.extend(bincode::serialize(&digest(&payload), Infinite).unwrap());
+        }
+        data.extend(payload);
+        data
+    }
+
+    fn assert_default_globals(globals: &GlobalFlags) {
+        assert_eq!(globals.mode, GameMode::Normal);
+        assert_eq!(globals.home, None);
+        assert!(globals.kills.is_empty());
+        assert!(globals.maps_visited.is_empty());
+        assert_eq!(globals.maps, MapRegistry::new());
+    }
+
+    #[test]
+    fn test_version_1() {
+        // Version 1 manifests only had the global flags and the map id.
+        let payload = bincode::serialize(&((7u32, None::<Entity>), 3u32), Infinite).unwrap();
+
+        let manifest: SaveManifest = decode(SaveKind::Manifest, &encode_as(1, payload)).unwrap();
+        assert_eq!(manifest.globals.max_map_id, 7);
+        assert_eq!(manifest.map_id, 3);
+        assert_eq!(manifest.meta.character_name, "");
+        assert_eq!(manifest.meta.turns, 0);
+        assert_default_globals(&manifest.globals);
+    }
+
+    #[test]
+    fn test_version_3_manifest() {
+        let old = ((7u32, None::<Entity>), 3u32, ("dood".to_string(), 42u64, 100i64));
+        let payload = bincode::serialize(&old, Infinite).unwrap();
+
+        let manifest: SaveManifest = decode(SaveKind::Manifest, &encode_as(3, payload)).unwrap();
+        assert_eq!(manifest.globals.max_map_id, 7);
+        assert_eq!(manifest.globals.player, None);
+        assert_eq!(manifest.map_id, 3);
+        assert_eq!(manifest.meta.character_name, "dood");
+        assert_eq!(manifest.meta.turns, 42);
+        assert_eq!(manifest.meta.last_played, 100);
+        assert_default_globals(&manifest.globals);
+    }
+
+    /// Lays a world out like version 3 did, when the global flags only held
+    /// the highest map id and the player, and nothing came after the turns.
+    fn version_3_world(world: &World) -> Vec<u8> {
+        let current = bincode::serialize(world, Infinite).unwrap();
+        let offset = global_flags_offset(SaveKind::World, &current).unwrap();
+
+        let mut rest = &current[offset..];
+        let globals: GlobalFlags = bincode::deserialize_from(&mut rest, Infinite).unwrap();
+        let placements = bincode::serialize(&world.placements, Infinite).unwrap();
+        let between = &rest[..rest.len() - placements.len()];
+
+        let mut old = current[..offset].to_vec();
+        old.extend(bincode::serialize(&(globals.max_map_id, globals.player), Infinite).unwrap());
+        old.extend_from_slice(between);
+        old
+    }
+
+    #[test]
+    fn test_version_3_world() {
+        let mut world = get_world_bounded(32, 32);
+        let player = world.player();
+        world.flags_mut().globals.max_map_id = 7;
+        world.flags_mut().globals.record_kill("putit");
+        for _ in 0..42 {
+            world.pass_turn();
+        }
+
+        let payload = version_3_world(&world);
+        let decoded: World = decode(SaveKind::World, &encode_as(3, payload)).unwrap();
+
+        let globals = &decoded.flags().globals;
+        assert_eq!(globals.max_map_id, 7);
+        assert_eq!(globals.player, player);
+        assert_default_globals(globals);
+
+        assert_eq!(decoded.turns(), 42);
+        assert_eq!(decoded.entities().len(), world.entities().len());
+        assert_eq!(decoded.position(player.unwrap()), world.position(player.unwrap()));
+        assert_eq!(decoded.placements.iter().count(), 0);
+    }
+
+    #[test]
+    fn test_corrupt() {
+        let mut data = encode(&dood()).unwrap();
+        let len = data.len();
+        data.truncate(len - 4);
+
+        match decode::<Dood>(SaveKind::World, &data) {
+            Err(SaveError::Corrupt(..)) => (),
+            other => panic!("Expected Corrupt, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_damaged() {
+        let mut data = encode(&dood()).unwrap();
+        let last = data.len() - 1;
+        data[last] ^= 0xFF;
+
+        match decode::<Dood>(SaveKind::World, &data) {
+            Err(SaveError::Corrupt(..)) => (),
+            other => panic!("Expected Corrupt, got {:?}", other),
+        }
+    }
+}
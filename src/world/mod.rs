@@ -3,13 +3,20 @@ mod tests;
 mod bounds;
 mod transition;
 pub mod flags;
+pub mod gc;
+pub mod migration;
+pub mod placement;
+pub mod registry;
 pub mod serial;
 pub mod traits;
 
 pub use self::bounds::Bounds;
-use self::flags::Flags;
+use self::flags::{Flags, GameRng, RngStream};
+use self::placement::Placements;
+use self::registry::{MapInfo, MapRegistry, StairConnection};
 use self::traits::*;
 
+use std::cell::RefMut;
 use std::collections::HashSet;
 use std::slice;
 
@@ -23,14 +30,17 @@ use chunk::generator::ChunkType;
 use chunk::serial::SerialChunk;
 use data::spatial::{Spatial, Place};
 use data::{TurnOrder, Walkability, MessageLog};
+use event::Event;
 use ecs;
 use ecs::*;
 use ecs::components;
 use ecs::traits::*;
 use graphics::Marks;
-use graphics::cell::{CellFeature, StairDir, StairDest};
+use graphics::cell::{CellFeature, DoorState, StairDir, StairDest};
 use log;
+use logic::death;
 use logic::entity::EntityQuery;
+use lua;
 use point::{Direction, Point, POINT_ZERO};
 use prefab::{self, Prefab, PrefabArgs, PrefabMarker};
 use terrain::Terrain;
@@ -63,6 +73,11 @@ pub struct WorldBuilder {
     id: u32,
     max_id: Option<u32>,
     seed: u32,
+    name: Option<String>,
+    depth: i32,
+    parent: Option<MapId>,
+    temporary: bool,
+    maps: MapRegistry,
 }
 
 impl World {
@@ -75,6 +90,11 @@ impl World {
             id: 0,
             max_id: None,
             seed: 1,
+            name: None,
+            depth: 0,
+            parent: None,
+            temporary: false,
+            maps: MapRegistry::new(),
         }
     }
 
@@ -99,8 +119,16 @@ impl World {
         for (pos, marker) in prefab.markers.iter() {
             let offset_pos = *pos + offset;
             debug!(self.logger, "Marker: {:?} {}", marker, offset_pos);
-            if *marker == PrefabMarker::Npc {
-                self.create(ecs::prefab::npc("dude"), offset_pos);
+            match *marker {
+                PrefabMarker::Npc => {
+                    self.create(ecs::prefab::npc("dude"), offset_pos);
+                },
+                PrefabMarker::Door => {
+                    if let Some(cell_mut) = self.cell_mut(&offset_pos) {
+                        cell_mut.feature = Some(CellFeature::Door(DoorState::Closed));
+                    }
+                },
+                _ => (),
             }
         }
 
@@ -114,7 +142,22 @@ impl WorldBuilder {
     pub fn build(&mut self) -> Result<World, String> {
         let mut prefab_opt = None;
 
+        let mut maps = self.maps.clone();
+        maps.register(MapInfo {
+            id: self.id,
+            name: self.name.clone().unwrap_or_else(|| format!("Map {}", self.id)),
+            prefab: self.prefab_name.clone(),
+            depth: self.depth,
+            parent: self.parent,
+            bounds: self.bounds,
+        });
+        if self.temporary {
+            maps.set_temporary(self.id);
+        }
+        lua::set_maps(&maps, self.id);
+
         if let Some(ref prefab_name) = self.prefab_name {
+            lua::reseed(RngStream::Mapgen.seed_for(self.seed, self.id));
             let prefab = prefab::create(prefab_name, &self.prefab_args).map_err(
                 |e| {
                     e.to_string()
@@ -131,9 +174,12 @@ impl WorldBuilder {
             turn_order: TurnOrder::new(),
             flags: Flags::new(self.seed, self.id),
             chunk_type: self.chunk_type.clone(),
+            turns: 0,
+            placements: Placements::new(),
 
             logger: get_world_log(),
             messages: MessageLog::new(),
+            events: Vec::new(),
             marks: Marks::new(),
             debug_overlay: Marks::new(),
         };
@@ -142,6 +188,11 @@ impl WorldBuilder {
             world.flags_mut().globals.max_map_id = max_id;
         }
 
+        if let Some(info) = maps.get_mut(self.id) {
+            info.bounds = self.bounds;
+        }
+        world.flags_mut().globals.maps = maps;
+
         if let Some(prefab) = prefab_opt {
             world.deploy_prefab(&prefab, POINT_ZERO);
         }
@@ -150,10 +201,33 @@ impl WorldBuilder {
     }
 
     pub fn from_other_world<'a>(&'a mut self, other: &World) -> &'a mut Self {
-        let next_id = other.flags().get_globals().max_map_id + 1;
+        let globals = other.flags().get_globals();
+        let next_id = globals.max_map_id + 1;
         self.id = next_id;
         self.max_id = Some(next_id);
         self.seed = other.flags().seed();
+        self.parent = Some(other.map_id());
+        self.depth = globals.maps.get(other.map_id()).map_or(0, |m| m.depth) + 1;
+        self.maps = globals.maps;
+        self
+    }
+
+    pub fn with_name<'a>(&'a mut self, name: &str) -> &'a mut Self {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    /// Makes the map get deleted once the player leaves it, like dungeons
+    /// made by items or quests.
+    pub fn temporary<'a>(&'a mut self) -> &'a mut Self {
+        self.temporary = true;
+        self
+    }
+
+    /// Sets how far below the surface the map is. Maps made from another map
+    /// are one level below it by default.
+    pub fn with_depth<'a>(&'a mut self, depth: i32) -> &'a mut Self {
+        self.depth = depth;
         self
     }
 
@@ -162,11 +236,18 @@ impl WorldBuilder {
         self
     }
 
+    /// Picks a new seed to start a game from. Everything random that happens
+    /// afterwards is decided by this seed.
     pub fn with_randomized_seed<'a>(&'a mut self) -> &'a mut Self {
         self.seed = thread_rng().next_u32();
         self
     }
 
+    pub fn with_seed<'a>(&'a mut self, seed: u32) -> &'a mut Self {
+        self.seed = seed;
+        self
+    }
+
     pub fn with_chunk_type<'a>(&'a mut self, chunk_type: ChunkType) -> &'a mut Self {
         self.chunk_type = chunk_type;
         self
@@ -198,6 +279,12 @@ pub struct World {
 
     chunk_type: ChunkType,
 
+    /// Turns the player has taken since the game started.
+    turns: u64,
+
+    /// The prefabs stamped into the world as its chunks were generated.
+    placements: Placements,
+
     #[serde(skip_serializing)]
     #[serde(skip_deserializing)]
     #[serde(default = "get_world_log")]
@@ -208,6 +295,11 @@ pub struct World {
     #[serde(default = "MessageLog::new")]
     messages: MessageLog,
 
+    #[serde(skip_serializing)]
+    #[serde(skip_deserializing)]
+    #[serde(default = "Vec::new")]
+    events: Vec<Event>,
+
     #[serde(skip_serializing)]
     #[serde(skip_deserializing)]
     #[serde(default = "Marks::new")]
@@ -220,6 +312,23 @@ pub struct World {
 }
 
 impl World {
+    pub fn turns(&self) -> u64 {
+        self.turns
+    }
+
+    pub fn pass_turn(&mut self) {
+        self.turns += 1;
+    }
+
+    /// Puts a killed entity back into the world.
+    pub fn respawn_entity(&mut self, e: Entity, pos: WorldPosition) {
+        self.place_entity(e, pos);
+
+        if self.ecs().turns.has(e) && !self.turn_order.contains(e) {
+            self.turn_order.insert(e, 0).unwrap();
+        }
+    }
+
     pub fn get_messages(&self, count: usize) -> Vec<String> {
         self.messages.get_lines(count)
     }
@@ -227,6 +336,7 @@ impl World {
     #[cfg(test)]
     pub fn message(&mut self, text: &str) {
         println!("MESSAGE: {}", text);
+        self.messages.append(text);
     }
 
     #[cfg(not(test))]
@@ -237,6 +347,35 @@ impl World {
     pub fn next_message(&mut self) {
         self.messages.next_line();
     }
+
+    pub fn message_count(&self) -> usize {
+        self.messages.total()
+    }
+
+    pub fn rng(&self, stream: RngStream) -> RefMut<GameRng> {
+        self.flags.rng(stream)
+    }
+
+    /// Queues an event to be handled after the current action.
+    pub fn push_event(&mut self, event: Event) {
+        self.events.push(event);
+    }
+
+    pub fn take_events(&mut self) -> Vec<Event> {
+        ::std::mem::replace(&mut self.events, Vec::new())
+    }
+
+    /// Counts a kill for the player if they were the last to hurt the killed
+    /// entity. The attacker is cleared so the kill is only counted once.
+    fn credit_kill(&mut self, e: Entity) {
+        let attacker = self.ecs_.healths.get_mut(e).and_then(|h| h.last_attacker.take());
+        if attacker.is_none() || attacker != self.flags.globals.player {
+            return;
+        }
+
+        let name = self.ecs_.names.map_or("something".to_string(), |n| n.name.clone(), e);
+        self.flags.globals.record_kill(&name);
+    }
 }
 
 impl Query for World {
@@ -351,6 +490,12 @@ impl Mutate for World {
     }
 
     fn kill_entity(&mut self, e: Entity) {
+        if self.flags.globals.player == Some(e) && !self.is_alive(e) {
+            death::on_player_killed(self, e);
+        } else if self.is_mob(e) && !self.is_alive(e) {
+            self.credit_kill(e);
+        }
+
         debug!(self.logger, "Marking entity {:?} as killed.", e);
         self.spatial.remove(e);
         let result = self.turn_order.remove(e);
@@ -535,16 +680,27 @@ impl<'a> ChunkedWorld<'a, ChunkIndex, SerialChunk, Regions, Terrain> for World {
                 self.flags.seed(),
             ),
         );
+        self.terrain.mark_dirty(*index);
 
-        let chunk_pos = ChunkPosition::from(Point::new(0, 0));
-        let cell_pos = Chunk::world_position_at(index, &chunk_pos);
-        let stair_pos = cell_pos + (0, 1);
+        self.stamp_prefabs(index);
 
-        if self.can_walk(stair_pos, Walkability::MonstersWalkable) {
-            self.terrain.cell_mut(&stair_pos).unwrap().feature = Some(CellFeature::Stairs(
-                StairDir::Descending,
-                StairDest::Ungenerated,
-            ));
+        if !self.chunk_type.uses_layout() {
+            let chunk_pos = ChunkPosition::from(Point::new(0, 0));
+            let cell_pos = Chunk::world_position_at(index, &chunk_pos);
+            let stair_pos = cell_pos + (0, 1);
+
+            if self.can_walk(stair_pos, Walkability::MonstersWalkable) {
+                self.terrain.cell_mut(&stair_pos).unwrap().feature = Some(CellFeature::Stairs(
+                    StairDir::Descending,
+                    StairDest::Ungenerated,
+                ));
+            }
+        }
+
+        for (pos, entry) in self.chunk_type.spawns(index, self.flags.seed()) {
+            if self.can_walk(pos, Walkability::MonstersBlocking) {
+                self.create(ecs::prefab::mob(entry.name, entry.health, entry.sprite), pos);
+            }
         }
 
         Ok(())
@@ -562,6 +718,22 @@ impl<'a> ChunkedWorld<'a, ChunkIndex, SerialChunk, Regions, Terrain> for World {
 }
 
 impl World {
+    /// Writes the chunks that changed since they were loaded to the region
+    /// files, keeping them loaded.
+    pub fn flush_chunks(&mut self) -> Result<(), SerialError> {
+        let indices = self.terrain.take_dirty_chunks();
+        debug!(self.logger, "Flushing {} chunks...", indices.len());
+        for index in indices.iter() {
+            let chunk = match self.terrain.chunk(*index) {
+                Some(chunk) => chunk.clone(),
+                None => continue,
+            };
+            let region = self.terrain.regions_mut().get_for_chunk(index);
+            region.write_chunk(SerialChunk { chunk: chunk }, index)?;
+        }
+        Ok(())
+    }
+
     pub fn update_chunks(&mut self, center: Point) -> Result<(), SerialError> {
         let mut relevant: HashSet<ChunkIndex> = HashSet::new();
 
@@ -623,8 +795,57 @@ impl World {
         self.update_chunks(center).unwrap();
     }
 
+    /// Adds this map to the map registry if it isn't there, like the maps of
+    /// saves from before there was a registry. Stairs in the loaded part of
+    /// the map are recorded too, in case they were made before stairs were.
+    fn register_map(&mut self) {
+        let id = self.map_id();
+        if !self.flags().globals.maps.contains(id) {
+            let info = MapInfo {
+                id: id,
+                name: format!("Map {}", id),
+                prefab: None,
+                depth: 0,
+                parent: None,
+                bounds: self.terrain.bounds(),
+            };
+            self.flags_mut().globals.maps.register(info);
+        }
+
+        for connection in self.loaded_stairs().into_iter() {
+            self.flags_mut().globals.maps.connect(connection);
+        }
+    }
+
+    /// The stairs leading to other maps in all loaded chunks.
+    fn loaded_stairs(&self) -> Vec<StairConnection> {
+        let id = self.map_id();
+        let mut stairs = Vec::new();
+
+        for index in self.terrain.chunk_indices().into_iter() {
+            let top_left = Point::new(index.0.x * CHUNK_WIDTH, index.0.y * CHUNK_WIDTH);
+            self.with_cells(top_left, Point::new(CHUNK_WIDTH, CHUNK_WIDTH), |pos, cell| {
+                if let Some(CellFeature::Stairs(dir, dest)) = cell.feature {
+                    if let StairDest::Generated(to, dest_pos) = dest {
+                        stairs.push(StairConnection {
+                            from: id,
+                            pos: pos,
+                            dir: dir,
+                            to: to,
+                            dest: dest_pos,
+                        });
+                    }
+                }
+            });
+        }
+
+        stairs
+    }
+
     pub fn on_load(&mut self) {
         self.update_terrain();
+        self.register_map();
+        lua::set_maps(&self.flags().globals.maps, self.map_id());
         self.recalc_entity_fovs();
         self.update_camera();
     }
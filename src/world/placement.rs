@@ -0,0 +1,488 @@
+//! Stamping Lua prefabs into worlds made of generated chunks. The world is
+//! split into sites, each of which may hold one prefab. Sites with a town from
+//! the world layout get a town, and other sites may get something from the
+//! biome's prefab table. Whether a site has one, which one it is and where it
+//! goes only depend on the seed, but the prefab itself has to be generated by
+//! Lua, so what was decided is saved with the world.
+//!
+//! A prefab usually covers several chunks. Each chunk gets its part of the
+//! prefab's cells and markers when it's generated, and is remembered so the
+//! markers aren't realized again. Roads, rivers and dungeon entrances from the
+//! layout are left alone.
+
+use std::cmp;
+use std::collections::HashMap;
+use std::slice;
+
+use chunk::biome::{self, roll, Biome, BiomeNoise};
+use chunk::layout::{ChunkLayout, LayoutIndex, RegionLayout, Town};
+use chunk::{CHUNK_WIDTH, ChunkIndex};
+use ecs;
+use graphics::cell::{CellFeature, DoorState, StairDest, StairDir};
+use lua;
+use point::Point;
+use prefab::{self, Prefab, PrefabMarker};
+use world::traits::*;
+use world::{MapId, World, WorldPosition};
+
+/// The width and height of a site, in cells. Prefabs bigger than this aren't
+/// placed.
+pub const SITE_SIZE: i32 = 64;
+
+/// The chance that a site has a prefab, if there are any that can go there.
+const SITE_CHANCE: f32 = 0.3;
+
+/// The prefab built at each town in the world layout.
+const TOWN_PREFAB: &'static str = "town";
+
+const SALT_SITE: u32 = 3000;
+const SALT_PICK: u32 = 3001;
+const SALT_OFFSET_X: u32 = 3002;
+const SALT_OFFSET_Y: u32 = 3003;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct SiteIndex(pub i32, pub i32);
+
+impl SiteIndex {
+    pub fn from_world_pos(pos: WorldPosition) -> SiteIndex {
+        let div = |a: i32| if a < 0 { (a + 1) / SITE_SIZE - 1 } else { a / SITE_SIZE };
+        SiteIndex(div(pos.x), div(pos.y))
+    }
+
+    pub fn corner(&self) -> WorldPosition {
+        Point::new(self.0 * SITE_SIZE, self.1 * SITE_SIZE)
+    }
+
+    pub fn center(&self) -> WorldPosition {
+        self.corner() + (SITE_SIZE / 2, SITE_SIZE / 2)
+    }
+
+    /// The seed the prefab at this site is generated from.
+    fn lua_seed(&self, seed: u32, map_id: MapId) -> [u32; 4] {
+        // XorShiftRng can't be seeded with all zeroes.
+        [seed, map_id, self.0 as u32, (self.1 as u32) ^ 0x9E37_79B9]
+    }
+}
+
+/// A prefab that was decided to go somewhere in the world.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Placement {
+    pub site: SiteIndex,
+    pub prefab: String,
+    pub pos: WorldPosition,
+    pub size: Point,
+
+    /// The chunks this placement has already been stamped into.
+    stamped: Vec<ChunkIndex>,
+}
+
+impl Placement {
+    pub fn overlaps(&self, index: &ChunkIndex) -> bool {
+        let corner = WorldPosition::from(*index);
+        self.pos.x < corner.x + CHUNK_WIDTH && self.pos.x + self.size.x > corner.x &&
+            self.pos.y < corner.y + CHUNK_WIDTH && self.pos.y + self.size.y > corner.y
+    }
+
+    pub fn is_stamped(&self, index: &ChunkIndex) -> bool {
+        self.stamped.contains(index)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Placements {
+    placed: Vec<Placement>,
+    /// Sites that were looked at and didn't get a prefab.
+    empty: Vec<SiteIndex>,
+
+    /// Prefabs that were generated since the world was loaded. They can be
+    /// generated again from the site's seed.
+    #[serde(skip_serializing)]
+    #[serde(skip_deserializing)]
+    #[serde(default = "HashMap::new")]
+    prefabs: HashMap<SiteIndex, Prefab>,
+}
+
+impl Placements {
+    pub fn new() -> Self {
+        Placements {
+            placed: Vec::new(),
+            empty: Vec::new(),
+            prefabs: HashMap::new(),
+        }
+    }
+
+    pub fn get(&self, site: SiteIndex) -> Option<&Placement> {
+        self.placed.iter().find(|p| p.site == site)
+    }
+
+    fn get_mut(&mut self, site: SiteIndex) -> Option<&mut Placement> {
+        self.placed.iter_mut().find(|p| p.site == site)
+    }
+
+    pub fn is_decided(&self, site: SiteIndex) -> bool {
+        self.get(site).is_some() || self.empty.contains(&site)
+    }
+
+    pub fn iter(&self) -> slice::Iter<Placement> {
+        self.placed.iter()
+    }
+}
+
+/// Turns a marker of a stamped prefab into whatever it stands for. Stairs
+/// into the prefab only mean something when it's the whole map, so both
+/// kinds of stairs lead down from a stamped prefab.
+fn realize_marker(world: &mut World, pos: WorldPosition, marker: &PrefabMarker) {
+    match *marker {
+        PrefabMarker::Npc => {
+            world.create(ecs::prefab::npc("dude"), pos);
+        },
+        PrefabMarker::Mob(ref name) => {
+            match biome::spawn_entry(name) {
+                Some(entry) => {
+                    world.create(ecs::prefab::mob(entry.name, entry.health, entry.sprite), pos);
+                },
+                None => warn!(world.logger, "Prefab placed an unknown mob \"{}\"", name),
+            }
+        },
+        PrefabMarker::Door => {
+            if let Some(cell_mut) = world.terrain_mut().cell_mut(&pos) {
+                cell_mut.feature = Some(CellFeature::Door(DoorState::Closed));
+            }
+        },
+        PrefabMarker::StairsIn | PrefabMarker::StairsOut => {
+            if let Some(cell_mut) = world.terrain_mut().cell_mut(&pos) {
+                cell_mut.feature = Some(CellFeature::Stairs(StairDir::Descending, StairDest::Ungenerated));
+            }
+        },
+        PrefabMarker::Connection => (),
+    }
+}
+
+impl World {
+    fn generate_site_prefab(&self, site: SiteIndex, name: &str) -> Option<Prefab> {
+        lua::reseed(site.lua_seed(self.flags.seed(), self.flags.map_id));
+        match prefab::create(name, &None) {
+            Ok(prefab) => Some(prefab),
+            Err(e) => {
+                warn!(self.logger, "Couldn't create prefab \"{}\" at site {:?}: {}", name, site, e);
+                None
+            },
+        }
+    }
+
+    /// The town the world layout puts in a site, if there is one. Sites never
+    /// straddle layout regions, so only the region the site is in can have
+    /// one.
+    fn site_town(&self, site: SiteIndex) -> Option<Town> {
+        if !self.chunk_type.uses_layout() {
+            return None;
+        }
+
+        let seed = self.flags.seed();
+        let region = RegionLayout::new(LayoutIndex::from_world_pos(site.corner()), seed);
+        match region.town {
+            // The layout isn't drawn out at sea.
+            Some(town) if SiteIndex::from_world_pos(town.center) == site &&
+                BiomeNoise::new(seed).biome_at(town.center) != Biome::Ocean => Some(town),
+            _ => None,
+        }
+    }
+
+    /// Whether the world layout puts a dungeon entrance inside a rectangle
+    /// in one site.
+    fn has_entrance_within(&self, pos: WorldPosition, size: Point) -> bool {
+        if !self.chunk_type.uses_layout() {
+            return false;
+        }
+
+        let region = RegionLayout::new(LayoutIndex::from_world_pos(pos), self.flags.seed());
+        region.dungeon_entrance.map_or(false, |e| {
+            e.x >= pos.x && e.x < pos.x + size.x && e.y >= pos.y && e.y < pos.y + size.y
+        })
+    }
+
+    /// Rolls for which prefab from the biome's table a site gets, if any.
+    fn roll_site_prefab(&self, site: SiteIndex) -> Option<&'static str> {
+        let seed = self.flags.seed();
+        let key = Point::new(site.0, site.1);
+        let table = self.chunk_type.prefabs(site.center(), seed);
+
+        if table.is_empty() || roll(seed, key, SALT_SITE) >= SITE_CHANCE {
+            return None;
+        }
+
+        let total: u32 = table.iter().map(|e| e.weight).sum();
+        let mut pick = (roll(seed, key, SALT_PICK) * total as f32) as u32;
+        let entry = table.iter()
+            .find(|e| if pick < e.weight { true } else { pick -= e.weight; false })
+            .unwrap_or(&table[table.len() - 1]);
+        Some(entry.prefab)
+    }
+
+    /// Decides which prefab a site gets and where it goes. Towns go where the
+    /// world layout put them, and anything else is left to chance.
+    fn plan_site(&self, site: SiteIndex) -> Option<(Placement, Prefab)> {
+        let seed = self.flags.seed();
+        let key = Point::new(site.0, site.1);
+        let town = self.site_town(site);
+
+        let name = match town {
+            Some(_) => TOWN_PREFAB,
+            None => match self.roll_site_prefab(site) {
+                Some(name) => name,
+                None => return None,
+            },
+        };
+
+        let prefab = match self.generate_site_prefab(site, name) {
+            Some(prefab) => prefab,
+            None => return None,
+        };
+
+        let size = Point::new(prefab.width(), prefab.height());
+        if size.x > SITE_SIZE || size.y > SITE_SIZE {
+            warn!(self.logger, "Prefab \"{}\" is too big for a site: {}", name, size);
+            return None;
+        }
+
+        let corner = site.corner();
+        let pos = match town {
+            Some(town) => {
+                // Centered on the town, as far as the site allows.
+                let fit = |center: i32, len: i32, start: i32| {
+                    cmp::max(start, cmp::min(center - len / 2, start + SITE_SIZE - len))
+                };
+                Point::new(fit(town.center.x, size.x, corner.x),
+                           fit(town.center.y, size.y, corner.y))
+            },
+            None => {
+                let offset = |salt, room: i32| (roll(seed, key, salt) * (room + 1) as f32) as i32 % (room + 1);
+                corner + (offset(SALT_OFFSET_X, SITE_SIZE - size.x),
+                          offset(SALT_OFFSET_Y, SITE_SIZE - size.y))
+            },
+        };
+
+        // Towns are built around the stairs, but anything else would wall
+        // them in.
+        if town.is_none() && self.has_entrance_within(pos, size) {
+            debug!(self.logger, "Not placing prefab \"{}\" over a dungeon entrance", name);
+            return None;
+        }
+
+        debug!(self.logger, "Placing prefab \"{}\" at {}", name, pos);
+        let placement = Placement {
+            site: site,
+            prefab: name.to_string(),
+            pos: pos,
+            size: size,
+            stamped: Vec::new(),
+        };
+        Some((placement, prefab))
+    }
+
+    /// Decides whether a site gets a prefab, and where.
+    fn decide_site(&mut self, site: SiteIndex) {
+        match self.plan_site(site) {
+            Some((placement, prefab)) => {
+                self.placements.prefabs.insert(site, prefab);
+                self.placements.placed.push(placement);
+            },
+            None => self.placements.empty.push(site),
+        }
+    }
+
+    /// Stamps the part of a prefab that falls in a newly generated chunk, if
+    /// there is one. The chunk has to be loaded already.
+    pub fn stamp_prefabs(&mut self, index: &ChunkIndex) {
+        let site = SiteIndex::from_world_pos(WorldPosition::from(*index));
+        if !self.placements.is_decided(site) {
+            self.decide_site(site);
+        }
+
+        let (name, offset) = match self.placements.get(site) {
+            Some(p) if p.overlaps(index) && !p.is_stamped(index) => (p.prefab.clone(), p.pos),
+            _ => return,
+        };
+
+        if !self.placements.prefabs.contains_key(&site) {
+            match self.generate_site_prefab(site, &name) {
+                Some(prefab) => { self.placements.prefabs.insert(site, prefab); },
+                None => return,
+            }
+        }
+        let prefab = self.placements.prefabs[&site].clone();
+
+        debug!(self.logger, "Stamping prefab \"{}\" into chunk {}", name, index);
+
+        // Roads, rivers and dungeon entrances from the world layout stay put.
+        let layout = if self.chunk_type.uses_layout() {
+            Some(ChunkLayout::new(index, self.flags.seed()))
+        } else {
+            None
+        };
+        let stampable = |pos: WorldPosition| {
+            let kept = layout.as_ref()
+                .and_then(|l| l.feature_at(pos))
+                .map_or(false, |f| f.is_kept());
+            ChunkIndex::from_world_pos(pos) == *index && !kept
+        };
+
+        for (pos, cell) in prefab.iter() {
+            let world_pos = pos + offset;
+            if stampable(world_pos) {
+                if let Some(cell_mut) = self.terrain_mut().cell_mut(&world_pos) {
+                    *cell_mut = *cell;
+                }
+            }
+        }
+
+        for (pos, marker) in prefab.markers() {
+            let world_pos = *pos + offset;
+            if stampable(world_pos) {
+                realize_marker(self, world_pos, marker);
+            }
+        }
+
+        if let Some(placement) = self.placements.get_mut(site) {
+            placement.stamped.push(*index);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chunk::generator::ChunkType;
+    use chunk::layout::LayoutFeature;
+    use graphics::cell::Cell;
+    use world::Bounds;
+    use world::serial;
+
+    fn placement() -> Placement {
+        Placement {
+            site: SiteIndex(0, 0),
+            prefab: "house".to_string(),
+            pos: Point::new(10, 20),
+            size: Point::new(12, 12),
+            stamped: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_site_index() {
+        assert_eq!(SiteIndex::from_world_pos(Point::new(0, 63)), SiteIndex(0, 0));
+        assert_eq!(SiteIndex::from_world_pos(Point::new(64, -1)), SiteIndex(1, -1));
+        assert_eq!(SiteIndex::from_world_pos(Point::new(-65, 0)), SiteIndex(-2, 0));
+    }
+
+    #[test]
+    fn test_overlaps() {
+        let p = placement();
+        assert!(p.overlaps(&ChunkIndex::new(0, 1)));
+        assert!(p.overlaps(&ChunkIndex::new(1, 1)));
+        assert!(!p.overlaps(&ChunkIndex::new(2, 1)));
+        assert!(!p.overlaps(&ChunkIndex::new(0, 0)));
+        assert!(!p.overlaps(&ChunkIndex::new(0, 2)));
+    }
+
+    #[test]
+    fn test_stamp_once() {
+        serial::delete_world_if_exists(109).unwrap();
+        let mut world = World::new()
+            .with_bounds(Bounds::Unbounded)
+            .with_chunk_type(ChunkType::Biomes)
+            .with_id(109)
+            .build()
+            .unwrap();
+
+        // Find a site with a prefab in it.
+        let mut found = None;
+        for x in 0..16 {
+            for y in 0..16 {
+                let site = SiteIndex(x, y);
+                world.decide_site(site);
+                if let Some(p) = world.placements.get(site) {
+                    found = Some((site, p.clone()));
+                    break;
+                }
+            }
+            if found.is_some() {
+                break;
+            }
+        }
+        let (site, placement) = found.expect("No site got a prefab");
+
+        // Deciding again gives the same placement, even without the cached
+        // prefab.
+        world.placements.placed.clear();
+        world.placements.empty.clear();
+        world.placements.prefabs.clear();
+        world.decide_site(site);
+        assert_eq!(world.placements.get(site).unwrap().pos, placement.pos);
+
+        let index = ChunkIndex::from_world_pos(placement.pos);
+        world.cell(&placement.pos);
+        assert!(world.placements.get(site).unwrap().is_stamped(&index));
+
+        let entities = world.entities().len();
+        world.stamp_prefabs(&index);
+        assert_eq!(world.entities().len(), entities);
+    }
+
+    #[test]
+    fn test_town_keeps_layout() {
+        serial::delete_world_if_exists(113).unwrap();
+        let mut world = World::new()
+            .with_bounds(Bounds::Unbounded)
+            .with_chunk_type(ChunkType::Biomes)
+            .with_id(113)
+            .build()
+            .unwrap();
+        let seed = world.flags.seed();
+        let noise = BiomeNoise::new(seed);
+
+        let mut found = None;
+        for x in 0..16 {
+            for y in 0..16 {
+                let town = RegionLayout::new(LayoutIndex(x, y), seed).town;
+                if let Some(town) = town {
+                    if noise.biome_at(town.center) != Biome::Ocean {
+                        found = Some(town);
+                        break;
+                    }
+                }
+            }
+            if found.is_some() {
+                break;
+            }
+        }
+        let town = found.expect("No town in the layout");
+
+        let site = SiteIndex::from_world_pos(town.center);
+        world.decide_site(site);
+        assert_eq!(world.placements.get(site).unwrap().prefab, TOWN_PREFAB);
+
+        // Whatever the town was built over, the roads, rivers and stairs are
+        // still there.
+        let index = ChunkIndex::from_world_pos(town.center);
+        world.cell(&town.center);
+        let layout = ChunkLayout::new(&index, seed);
+        let corner = WorldPosition::from(index);
+        for i in 0..CHUNK_WIDTH {
+            for j in 0..CHUNK_WIDTH {
+                let pos = corner + (i, j);
+                if noise.biome_at(pos) == Biome::Ocean {
+                    continue;
+                }
+
+                let cell = *world.cell(&pos).unwrap();
+                match layout.feature_at(pos) {
+                    Some(LayoutFeature::Road) => assert_eq!(cell.type_, Cell::new("cobble").type_),
+                    Some(LayoutFeature::River) => assert_eq!(cell.type_, Cell::new("water").type_),
+                    Some(LayoutFeature::DungeonEntrance) => assert!(cell.feature.is_some()),
+                    _ => (),
+                }
+            }
+        }
+    }
+}
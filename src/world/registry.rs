@@ -0,0 +1,262 @@
+//! A record of every map in the game and how they're connected, kept in the
+//! global flags so it travels with the player and ends up in the manifest.
+//! Stairs only know the map they lead to, so this is the one place to look
+//! for how the maps fit together.
+
+use std::collections::{HashSet, VecDeque};
+use std::slice;
+
+use graphics::cell::StairDir;
+use point::Point;
+use world::{Bounds, MapId};
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct MapInfo {
+    pub id: MapId,
+    pub name: String,
+    /// The prefab the map was made from, if any.
+    pub prefab: Option<String>,
+    /// How far below the surface the map is.
+    pub depth: i32,
+    /// The map the player came from when this one was made.
+    pub parent: Option<MapId>,
+    pub bounds: Bounds,
+}
+
+/// A staircase from one map to another.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Eq, PartialEq)]
+pub struct StairConnection {
+    pub from: MapId,
+    pub pos: Point,
+    pub dir: StairDir,
+    pub to: MapId,
+    pub dest: Point,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct MapRegistry {
+    /// Kept sorted by id.
+    maps: Vec<MapInfo>,
+    connections: Vec<StairConnection>,
+    /// Maps that are deleted once the player leaves them.
+    temporary: Vec<MapId>,
+}
+
+impl MapRegistry {
+    pub fn new() -> Self {
+        MapRegistry {
+            maps: Vec::new(),
+            connections: Vec::new(),
+            temporary: Vec::new(),
+        }
+    }
+
+    pub fn register(&mut self, info: MapInfo) {
+        match self.maps.binary_search_by_key(&info.id, |m| m.id) {
+            Ok(i) => self.maps[i] = info,
+            Err(i) => self.maps.insert(i, info),
+        }
+    }
+
+    pub fn get(&self, id: MapId) -> Option<&MapInfo> {
+        self.maps.binary_search_by_key(&id, |m| m.id).ok().map(|i| &self.maps[i])
+    }
+
+    pub fn get_mut(&mut self, id: MapId) -> Option<&mut MapInfo> {
+        match self.maps.binary_search_by_key(&id, |m| m.id) {
+            Ok(i) => Some(&mut self.maps[i]),
+            Err(_) => None,
+        }
+    }
+
+    pub fn contains(&self, id: MapId) -> bool {
+        self.get(id).is_some()
+    }
+
+    /// All known maps, ordered by id.
+    pub fn iter(&self) -> slice::Iter<MapInfo> {
+        self.maps.iter()
+    }
+
+    pub fn ids(&self) -> Vec<MapId> {
+        self.maps.iter().map(|m| m.id).collect()
+    }
+
+    /// Forgets about a map, along with the stairs leading to and from it.
+    pub fn remove(&mut self, id: MapId) {
+        self.maps.retain(|m| m.id != id);
+        self.connections.retain(|c| c.from != id && c.to != id);
+        self.temporary.retain(|&t| t != id);
+    }
+
+    pub fn set_temporary(&mut self, id: MapId) {
+        if !self.temporary.contains(&id) {
+            self.temporary.push(id);
+        }
+    }
+
+    /// Keeps a temporary map around after the player leaves it.
+    pub fn set_permanent(&mut self, id: MapId) {
+        self.temporary.retain(|&t| t != id);
+    }
+
+    pub fn is_temporary(&self, id: MapId) -> bool {
+        self.temporary.contains(&id)
+    }
+
+    /// Records a staircase and the one leading back.
+    pub fn connect(&mut self, connection: StairConnection) {
+        let back = StairConnection {
+            from: connection.to,
+            pos: connection.dest,
+            dir: connection.dir.reverse(),
+            to: connection.from,
+            dest: connection.pos,
+        };
+
+        for c in [connection, back].iter() {
+            if !self.connections.contains(c) {
+                self.connections.push(*c);
+            }
+        }
+    }
+
+    /// The staircases leading out of a map.
+    pub fn connections_from(&self, id: MapId) -> Vec<StairConnection> {
+        self.connections.iter().filter(|c| c.from == id).cloned().collect()
+    }
+
+    /// The staircases leading into a map.
+    pub fn connections_to(&self, id: MapId) -> Vec<StairConnection> {
+        self.connections.iter().filter(|c| c.to == id).cloned().collect()
+    }
+
+    /// Finds every map that can be reached by taking stairs from any of the
+    /// given maps, including those maps.
+    pub fn reachable_from(&self, start: &[MapId]) -> HashSet<MapId> {
+        let mut reached: HashSet<MapId> = start.iter().cloned().collect();
+        let mut queue: VecDeque<MapId> = start.iter().cloned().collect();
+
+        while let Some(id) = queue.pop_front() {
+            for c in self.connections.iter().filter(|c| c.from == id) {
+                if reached.insert(c.to) {
+                    queue.push_back(c.to);
+                }
+            }
+        }
+
+        reached
+    }
+
+    /// The known maps that can't be reached from any of the given maps.
+    pub fn unreachable_from(&self, start: &[MapId]) -> Vec<MapId> {
+        let reached = self.reachable_from(start);
+        self.ids().into_iter().filter(|id| !reached.contains(id)).collect()
+    }
+
+    /// Copies what `other` knows about a map, if it isn't known here. Other
+    /// maps keep their own copy of the registry from when they were saved,
+    /// which may list maps that have since been deleted, so only the one map
+    /// is taken.
+    pub fn adopt(&mut self, other: &MapRegistry, id: MapId) {
+        if self.contains(id) {
+            return;
+        }
+        if let Some(info) = other.get(id) {
+            self.register(info.clone());
+        }
+        if other.is_temporary(id) {
+            self.set_temporary(id);
+        }
+    }
+
+    /// A short description, for listing maps.
+    pub fn describe(&self, id: MapId) -> String {
+        match self.get(id) {
+            Some(info) => format!("{}: {} (depth {})", id, info.name, info.depth),
+            None => format!("{}: (unknown)", id),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(id: MapId, depth: i32) -> MapInfo {
+        MapInfo {
+            id: id,
+            name: format!("Map {}", id),
+            prefab: None,
+            depth: depth,
+            parent: None,
+            bounds: Bounds::Unbounded,
+        }
+    }
+
+    #[test]
+    fn test_connect() {
+        let mut registry = MapRegistry::new();
+        registry.register(info(0, 0));
+        registry.register(info(1, 1));
+        registry.connect(StairConnection {
+            from: 0,
+            pos: Point::new(1, 2),
+            dir: StairDir::Descending,
+            to: 1,
+            dest: Point::new(3, 4),
+        });
+
+        let back = registry.connections_from(1);
+        assert_eq!(back.len(), 1);
+        assert_eq!(back[0].to, 0);
+        assert_eq!(back[0].pos, Point::new(3, 4));
+        assert_eq!(back[0].dir, StairDir::Ascending);
+    }
+
+    fn connect(registry: &mut MapRegistry, from: MapId, to: MapId) {
+        registry.connect(StairConnection {
+            from: from,
+            pos: Point::new(0, 0),
+            dir: StairDir::Descending,
+            to: to,
+            dest: Point::new(0, 0),
+        });
+    }
+
+    #[test]
+    fn test_unreachable() {
+        let mut registry = MapRegistry::new();
+        for id in 0..5 {
+            registry.register(info(id, 0));
+        }
+        connect(&mut registry, 0, 1);
+        connect(&mut registry, 1, 2);
+        connect(&mut registry, 3, 4);
+
+        assert_eq!(registry.unreachable_from(&[2]), vec![3, 4]);
+        assert_eq!(registry.unreachable_from(&[2, 4]), Vec::<MapId>::new());
+
+        registry.remove(1);
+        assert_eq!(registry.unreachable_from(&[2]), vec![0, 3, 4]);
+        assert!(registry.connections_from(0).is_empty());
+    }
+
+    #[test]
+    fn test_adopt() {
+        let mut registry = MapRegistry::new();
+        registry.register(info(0, 0));
+
+        let mut other = MapRegistry::new();
+        other.register(info(0, 5));
+        other.register(info(1, 1));
+        other.register(info(2, 2));
+        other.set_temporary(1);
+        registry.adopt(&other, 0);
+        registry.adopt(&other, 1);
+
+        assert_eq!(registry.ids(), vec![0, 1]);
+        assert_eq!(registry.get(0).unwrap().depth, 0);
+        assert!(registry.is_temporary(1));
+    }
+}
@@ -1,17 +1,33 @@
+use std::fmt;
 use std::fs::{self, File};
-use std::io::{Read, Write};
+use std::io::{self, Read, Write};
 use std::path::{Path, PathBuf};
 
-use bincode::{self, Infinite};
+use bincode;
+use chrono::{Local, TimeZone};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use slog::Logger;
 
 use infinigen::*;
+use log;
 use world::{World, MapId};
+use world::migration::{self, SaveKind};
 use world::traits::*;
 use world::flags::GlobalFlags;
 
 pub const SAVE_DIRECTORY: &'static str = "save";
 
-fn get_save_directory() -> String {
+/// The slot used when none was picked, like in tests.
+pub const DEFAULT_SLOT: &'static str = "default";
+
+lazy_static! {
+    static ref SAVE_LOG: Logger = log::make_logger("save");
+}
+
+make_global!(SAVE_SLOT, String, DEFAULT_SLOT.to_string());
+
+fn get_save_root() -> String {
     if cfg!(test) {
         format!("test/{}", SAVE_DIRECTORY)
     } else {
@@ -19,6 +35,29 @@ fn get_save_directory() -> String {
     }
 }
 
+/// Morgue files are kept outside of the slots, so they survive the save being
+/// deleted.
+pub fn get_morgue_dir() -> PathBuf {
+    PathBuf::from(format!("{}/morgue", get_save_root()))
+}
+
+fn get_slot_directory(slot: &str) -> String {
+    format!("{}/{}", get_save_root(), slot)
+}
+
+fn get_save_directory() -> String {
+    get_slot_directory(&current_slot())
+}
+
+/// The name of the save slot everything is saved to and loaded from.
+pub fn current_slot() -> String {
+    instance::with(|slot| slot.clone())
+}
+
+pub fn set_slot(slot: &str) {
+    instance::with_mut(|s| *s = slot.to_string());
+}
+
 pub fn get_world_save_dir(id: u32) -> PathBuf {
     let savedir = get_save_directory();
     PathBuf::from(format!("{}/{}/", savedir, id))
@@ -30,39 +69,176 @@ fn get_world_savefile(id: u32) -> PathBuf {
     save_dir
 }
 
+fn get_manifest_file_in(slot: &str) -> PathBuf {
+    PathBuf::from(format!("{}/manifest.bin", get_slot_directory(slot)))
+}
+
 fn get_manifest_file() -> PathBuf {
-    PathBuf::from(format!("{}/manifest.bin", get_save_directory()))
+    get_manifest_file_in(&current_slot())
+}
+
+#[derive(Debug)]
+pub enum SaveError {
+    Serial(SerialError),
+    /// The save was written with a newer format than this version supports.
+    TooNew {
+        found: u32,
+        supported: u32,
+        game_version: String,
+    },
+    /// The save is from before save files had a format version. Too much
+    /// changed since then for it to be upgraded.
+    TooOld,
+    Corrupt(String),
 }
 
-// TODO: Allow quicksaving, as in not unloading the entire world first
-pub fn save_world(world: &mut World) -> SerialResult<()> {
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SaveError::Serial(ref e) => write!(f, "Couldn't read or write save: {:?}", e),
+            SaveError::TooNew { found, supported, ref game_version } => {
+                write!(f,
+                       "Save is from a newer version of the game ({}, format {}). \
+                        This version can only load format {} and older.",
+                       game_version,
+                       found,
+                       supported)
+            },
+            SaveError::TooOld => {
+                write!(f,
+                       "Save is from a version of the game before save files were versioned, \
+                        and can't be loaded by this one.")
+            },
+            SaveError::Corrupt(ref reason) => write!(f, "Save is corrupt: {}", reason),
+        }
+    }
+}
+
+impl From<SerialError> for SaveError {
+    fn from(e: SerialError) -> SaveError {
+        SaveError::Serial(e)
+    }
+}
+
+impl From<io::Error> for SaveError {
+    fn from(e: io::Error) -> SaveError {
+        SaveError::Serial(SerialError::from(e))
+    }
+}
+
+impl From<bincode::Error> for SaveError {
+    fn from(e: bincode::Error) -> SaveError {
+        SaveError::Serial(SerialError::from(e))
+    }
+}
+
+pub type SaveResult<T> = Result<T, SaveError>;
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+fn get_backup_file(path: &Path) -> PathBuf {
+    with_suffix(path, ".bak")
+}
+
+/// Writes a save file so that a crash partway through never leaves a broken
+/// file behind. The data goes to a temporary file that is then renamed over
+/// the old save, which is kept as a backup first.
+fn write_file(path: &Path, data: &[u8]) -> SaveResult<()> {
+    let temp_path = with_suffix(path, ".tmp");
+
+    {
+        let mut temp = File::create(&temp_path)?;
+        temp.write_all(data)?;
+        temp.sync_all()?;
+    }
+
+    if path.exists() {
+        fs::copy(path, get_backup_file(path))?;
+    }
+
+    fs::rename(&temp_path, path)?;
+    Ok(())
+}
+
+pub fn write_save<T: Serialize>(path: &Path, value: &T) -> SaveResult<()> {
+    let data = migration::encode(value)?;
+    write_file(path, &data)
+}
+
+fn read_file(path: &Path) -> SaveResult<Vec<u8>> {
+    let mut data: Vec<u8> = Vec::new();
+    let mut file = File::open(path)?;
+    file.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+/// Reads a save file of the given kind, falling back to its backup if it's
+/// damaged.
+pub fn read_save<T: DeserializeOwned>(path: &Path, kind: SaveKind) -> SaveResult<T> {
+    let error = match read_file(path).and_then(|data| migration::decode(kind, &data)) {
+        Ok(value) => return Ok(value),
+        Err(e) => e,
+    };
+
+    // Falling back to an older save won't help.
+    match error {
+        SaveError::TooNew { .. } | SaveError::TooOld => return Err(error),
+        _ => (),
+    }
+
+    let backup_path = get_backup_file(path);
+    if !backup_path.exists() {
+        return Err(error);
+    }
+
+    warn!(SAVE_LOG, "Couldn't load {}, trying the backup. {}", path.display(), error);
+    read_file(&backup_path)
+        .and_then(|data| migration::decode(kind, &data))
+        .map_err(|_| error)
+}
+
+/// Saves the world, unloading all of its terrain. Used when leaving the map.
+pub fn save_world(world: &mut World) -> SaveResult<()> {
     // Unloads and saves the terrain.
     world.save()?;
 
+    write_world(world)
+}
+
+/// Saves the world without unloading anything, so the game can go on. Chunks
+/// that changed are written to the region files.
+pub fn quicksave(world: &mut World) -> SaveResult<()> {
+    world.flush_chunks()?;
+
+    // The chunks were just written to the region files, so like when saving
+    // normally they're left out of the world file and loaded from there.
+    let chunks = world.terrain_mut().take_chunks();
+    let result = write_world(world);
+    world.terrain_mut().restore_chunks(chunks);
+    result?;
+
+    save_manifest(world)
+}
+
+fn write_world(world: &World) -> SaveResult<()> {
     debug!(world.logger,
            "Saving entities and world data, MapId: {}",
            world.flags().map_id);
-    let data = bincode::serialize(&world, Infinite)?;
     let id = world.map_id();
 
     fs::create_dir_all(get_world_save_dir(id)).map_err(SerialError::from)?;
-    let save_path = get_world_savefile(id);
-
-    let mut savefile = File::create(save_path).map_err(SerialError::from)?;
-    savefile.write(data.as_slice()).map_err(SerialError::from)?;
-    Ok(())
+    write_save(&get_world_savefile(id), world)
 }
 
 // TODO: load_world, or load_map? map_id?
-pub fn load_world(id: u32) -> SerialResult<World> {
+pub fn load_world(id: u32) -> SaveResult<World> {
     fs::create_dir_all(get_world_save_dir(id)).map_err(SerialError::from)?;
 
-    let save_path = get_world_savefile(id);
-
-    let mut data: Vec<u8> = Vec::new();
-    let mut savefile = File::open(save_path)?;
-    savefile.read_to_end(&mut data)?;
-    let mut world: World = bincode::deserialize(&data)?;
+    let mut world: World = read_save(&get_world_savefile(id), SaveKind::World)?;
 
     // TODO: shouldn't have to set manually.
     world.set_map_id(id);
@@ -70,30 +246,131 @@ pub fn load_world(id: u32) -> SerialResult<World> {
     Ok(world)
 }
 
-pub fn save_manifest(world: &World) -> SerialResult<()> {
+pub fn save_manifest(world: &World) -> SaveResult<()> {
+    let character_name = world.player()
+        .and_then(|p| world.ecs().names.get(p))
+        .map_or(String::new(), |n| n.name.clone());
+
     let manifest = SaveManifest {
         globals: world.flags.get_globals(),
         map_id: world.map_id(),
+        meta: SlotMeta {
+            character_name: character_name,
+            turns: world.turns(),
+            last_played: Local::now().timestamp(),
+        },
     };
 
-    let data = bincode::serialize(&manifest, Infinite)?;
+    write_save(&get_manifest_file(), &manifest)
+}
+
+pub fn load_manifest() -> SaveResult<SaveManifest> {
+    read_save(&get_manifest_file(), SaveKind::Manifest)
+}
+
+/// Returns true if there is a save to continue from.
+pub fn manifest_exists() -> bool {
+    let path = get_manifest_file();
+    path.exists() || get_backup_file(&path).exists()
+}
+
+/// Returns every save slot with a manifest, most recently played first.
+pub fn list_slots() -> Vec<SlotInfo> {
+    let entries = match fs::read_dir(get_save_root()) {
+        Ok(entries) => entries,
+        Err(..) => return Vec::new(),
+    };
+
+    let mut slots: Vec<SlotInfo> = entries.filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_dir())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|name| {
+            let path = get_manifest_file_in(name);
+            path.exists() || get_backup_file(&path).exists()
+        })
+        .map(|name| {
+            let manifest = read_save(&get_manifest_file_in(&name), SaveKind::Manifest);
+            SlotInfo {
+                name: name,
+                manifest: manifest,
+            }
+        })
+        .collect();
+
+    slots.sort_by_key(|slot| {
+        -slot.manifest.as_ref().map_or(0, |m| m.meta.last_played)
+    });
+    slots
+}
+
+/// Picks a slot name for a new character that isn't already used.
+pub fn new_slot_name(character_name: &str) -> String {
+    let base: String = character_name.chars()
+        .filter(|&c| c.is_alphanumeric() || c == '_' || c == '-')
+        .flat_map(|c| c.to_lowercase())
+        .collect();
+    let base = if base.is_empty() { "save".to_string() } else { base };
+
+    let mut name = base.clone();
+    let mut count = 1;
+    while Path::new(&get_slot_directory(&name)).exists() {
+        count += 1;
+        name = format!("{}{}", base, count);
+    }
+    name
+}
+
+pub fn delete_slot(slot: &str) -> SerialResult<()> {
+    let slot_dir = PathBuf::from(get_slot_directory(slot));
+
+    if Path::exists(slot_dir.as_path()) {
+        fs::remove_dir_all(slot_dir).map_err(SerialError::from)?;
+    }
 
-    let manifest_path = get_manifest_file();
-    println!("{:?}", manifest_path.display());
-    let mut manifest = File::create(manifest_path).map_err(SerialError::from)?;
-    manifest.write(data.as_slice()).map_err(SerialError::from)?;
     Ok(())
 }
 
-pub fn load_manifest() -> SerialResult<SaveManifest> {
-    let manifest_path = get_manifest_file();
+/// Saves from before there were slots kept the manifest and the maps right in
+/// the save directory. Moves such a save into the default slot, or a new slot
+/// if that one is taken, so it still shows up on the title screen.
+pub fn migrate_legacy_save() -> SerialResult<()> {
+    let root = PathBuf::from(get_save_root());
+    let slot = new_slot_name(DEFAULT_SLOT);
 
-    let mut data: Vec<u8> = Vec::new();
-    let mut manifest_file = File::open(manifest_path)?;
-    manifest_file.read_to_end(&mut data)?;
-    let manifest = bincode::deserialize(&data)?;
+    if move_legacy_save(&root, &PathBuf::from(get_slot_directory(&slot)))? {
+        info!(SAVE_LOG, "Moved the save in {} to slot {}", root.display(), slot);
+    }
+
+    Ok(())
+}
+
+/// Moves the manifest and map directories in `from` into `to`. Returns false
+/// if there was no manifest to move.
+fn move_legacy_save(from: &Path, to: &Path) -> SerialResult<bool> {
+    let manifest = from.join("manifest.bin");
+    if !manifest.exists() && !get_backup_file(&manifest).exists() {
+        return Ok(false);
+    }
 
-    Ok(manifest)
+    fs::create_dir_all(to).map_err(SerialError::from)?;
+
+    for entry in fs::read_dir(from).map_err(SerialError::from)? {
+        let entry = entry.map_err(SerialError::from)?;
+        let name = match entry.file_name().into_string() {
+            Ok(name) => name,
+            Err(..) => continue,
+        };
+
+        // Slots named after characters could look like map ids too.
+        let is_map = entry.path().is_dir() &&
+            name.parse::<MapId>().is_ok() &&
+            !entry.path().join("manifest.bin").exists();
+        if is_map || name.starts_with("manifest.bin") {
+            fs::rename(entry.path(), to.join(&name)).map_err(SerialError::from)?;
+        }
+    }
+
+    Ok(true)
 }
 
 pub fn init_paths() -> SerialResult<()> {
@@ -111,6 +388,7 @@ pub fn delete_world_if_exists(id: u32) -> SerialResult<()> {
     Ok(())
 }
 
+/// Deletes everything saved in the current slot.
 pub fn wipe_save() -> SerialResult<()> {
     let savedir_buf = PathBuf::from(get_save_directory());
 
@@ -126,13 +404,37 @@ pub fn wipe_save() -> SerialResult<()> {
 pub struct SaveManifest {
     pub globals: GlobalFlags,
     pub map_id: MapId,
+    pub meta: SlotMeta,
+}
+
+/// What's shown about a save slot when picking one.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct SlotMeta {
+    pub character_name: String,
+    pub turns: u64,
+    /// Seconds since the Unix epoch.
+    pub last_played: i64,
+}
+
+pub struct SlotInfo {
+    pub name: String,
+    pub manifest: SaveResult<SaveManifest>,
 }
 
-impl SaveManifest {
-    pub fn new() -> Self {
-        SaveManifest {
-            globals: GlobalFlags::new(),
-            map_id: 0,
+impl SlotInfo {
+    pub fn describe(&self) -> String {
+        match self.manifest {
+            Ok(ref manifest) => {
+                let meta = &manifest.meta;
+                let last_played = Local.timestamp(meta.last_played, 0).format("%Y-%m-%d %H:%M");
+                format!("{} ({}), turn {}, map {}, {}",
+                        meta.character_name,
+                        self.name,
+                        meta.turns,
+                        manifest.map_id,
+                        last_played)
+            },
+            Err(ref e) => format!("{} - {}", self.name, e),
         }
     }
 }
@@ -159,4 +461,103 @@ mod tests {
         assert_eq!(manifest.globals, globals);
         assert_eq!(manifest.map_id, map_id);
     }
+
+    #[test]
+    fn test_slots() {
+        set_slot("test_slots");
+        init_paths().unwrap();
+
+        let mut context = test_context_bounded(64, 64);
+        context.state.world.pass_turn();
+        save_manifest(&context.state.world).unwrap();
+
+        {
+            let slots = list_slots();
+            let slot = slots.iter().find(|s| s.name == "test_slots").unwrap();
+            let manifest = slot.manifest.as_ref().unwrap();
+            assert_eq!(manifest.meta.turns, 1);
+        }
+
+        assert_eq!(new_slot_name("Test Slots!"), "testslots");
+        assert_eq!(new_slot_name("test_slots"), "test_slots2");
+
+        delete_slot("test_slots").unwrap();
+        assert!(list_slots().iter().all(|s| s.name != "test_slots"));
+    }
+
+    #[test]
+    fn test_quicksave() {
+        init_paths().unwrap();
+
+        let mut context = test_context_bounded(64, 64);
+        let map_id = 102;
+        context.state.world.set_map_id(map_id);
+        let player = context.state.world.player().unwrap();
+        let chunks = context.state.world.terrain().chunk_count();
+
+        quicksave(&mut context.state.world).unwrap();
+
+        // Nothing was unloaded.
+        assert_eq!(context.state.world.terrain().chunk_count(), chunks);
+        assert!(context.state.world.position(player).is_some());
+
+        let mut region_file = get_world_save_dir(map_id);
+        region_file.push("r.0.0.sr");
+        assert!(region_file.exists());
+
+        // The chunks are read back from the region files.
+        let mut world = load_world(map_id).unwrap();
+        assert_eq!(world.position(player), context.state.world.position(player));
+        assert_eq!(world.terrain().chunk_count(), 0);
+
+        world.update_terrain();
+        assert_eq!(world.terrain().chunk_count(), chunks);
+    }
+
+    #[test]
+    fn test_backup() {
+        init_paths().unwrap();
+
+        let mut context = test_context_bounded(64, 64);
+        let map_id = 103;
+        context.state.world.set_map_id(map_id);
+        let player = context.state.world.player().unwrap();
+        let pos = context.state.world.position(player);
+
+        // The first save becomes the backup.
+        write_world(&context.state.world).unwrap();
+        write_world(&context.state.world).unwrap();
+
+        let path = get_world_savefile(map_id);
+        assert!(get_backup_file(&path).exists());
+
+        let data = read_file(&path).unwrap();
+        File::create(&path).unwrap().write_all(&data[..data.len() / 2]).unwrap();
+
+        let world = load_world(map_id).unwrap();
+        assert_eq!(world.position(player), pos);
+    }
+
+    #[test]
+    fn test_move_legacy_save() {
+        let from = PathBuf::from(format!("{}/legacy_save", get_save_root()));
+        let to = from.join("default");
+        let _ = fs::remove_dir_all(&from);
+        fs::create_dir_all(from.join("1")).unwrap();
+        fs::create_dir_all(from.join("morgue")).unwrap();
+        File::create(from.join("manifest.bin")).unwrap();
+        File::create(from.join("1/world.bin")).unwrap();
+
+        assert!(move_legacy_save(&from, &to).unwrap());
+        assert!(to.join("manifest.bin").exists());
+        assert!(to.join("1/world.bin").exists());
+        assert!(!from.join("manifest.bin").exists());
+        assert!(!from.join("1").exists());
+        assert!(from.join("morgue").exists());
+
+        // Nothing left to move.
+        assert!(!move_legacy_save(&from, &to).unwrap());
+
+        fs::remove_dir_all(&from).unwrap();
+    }
 }
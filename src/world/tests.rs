@@ -1,4 +1,7 @@
+use event::{self, Event, EventArea, EventKind};
+use item;
 use logic::Action;
+use logic::status::{self, StatusEffect, StatusKind};
 use state;
 use testing::*;
 use world::*;
@@ -133,3 +136,284 @@ fn test_load_modify_terrain() {
     assert!(cell_mut.is_some(), "World terrain wasn't loaded in before mutate");
 }
 
+
+#[test]
+fn test_doors() {
+    let mut context = test_context_bounded(64, 64);
+    let door_pos = WorldPosition::new(1, 0);
+
+    context.state.world.cell_mut(&door_pos).unwrap().feature =
+        Some(CellFeature::Door(DoorState::Closed));
+
+    {
+        let world = &context.state.world;
+        assert!(!world.can_walk(door_pos, Walkability::MonstersWalkable));
+        assert!(!world.light_passes_through(&door_pos));
+    }
+
+    state::run_action_no_ai(&mut context, Action::OpenDoor(Direction::E));
+
+    {
+        let world = &context.state.world;
+        assert_eq!(world.door_at(&door_pos), Some(DoorState::Open));
+        assert!(world.can_walk(door_pos, Walkability::MonstersWalkable));
+        assert!(world.light_passes_through(&door_pos));
+    }
+
+    state::run_action_no_ai(&mut context, Action::CloseDoor(Direction::E));
+
+    assert_eq!(context.state.world.door_at(&door_pos), Some(DoorState::Closed));
+}
+
+#[test]
+fn test_locked_doors() {
+    let mut context = test_context_bounded(64, 64);
+    let player = context.state.world.player().unwrap();
+    let door_pos = WorldPosition::new(1, 0);
+
+    context.state.world.cell_mut(&door_pos).unwrap().feature =
+        Some(CellFeature::Door(DoorState::Closed));
+
+    // Locking needs a key.
+    state::run_action_no_ai(&mut context, Action::LockDoor(Direction::E));
+    assert_eq!(context.state.world.door_at(&door_pos), Some(DoorState::Closed));
+
+    let key = context.state.world.create(ecs::prefab::key("key", "cola"), POINT_ZERO);
+    context.state.world.place_entity_in(player, key);
+
+    state::run_action_no_ai(&mut context, Action::LockDoor(Direction::E));
+    state::run_action_no_ai(&mut context, Action::OpenDoor(Direction::E));
+    assert_eq!(context.state.world.door_at(&door_pos), Some(DoorState::Locked));
+    assert!(!context.state.world.can_walk(door_pos, Walkability::MonstersWalkable));
+
+    state::run_action_no_ai(&mut context, Action::UnlockDoor(Direction::E));
+    state::run_action_no_ai(&mut context, Action::OpenDoor(Direction::E));
+    assert_eq!(context.state.world.door_at(&door_pos), Some(DoorState::Open));
+}
+
+#[test]
+fn test_dig() {
+    let mut context = test_context_bounded(64, 64);
+    let wall_pos = WorldPosition::new(1, 0);
+    let door_pos = WorldPosition::new(0, 1);
+
+    context.state.world.cell_mut(&wall_pos).unwrap().set("wall");
+    {
+        let cell = context.state.world.cell_mut(&door_pos).unwrap();
+        cell.set("wall");
+        cell.feature = Some(CellFeature::Door(DoorState::Closed));
+    }
+
+    state::run_action_no_ai(&mut context, Action::Dig(wall_pos));
+    assert_eq!(context.state.world.cell_const(&wall_pos).unwrap().name(), "floor");
+
+    // Only bare walls can be dug through.
+    state::run_action_no_ai(&mut context, Action::Dig(door_pos));
+    assert_eq!(context.state.world.cell_const(&door_pos).unwrap().name(), "wall");
+    assert_eq!(context.state.world.door_at(&door_pos), Some(DoorState::Closed));
+}
+
+#[test]
+fn test_use_item() {
+    let mut context = test_context_bounded(64, 64);
+    let player = context.state.world.player().unwrap();
+    let potion = context.state.world.create(ecs::prefab::item_from_data("potion"), POINT_ZERO);
+    context.state.world.place_entity_in(player, potion);
+    context.state.world.ecs_mut().healths.map_mut(|h| h.hurt(100), player);
+
+    state::run_action_no_ai(&mut context, Action::Use(potion));
+
+    let world = &context.state.world;
+    let health = world.ecs().healths.get_or_err(player);
+    assert_eq!(health.hit_points, health.max_hit_points);
+    assert!(!world.ecs().contains(potion));
+}
+
+#[test]
+fn test_use_item_at() {
+    let mut context = test_context_bounded(64, 64);
+    let player = context.state.world.player().unwrap();
+    let mob = place_mob(&mut context.state.world, WorldPosition::new(4, 4));
+    let bomb = context.state.world.create(ecs::prefab::item_from_data("bomb"), POINT_ZERO);
+    context.state.world.place_entity_in(player, bomb);
+
+    state::run_action_no_ai(&mut context, Action::UseAt(bomb, WorldPosition::new(5, 5)));
+
+    let world = &context.state.world;
+    assert!(!world.is_alive(mob));
+    assert!(!world.ecs().contains(bomb));
+}
+
+#[test]
+fn test_use_item_at_out_of_sight() {
+    let mut context = test_context_bounded(64, 64);
+    let player = context.state.world.player().unwrap();
+    let mob = place_mob(&mut context.state.world, WorldPosition::new(4, 4));
+    let bomb = context.state.world.create(ecs::prefab::item_from_data("bomb"), POINT_ZERO);
+    context.state.world.place_entity_in(player, bomb);
+
+    // Too far away.
+    state::run_action_no_ai(&mut context, Action::UseAt(bomb, WorldPosition::new(30, 30)));
+    assert!(context.state.world.ecs().contains(bomb));
+
+    // Behind a wall.
+    context.state.world.cell_mut(&WorldPosition::new(2, 2)).unwrap().set("wall");
+    state::run_action_no_ai(&mut context, Action::UseAt(bomb, WorldPosition::new(4, 4)));
+
+    let world = &context.state.world;
+    assert!(world.is_alive(mob));
+    assert!(world.ecs().contains(bomb));
+}
+
+#[test]
+fn test_kills_credited() {
+    let mut context = test_context_bounded(64, 64);
+    let player = context.state.world.player().unwrap();
+    let blown_up = place_mob(&mut context.state.world, WorldPosition::new(5, 5));
+    let poisoned = place_mob(&mut context.state.world, WorldPosition::new(10, 10));
+    let bystander = place_mob(&mut context.state.world, WorldPosition::new(20, 20));
+
+    // Killed by a thrown bomb.
+    let bomb = context.state.world.create(ecs::prefab::explosive_item("bomb", "berry"),
+                                          POINT_ZERO);
+    context.state.world.place_entity_in(player, bomb);
+    state::run_action_no_ai(&mut context, Action::Throw(bomb, WorldPosition::new(4, 4)));
+
+    let world = &mut context.state.world;
+    assert!(!world.is_alive(blown_up));
+
+    // Hurt by the player, then finished off by poison.
+    world.ecs_mut().healths.map_mut(|h| h.hurt_by(1, Some(player)), poisoned);
+    status::add_effect(world, poisoned, StatusEffect::new(StatusKind::Poison(1000), 300));
+    status::update(world, status::DEFAULT_INTERVAL);
+    assert!(!world.is_alive(poisoned));
+
+    // Nobody set this one off.
+    world.push_event(Event::new(EventKind::Explosion(1000, None),
+                                EventArea::Square(WorldPosition::new(20, 20), 1)));
+    event::process_events(world);
+    assert!(!world.is_alive(bystander));
+
+    world.update_killed();
+    assert_eq!(world.flags().globals.kills.get("mob"), Some(&2));
+}
+
+#[test]
+fn test_pickup_merges_stacks() {
+    let mut context = test_context_bounded(64, 64);
+    let player = context.state.world.player().unwrap();
+    let a = context.state.world.create(ecs::prefab::item("cola", "cola"), POINT_ZERO);
+    let b = context.state.world.create(ecs::prefab::item("cola", "cola"), POINT_ZERO);
+
+    state::run_action_no_ai(&mut context, Action::Pickup(a));
+    state::run_action_no_ai(&mut context, Action::Pickup(b));
+
+    let world = &context.state.world;
+    assert_eq!(world.entities_in(player), vec![a]);
+    assert_eq!(world.ecs().items.get_or_err(a).count, 2);
+    assert!(!world.ecs().contains(b));
+}
+
+#[test]
+fn test_pickup_keeps_corpses_apart() {
+    let mut context = test_context_bounded(64, 64);
+    let player = context.state.world.player().unwrap();
+    let a = context.state.world.create(ecs::prefab::corpse("you"), POINT_ZERO);
+    let b = context.state.world.create(ecs::prefab::corpse("you"), POINT_ZERO);
+    let cola = context.state.world.create(ecs::prefab::item("cola", "cola"), POINT_ZERO);
+    context.state.world.place_entity_in(b, cola);
+
+    state::run_action_no_ai(&mut context, Action::Pickup(a));
+    state::run_action_no_ai(&mut context, Action::Pickup(b));
+
+    let world = &context.state.world;
+    assert_eq!(world.entities_in(player), vec![a, b]);
+    assert_eq!(world.entities_in(b), vec![cola]);
+}
+
+#[test]
+fn test_pickup_capacity() {
+    let mut context = test_context_bounded(64, 64);
+    let player = context.state.world.player().unwrap();
+    context.state.world.ecs_mut().invs.insert(player, components::Inventory::with_limits(1, 100.0));
+
+    let cola = context.state.world.create(ecs::prefab::item("cola", "cola"), POINT_ZERO);
+    let potion = context.state.world.create(ecs::prefab::item_from_data("potion"), POINT_ZERO);
+
+    state::run_action_no_ai(&mut context, Action::Pickup(cola));
+    state::run_action_no_ai(&mut context, Action::Pickup(potion));
+
+    let world = &context.state.world;
+    assert_eq!(world.entities_in(player), vec![cola]);
+    assert_eq!(world.position(potion), Some(POINT_ZERO));
+}
+
+#[test]
+fn test_pickup_weight_limit() {
+    let mut context = test_context_bounded(64, 64);
+    let player = context.state.world.player().unwrap();
+    context.state.world.ecs_mut().invs.insert(player, components::Inventory::with_limits(10, 1.2));
+
+    let a = context.state.world.create(ecs::prefab::item_from_data("bomb"), POINT_ZERO);
+    let b = context.state.world.create(ecs::prefab::item_from_data("bomb"), POINT_ZERO);
+
+    state::run_action_no_ai(&mut context, Action::Pickup(a));
+    state::run_action_no_ai(&mut context, Action::Pickup(b));
+
+    let world = &context.state.world;
+    assert_eq!(world.ecs().items.get_or_err(a).count, 1);
+    assert!(world.ecs().contains(b));
+    assert_eq!(world.ecs().invs.get_or_err(player).container.burden(), item::Burden::Stressed);
+}
+
+#[test]
+fn test_drop_splits_stack() {
+    let mut context = test_context_bounded(64, 64);
+    let player = context.state.world.player().unwrap();
+    let cola = context.state.world.create(ecs::prefab::item("cola", "cola"), POINT_ZERO);
+    context.state.world.ecs_mut().items.map_mut(|i| i.count = 5, cola);
+    context.state.world.place_entity_in(player, cola);
+
+    state::run_action_no_ai(&mut context, Action::Drop(cola, 2));
+
+    let world = &context.state.world;
+    assert_eq!(world.ecs().items.get_or_err(cola).count, 3);
+    let dropped = world.find_entity(POINT_ZERO, |&e| world.ecs().items.has(e)).unwrap();
+    assert!(dropped != cola);
+    assert_eq!(world.ecs().items.get_or_err(dropped).count, 2);
+}
+
+#[test]
+fn test_deterministic_rng() {
+    use rand::Rng;
+    use world::flags::RngStream;
+
+    let a = World::new().with_seed(1234).build().unwrap();
+    let b = World::new().with_seed(1234).build().unwrap();
+    let c = World::new().with_seed(1234).build().unwrap();
+
+    let rolls_a: Vec<u32> = (0..8).map(|_| a.rng(RngStream::Combat).next_u32()).collect();
+    let rolls_c: Vec<u32> = (0..8).map(|_| c.rng(RngStream::Combat).next_u32()).collect();
+    assert_eq!(rolls_a, rolls_c);
+
+    // Rolling on one stream doesn't change the others. Only `a` has rolled for
+    // combat.
+    let ai_a: Vec<u32> = (0..8).map(|_| a.rng(RngStream::Ai).next_u32()).collect();
+    let ai_b: Vec<u32> = (0..8).map(|_| b.rng(RngStream::Ai).next_u32()).collect();
+    assert_eq!(ai_a, ai_b);
+    assert!(rolls_a != ai_a);
+}
+
+#[test]
+fn test_deterministic_wander() {
+    let run = || {
+        let mut context = test_context_bounded(64, 64);
+        let mob = place_mob(&mut context.state.world, WorldPosition::new(10, 10));
+        for _ in 0..20 {
+            state::run_action(&mut context, Action::Wait);
+        }
+        context.state.world.position(mob)
+    };
+
+    assert_eq!(run(), run());
+}
@@ -72,11 +72,15 @@ pub trait Mutate: Query + Sized {
     /// The entities have to fulfill a specific set of criteria to be counted as "dead", since
     /// items and other entities without health do not count. For now, it is if the entity is a
     /// "mob".
+    ///
+    /// The player is kept around, so they can come back after dying.
     fn purge_dead(&mut self) {
+        let player = self.flags().globals.player;
         let kill_list: Vec<Entity> =
             self.entities().filter(|&&e|
                                     self.is_mob(e) &&
-                                   !self.is_alive(e)).cloned().collect();
+                                   !self.is_alive(e) &&
+                                   Some(e) != player).cloned().collect();
 
         for e in kill_list.into_iter() {
             self.remove_entity(e);
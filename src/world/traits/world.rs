@@ -3,7 +3,7 @@ use infinigen::*;
 use chunk::ChunkIndex;
 use data::Walkability;
 use graphics::cell::Cell;
-use graphics::cell::{CellFeature, StairDest, StairDir};
+use graphics::cell::{CellFeature, DoorState, StairDest, StairDir};
 use prefab::PrefabMarker;
 use terrain::traits::*;
 use world::World;
@@ -24,6 +24,9 @@ pub trait WorldQuery {
     /// FOV/line of sight.
     fn light_passes_through(&self, pos: &Point) -> bool;
 
+    /// Returns the state of the door at the given position, if there is one.
+    fn door_at(&self, pos: &Point) -> Option<DoorState>;
+
     fn with_cells<F>(&self, top_left: Point,
                      dimensions: Point,
                      callback: F)
@@ -49,6 +52,10 @@ impl WorldQuery for World {
         self.cell_const(&pos).map_or(false, |c| c.can_see_through())
     }
 
+    fn door_at(&self, pos: &Point) -> Option<DoorState> {
+        self.cell_const(&pos).and_then(|c| c.door_state())
+    }
+
     fn with_cells<F>(&self, top_left: Point,
                      dimensions: Point,
                      mut callback: F) where F: FnMut(Point, &Cell) {
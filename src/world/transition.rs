@@ -2,7 +2,9 @@ use calx_ecs::Entity;
 use infinigen::*;
 
 use ecs::Loadout;
+use logic::death;
 use point::Point;
+use world::gc;
 use world::serial;
 use world::{World, MapId};
 use world::traits::*;
@@ -44,6 +46,7 @@ impl TransitionLoadout {
 
 struct TransitionData {
     pub globals: GlobalFlags,
+    pub turns: u64,
 
     pub player_data: TransitionLoadout
 }
@@ -63,6 +66,7 @@ impl Transition<TransitionData> for World {
         let loadout = TransitionLoadout::from_entity(player, self);
         let data = TransitionData {
             globals: self.flags().get_globals(),
+            turns: self.turns(),
 
             player_data: loadout,
         };
@@ -73,11 +77,14 @@ impl Transition<TransitionData> for World {
     fn inject_transition_data(&mut self, previous: TransitionData) -> TransitionResult<()> {
         let map_id = self.flags.map_id;
 
-        self.flags_mut().globals.player = previous.globals.player;
-        let max_map_id = self.flags_mut().globals.max_map_id;
-        if previous.globals.max_map_id > max_map_id {
-            self.flags_mut().globals.max_map_id = previous.globals.max_map_id;
-        }
+        let max_map_id = self.flags().globals.max_map_id.max(previous.globals.max_map_id);
+        let maps = self.flags().globals.maps.clone();
+        self.flags_mut().globals = previous.globals;
+        self.flags_mut().globals.max_map_id = max_map_id;
+        self.flags_mut().globals.maps.adopt(&maps, map_id);
+        self.flags_mut().globals.visit(map_id);
+
+        self.turns = previous.turns;
 
         // TODO: shouldn't have to set manually.
         self.set_map_id(map_id);
@@ -91,9 +98,21 @@ impl Transition<TransitionData> for World {
 
 impl World {
     pub fn move_to_map(&mut self, other: World, dest: Point) -> TransitionResult<()> {
+        let prev_id = self.map_id();
+        let mut temporary = self.flags().globals.maps.is_temporary(prev_id);
+        if temporary && death::has_corpse(self) {
+            // Deleting the map would lose everything the player was carrying.
+            self.flags_mut().globals.maps.set_permanent(prev_id);
+            temporary = false;
+        }
+
         let data = self.get_transition_data()?;
 
-        serial::save_world(self).unwrap();
+        if !temporary {
+            serial::save_world(self).map_err(|e| {
+                warn!(self.logger, "Couldn't save map {}: {}", prev_id, e);
+            })?;
+        }
 
         *self = other;
 
@@ -104,6 +123,12 @@ impl World {
 
         self.on_load();
 
+        if temporary {
+            gc::delete_map(self, prev_id).map_err(|e| {
+                warn!(self.logger, "Couldn't delete temporary map {}: {}", prev_id, e);
+            })?;
+        }
+
         Ok(())
     }
 }
@@ -183,6 +208,46 @@ mod tests {
         assert_eq!(context.state.world.flags().globals.max_map_id, 1);
     }
 
+    #[test]
+    fn test_maps_visited() {
+        let mut context = test_context_bounded(64, 64);
+        let prev_id = context.state.world.flags().map_id;
+        let new_world = World::new()
+            .from_other_world(&context.state.world)
+            .build()
+            .unwrap();
+        let new_id = new_world.flags().map_id;
+
+        context.state.world.move_to_map(new_world, POINT_ZERO).unwrap();
+        let prev_world = world::serial::load_world(prev_id).unwrap();
+        context.state.world.move_to_map(prev_world, POINT_ZERO).unwrap();
+        let new_world = world::serial::load_world(new_id).unwrap();
+        context.state.world.move_to_map(new_world, POINT_ZERO).unwrap();
+
+        assert_eq!(context.state.world.flags().globals.maps_visited, vec![new_id, prev_id]);
+    }
+
+    #[test]
+    fn test_map_registry() {
+        let mut context = test_context_bounded(64, 64);
+        let prev_id = context.state.world.flags().map_id;
+        let new_world = World::new()
+            .from_other_world(&context.state.world)
+            .with_name("Dungeon")
+            .build()
+            .unwrap();
+        let new_id = new_world.flags().map_id;
+
+        context.state.world.move_to_map(new_world, POINT_ZERO).unwrap();
+
+        let maps = &context.state.world.flags().globals.maps;
+        assert_eq!(maps.ids(), vec![prev_id, new_id]);
+        let info = maps.get(new_id).unwrap();
+        assert_eq!(info.name, "Dungeon");
+        assert_eq!(info.parent, Some(prev_id));
+        assert_eq!(info.depth, 1);
+    }
+
     #[test]
     fn test_transition_loadout() {
         let mut context = test_context_bounded(64, 64);